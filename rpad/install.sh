@@ -14,7 +14,7 @@ sudo mkdir -p /usr/local/share/icons/hicolor/scalable/apps/
 sudo cp assets/rpad_icon.svg /usr/local/share/icons/hicolor/scalable/apps/rpad_icon.svg
 
 # Install desktop file
-sudo cp rpad.desktop /usr/local/share/applications/
+sudo cp dev.rpad.app.desktop /usr/local/share/applications/
 
 # Update icon cache
 sudo gtk-update-icon-cache /usr/local/share/icons/hicolor/ || true
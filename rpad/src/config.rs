@@ -0,0 +1,261 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use gtk4::gio::prelude::*;
+use gtk4::{gio, glib};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+// Most recently used files remembered in the session data
+const MAX_SESSION_FILES: usize = 100;
+
+// Find and Replace terms remembered in the session data
+const MAX_SEARCH_HISTORY: usize = 20;
+
+thread_local! {
+    // The config as rpad last loaded or wrote it, so that `watch` can tell
+    // rpad's own writes from edits made by hand
+    static KNOWN: RefCell<Option<Config>> = const { RefCell::new(None) };
+}
+
+// User preferences, stored in $XDG_CONFIG_HOME/rpad/config.toml.
+// Every field is optional so a hand-written file only needs what it changes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub zoom: Option<u32>,
+    // Mode of new documents when --mode isn't given: "plain" or "markup"
+    pub mode: Option<String>,
+    pub spell_check: Option<bool>,
+    // Dictionary code such as "en_US"; the system default when unset
+    pub spell_language: Option<String>,
+    // Insert closing brackets and quotes automatically; on when unset
+    pub auto_pair: Option<bool>,
+    // Curly quotes, em dashes and ellipses while typing prose; off when unset
+    pub smart_quotes: Option<bool>,
+    // GtkSourceView style scheme id such as "oblivion"; follows the
+    // desktop's light/dark preference when unset
+    pub style_scheme: Option<String>,
+    // Document overview beside the editor; off when unset
+    pub minimap: Option<bool>,
+    // Whitespace drawn as glyphs: "all", "leading" or "trailing"; none when unset
+    pub show_whitespace: Option<String>,
+    // Line numbers in the gutter: "absolute", or "relative" to the cursor
+    // line; none when unset
+    pub line_numbers: Option<String>,
+    pub right_margin: Option<bool>,
+    // Column of the right margin guide; 80 when unset
+    pub right_margin_column: Option<u32>,
+    // Vertical lines at each indent level while a language is set
+    pub indent_guides: Option<bool>,
+    // Whether the text cursor blinks; the desktop's setting when unset
+    pub cursor_blink: Option<bool>,
+    // "— Ln X, Col Y" after the window title; off when unset
+    pub cursor_in_title: Option<bool>,
+    // Find and Replace options, as last set in those dialogs; off when unset
+    pub match_case: Option<bool>,
+    pub preserve_case: Option<bool>,
+    // Undo steps kept per document, oldest dropped first; 500 when unset
+    pub undo_limit: Option<usize>,
+    // Words listed by Tools → Tasks; TODO, FIXME, XXX and HACK when unset
+    pub task_markers: Option<Vec<String>>,
+    // Action name → accelerator(s), replacing that action's default keys
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub keybindings: BTreeMap<String, Accels>,
+}
+
+// One accelerator such as "<Primary>f", or a list of them; an empty list
+// leaves the action without a shortcut
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Accels {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Accels {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            Accels::One(accel) => std::slice::from_ref(accel),
+            Accels::Many(accels) => accels,
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        glib::user_config_dir().join("rpad").join("config.toml")
+    }
+
+    pub fn load() -> Self {
+        load_toml(&Self::path())
+    }
+
+    // Re-read the file, apply `f` and write it back, so changes made by hand
+    // (or by another window) since startup aren't clobbered. A file that
+    // can't be read is left alone rather than replaced with defaults.
+    pub fn update(f: impl FnOnce(&mut Config)) {
+        let mut config = match try_load_toml(&Self::path()) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("Not saving settings: {err}");
+                return;
+            }
+        };
+        f(&mut config);
+        if let Err(err) = save_toml(&Self::path(), &config) {
+            eprintln!("Error saving config: {err}");
+        }
+        KNOWN.with(|known| *known.borrow_mut() = Some(config));
+    }
+
+    // Call `on_change(old, new)` whenever the file is changed from outside
+    // rpad. A file that doesn't parse (say, half-way through being written)
+    // is reported and otherwise ignored, as is one that gets deleted.
+    pub fn watch(on_change: impl Fn(&Config, &Config) + 'static) -> Option<gio::FileMonitor> {
+        let path = Self::path();
+        KNOWN.with(|known| *known.borrow_mut() = Some(Self::load()));
+
+        let monitor = gio::File::for_path(&path)
+            .monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE)
+            .map_err(|err| eprintln!("Not watching {}: {err}", path.display()))
+            .ok()?;
+        monitor.connect_changed(move |_, _, _, event| {
+            if !matches!(
+                event,
+                gio::FileMonitorEvent::ChangesDoneHint | gio::FileMonitorEvent::Created
+            ) {
+                return;
+            }
+            let Ok(contents) = fs::read_to_string(&path) else {
+                return;
+            };
+            let config: Config = match toml::from_str(&contents) {
+                Ok(config) => config,
+                Err(err) => {
+                    eprintln!("Ignoring invalid {}: {err}", path.display());
+                    return;
+                }
+            };
+
+            let old = KNOWN.with(|known| known.replace(Some(config.clone())));
+            let old = old.unwrap_or_default();
+            if old != config {
+                on_change(&old, &config);
+            }
+        });
+        Some(monitor)
+    }
+}
+
+// Per-file state remembered between sessions, stored in
+// $XDG_DATA_HOME/rpad/session.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    // Most recently used first
+    #[serde(rename = "file")]
+    pub files: Vec<FileSession>,
+    // Terms typed into Find and Replace, newest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub find_history: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub replace_history: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileSession {
+    pub path: PathBuf,
+    pub zoom: Option<u32>,
+    // Last saved through Sudo Mode
+    #[serde(skip_serializing_if = "is_false")]
+    pub sudo: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Session {
+    pub fn path() -> PathBuf {
+        glib::user_data_dir().join("rpad").join("session.toml")
+    }
+
+    pub fn load() -> Self {
+        load_toml(&Self::path())
+    }
+
+    pub fn file(&self, path: &Path) -> Option<&FileSession> {
+        self.files.iter().find(|f| f.path == path)
+    }
+
+    // Same load/modify/save cycle as Config::update
+    pub fn update(f: impl FnOnce(&mut Session)) {
+        let mut session = Self::load();
+        f(&mut session);
+        if let Err(err) = save_toml(&Self::path(), &session) {
+            eprintln!("Error saving session: {err}");
+        }
+    }
+
+    // Same again, for one file's entry.
+    // The entry moves to the front; the oldest entries fall off the end.
+    pub fn update_file(path: &Path, f: impl FnOnce(&mut FileSession)) {
+        Self::update(|session| {
+            let mut entry = match session.files.iter().position(|e| e.path == path) {
+                Some(index) => session.files.remove(index),
+                None => FileSession {
+                    path: path.to_path_buf(),
+                    ..Default::default()
+                },
+            };
+            f(&mut entry);
+            session.files.insert(0, entry);
+            session.files.truncate(MAX_SESSION_FILES);
+        });
+    }
+}
+
+// Move `term` to the front of a search history, dropping an older copy
+// and anything past the cap. Empty terms aren't worth remembering.
+pub fn remember_search(history: &mut Vec<String>, term: &str) {
+    if term.is_empty() {
+        return;
+    }
+    history.retain(|entry| entry != term);
+    history.insert(0, term.to_string());
+    history.truncate(MAX_SEARCH_HISTORY);
+}
+
+// A missing file is normal (first run); a broken one is reported and ignored
+fn load_toml<T: DeserializeOwned + Default>(path: &Path) -> T {
+    try_load_toml(path).unwrap_or_else(|err| {
+        eprintln!("Ignoring {err}");
+        T::default()
+    })
+}
+
+// The file's contents, or the defaults if it doesn't exist yet. One that
+// exists but can't be read or parsed is an error, for callers about to
+// write it back.
+fn try_load_toml<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            toml::from_str(&contents).map_err(|err| format!("invalid {}: {err}", path.display()))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(err) => Err(format!("unreadable {}: {err}", path.display())),
+    }
+}
+
+fn save_toml<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let contents = toml::to_string_pretty(value).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
@@ -0,0 +1,577 @@
+// A tab's document without any GTK: its file, mode, text history and Sudo
+// Mode session. DocumentState in main.rs holds one next to the widgets and
+// view settings that show it.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// How long a sudo password is reused before saving asks for it again
+pub const SUDO_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Undo steps kept unless the config says otherwise. Each one is a copy of
+// the whole text, so a large file edited for long adds up.
+pub const DEFAULT_UNDO_LIMIT: usize = 500;
+
+// Characters typed (or deleted) one after another in the same place join
+// one undo step, until the typing pauses this long
+pub const UNDO_GROUP_PAUSE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Plain,
+    Markup,
+}
+
+#[derive(Debug)]
+pub struct Document {
+    path: Option<PathBuf>,
+    mode: Mode,
+    // The text as of the last edit, load or save
+    text: String,
+    // Earlier texts, most recent last, and texts undone since the last edit.
+    // Together they hold at most undo_limit texts; the oldest go first.
+    undo_stack: VecDeque<String>,
+    redo_stack: Vec<String>,
+    undo_limit: usize,
+    // The last edit and when it happened, while later edits can still join
+    // its undo step
+    last_step: Option<(Step, Instant)>,
+    dirty: bool,
+    // Password for saving through sudo, and when it has to be asked for again
+    sudo: Option<(String, Instant)>,
+}
+
+impl Document {
+    pub fn new(path: Option<PathBuf>, mode: Mode) -> Self {
+        Self {
+            path,
+            mode,
+            text: String::new(),
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            undo_limit: DEFAULT_UNDO_LIMIT,
+            last_step: None,
+            dirty: false,
+            sudo: None,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn set_path(&mut self, path: Option<PathBuf>) {
+        self.path = path;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // Take `text` as the document's without it being an edit, for text
+    // that arrives on its own such as a followed file's new lines
+    pub fn set_text(&mut self, text: String) {
+        self.text = text;
+        self.last_step = None;
+    }
+
+    pub fn append_text(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.last_step = None;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn set_dirty(&mut self, dirty: bool) {
+        self.dirty = dirty;
+    }
+
+    // The text after an edit made at `now`. The text before it becomes the
+    // next undo step, unless the edit carries on typing or deleting where
+    // the last one left off, without a pause; then both undo together.
+    // Returns false, recording nothing, if the text didn't change.
+    pub fn record_edit(&mut self, text: String, now: Instant) -> bool {
+        if text == self.text {
+            return false;
+        }
+
+        let step = Step::between(&self.text, &text);
+        let joins = match (self.last_step, step) {
+            (Some((last, at)), Some(step)) => {
+                now.saturating_duration_since(at) < UNDO_GROUP_PAUSE && last.continues_with(step)
+            }
+            _ => false,
+        };
+        if joins {
+            self.text = text;
+        } else {
+            self.undo_stack
+                .push_back(std::mem::replace(&mut self.text, text));
+            if self.undo_stack.len() > self.undo_limit {
+                self.undo_stack.pop_front();
+            }
+        }
+        self.redo_stack.clear();
+        self.last_step = step.map(|step| (step, now));
+        self.dirty = true;
+        true
+    }
+
+    // Keep at most `limit` undo steps, dropping the oldest now if there are
+    // more. Undone steps waiting to be redone count against it too.
+    pub fn set_undo_limit(&mut self, limit: usize) {
+        self.undo_limit = limit;
+        let excess = (self.undo_stack.len() + self.redo_stack.len()).saturating_sub(limit);
+        let from_undo = excess.min(self.undo_stack.len());
+        self.undo_stack.drain(..from_undo);
+        self.redo_stack.drain(..excess - from_undo);
+    }
+
+    pub fn undo_limit(&self) -> usize {
+        self.undo_limit
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    // Step back to the text before the last edit, returning it
+    pub fn undo(&mut self) -> Option<String> {
+        let previous = self.undo_stack.pop_back()?;
+        self.last_step = None;
+        self.redo_stack
+            .push(std::mem::replace(&mut self.text, previous.clone()));
+        self.dirty = true;
+        Some(previous)
+    }
+
+    // Step forward again to the text the last undo left, returning it
+    pub fn redo(&mut self) -> Option<String> {
+        let next = self.redo_stack.pop()?;
+        self.last_step = None;
+        self.undo_stack
+            .push_back(std::mem::replace(&mut self.text, next.clone()));
+        self.dirty = true;
+        Some(next)
+    }
+
+    // Start over with `text`, as when a file is opened (or a new document
+    // started, with no path): no history, nothing unsaved, no Sudo Mode
+    pub fn load(&mut self, path: Option<PathBuf>, text: String) {
+        self.path = path;
+        self.text = text;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.last_step = None;
+        self.dirty = false;
+        self.sudo = None;
+    }
+
+    // `text` was written to `path`. The history stays, so edits made
+    // before the save can still be undone, but typing after it starts a
+    // new step so that undo comes back to the saved text.
+    pub fn mark_saved(&mut self, path: PathBuf, text: String) {
+        self.path = Some(path);
+        self.text = text;
+        self.last_step = None;
+        self.dirty = false;
+    }
+
+    // Save through sudo with `password` until SUDO_TIMEOUT after `now`
+    pub fn start_sudo(&mut self, password: String, now: Instant) {
+        self.sudo = Some((password, now + SUDO_TIMEOUT));
+    }
+
+    pub fn end_sudo(&mut self) {
+        self.sudo = None;
+    }
+
+    // In Sudo Mode, whether or not the password is still fresh
+    pub fn sudo_active(&self) -> bool {
+        self.sudo.is_some()
+    }
+
+    // The sudo password, unless it is due to be asked for again
+    pub fn sudo_password(&self, now: Instant) -> Option<&str> {
+        match &self.sudo {
+            Some((password, expiry)) if now <= *expiry => Some(password),
+            _ => None,
+        }
+    }
+
+    // Time left before the password is asked for again; None once it is due
+    // or outside Sudo Mode
+    pub fn sudo_remaining(&self, now: Instant) -> Option<Duration> {
+        let (_, expiry) = self.sudo.as_ref()?;
+        Some(expiry.saturating_duration_since(now)).filter(|remaining| !remaining.is_zero())
+    }
+}
+
+// A one-character edit, the kind that joins its neighbours' undo step.
+// Offsets are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    // A character other than a line break typed at `at`
+    Typing { at: usize, len: usize },
+    // A character deleted at `at`, by Delete or Backspace
+    Deleting { at: usize, len: usize },
+}
+
+impl Step {
+    // What changed from `old` to `new`, if it was a single typed or deleted
+    // character. Anything bigger, such as a paste, gets a step of its own.
+    fn between(old: &str, new: &str) -> Option<Step> {
+        let prefix: usize = old
+            .chars()
+            .zip(new.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(c, _)| c.len_utf8())
+            .sum();
+        let suffix: usize = old[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(c, _)| c.len_utf8())
+            .sum();
+        let removed = &old[prefix..old.len() - suffix];
+        let inserted = &new[prefix..new.len() - suffix];
+
+        let single = |text: &str| text.chars().count() == 1;
+        if removed.is_empty() && single(inserted) && !inserted.starts_with(['\n', '\r']) {
+            Some(Step::Typing {
+                at: prefix,
+                len: inserted.len(),
+            })
+        } else if inserted.is_empty() && single(removed) {
+            Some(Step::Deleting {
+                at: prefix,
+                len: removed.len(),
+            })
+        } else {
+            None
+        }
+    }
+
+    // Whether `next` picks up where this step left off
+    fn continues_with(self, next: Step) -> bool {
+        match (self, next) {
+            (Step::Typing { at, len }, Step::Typing { at: next_at, .. }) => next_at == at + len,
+            // Delete stays put; Backspace moves back over the next character
+            (Step::Deleting { at, .. }, Step::Deleting { at: next_at, len }) => {
+                next_at == at || next_at + len == at
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loaded(text: &str) -> Document {
+        let mut doc = Document::new(None, Mode::Plain);
+        doc.load(Some(PathBuf::from("notes.txt")), text.to_string());
+        doc
+    }
+
+    // Records an edit long enough after the last one that it never joins
+    // its undo step
+    fn edit(doc: &mut Document, text: &str) -> bool {
+        let now = doc
+            .last_step
+            .map_or_else(Instant::now, |(_, at)| at + UNDO_GROUP_PAUSE * 2);
+        doc.record_edit(text.to_string(), now)
+    }
+
+    #[test]
+    fn an_edit_makes_the_document_dirty_and_undoable() {
+        let mut doc = loaded("a");
+        assert!(!doc.is_dirty());
+        assert!(!doc.can_undo());
+
+        assert!(edit(&mut doc, "ab"));
+        assert!(doc.is_dirty());
+        assert!(doc.can_undo());
+        assert!(!doc.can_redo());
+        assert_eq!(doc.text(), "ab");
+    }
+
+    #[test]
+    fn unchanged_text_is_not_an_edit() {
+        let mut doc = loaded("same");
+        assert!(!edit(&mut doc, "same"));
+        assert!(!doc.is_dirty());
+        assert!(!doc.can_undo());
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_history() {
+        let mut doc = loaded("a");
+        edit(&mut doc, "ab");
+        edit(&mut doc, "abc");
+
+        assert_eq!(doc.undo().as_deref(), Some("ab"));
+        assert_eq!(doc.undo().as_deref(), Some("a"));
+        assert_eq!(doc.undo(), None);
+        assert_eq!(doc.text(), "a");
+
+        assert_eq!(doc.redo().as_deref(), Some("ab"));
+        assert_eq!(doc.redo().as_deref(), Some("abc"));
+        assert_eq!(doc.redo(), None);
+        assert_eq!(doc.text(), "abc");
+    }
+
+    #[test]
+    fn an_edit_after_undo_drops_the_redo_steps() {
+        let mut doc = loaded("a");
+        edit(&mut doc, "ab");
+        doc.undo();
+        assert!(doc.can_redo());
+
+        edit(&mut doc, "ax");
+        assert!(!doc.can_redo());
+        assert_eq!(doc.undo().as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn the_oldest_undo_steps_are_dropped_past_the_limit() {
+        let mut doc = loaded("");
+        doc.set_undo_limit(2);
+        for text in ["a", "ab", "abc"] {
+            edit(&mut doc, text);
+        }
+
+        assert_eq!(doc.undo().as_deref(), Some("ab"));
+        assert_eq!(doc.undo().as_deref(), Some("a"));
+        assert_eq!(doc.undo(), None);
+        // Redo still reaches the newest text
+        assert_eq!(doc.redo().as_deref(), Some("ab"));
+        assert_eq!(doc.redo().as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn lowering_the_limit_trims_existing_history() {
+        let mut doc = loaded("");
+        for text in ["a", "ab", "abc", "abcd"] {
+            edit(&mut doc, text);
+        }
+        doc.undo();
+
+        doc.set_undo_limit(2);
+        assert_eq!(doc.undo().as_deref(), Some("ab"));
+        assert_eq!(doc.undo(), None);
+
+        doc.set_undo_limit(0);
+        assert!(!doc.can_undo());
+        assert!(!doc.can_redo());
+        edit(&mut doc, "abc");
+        assert!(!doc.can_undo());
+    }
+
+    #[test]
+    fn undoing_back_to_the_saved_text_still_counts_as_unsaved() {
+        let mut doc = loaded("a");
+        edit(&mut doc, "ab");
+        doc.mark_saved(PathBuf::from("notes.txt"), "ab".to_string());
+        assert!(!doc.is_dirty());
+
+        doc.undo();
+        assert!(doc.is_dirty());
+    }
+
+    #[test]
+    fn saving_keeps_the_history_and_takes_the_new_path() {
+        let mut doc = Document::new(None, Mode::Plain);
+        edit(&mut doc, "draft");
+        doc.mark_saved(PathBuf::from("draft.txt"), "draft".to_string());
+
+        assert!(!doc.is_dirty());
+        assert_eq!(doc.path(), Some(Path::new("draft.txt")));
+        assert!(doc.can_undo());
+    }
+
+    #[test]
+    fn loading_starts_over() {
+        let mut doc = loaded("a");
+        edit(&mut doc, "ab");
+        doc.undo();
+        doc.start_sudo("secret".to_string(), Instant::now());
+
+        doc.load(Some(PathBuf::from("other.txt")), "other".to_string());
+        assert!(!doc.is_dirty());
+        assert!(!doc.can_undo());
+        assert!(!doc.can_redo());
+        assert!(!doc.sudo_active());
+        assert_eq!(doc.text(), "other");
+        assert_eq!(doc.path(), Some(Path::new("other.txt")));
+    }
+
+    #[test]
+    fn text_set_directly_is_not_an_edit() {
+        let mut doc = loaded("line 1\n");
+        doc.append_text("line 2\n");
+        assert_eq!(doc.text(), "line 1\nline 2\n");
+        assert!(!doc.is_dirty());
+        assert!(!doc.can_undo());
+    }
+
+    #[test]
+    fn mode_changes_leave_the_text_alone() {
+        let mut doc = loaded("# Title");
+        doc.set_mode(Mode::Markup);
+        assert_eq!(doc.mode(), Mode::Markup);
+        doc.set_mode(Mode::Plain);
+        assert_eq!(doc.mode(), Mode::Plain);
+        assert_eq!(doc.text(), "# Title");
+        assert!(!doc.is_dirty());
+    }
+
+    #[test]
+    fn the_sudo_password_expires() {
+        let mut doc = loaded("");
+        let start = Instant::now();
+        assert_eq!(doc.sudo_password(start), None);
+
+        doc.start_sudo("secret".to_string(), start);
+        assert!(doc.sudo_active());
+        assert_eq!(doc.sudo_password(start), Some("secret"));
+        assert_eq!(doc.sudo_remaining(start), Some(SUDO_TIMEOUT));
+
+        let later = start + SUDO_TIMEOUT + Duration::from_secs(1);
+        assert_eq!(doc.sudo_password(later), None);
+        assert_eq!(doc.sudo_remaining(later), None);
+        // Still in Sudo Mode: the next save asks for the password again
+        assert!(doc.sudo_active());
+
+        doc.end_sudo();
+        assert!(!doc.sudo_active());
+    }
+
+    // Types `text` one character at a time, `gap` apart, starting at `start`
+    fn type_chars(doc: &mut Document, text: &str, start: Instant, gap: Duration) {
+        let mut now = start;
+        for c in text.chars() {
+            let mut typed = doc.text().to_string();
+            typed.push(c);
+            doc.record_edit(typed, now);
+            now += gap;
+        }
+    }
+
+    #[test]
+    fn quick_typing_undoes_as_one_step() {
+        let mut doc = loaded("");
+        type_chars(
+            &mut doc,
+            "hello",
+            Instant::now(),
+            Duration::from_millis(100),
+        );
+        assert_eq!(doc.text(), "hello");
+
+        assert_eq!(doc.undo().as_deref(), Some(""));
+        assert!(!doc.can_undo());
+        assert_eq!(doc.redo().as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn a_pause_in_typing_starts_a_new_step() {
+        let mut doc = loaded("");
+        let start = Instant::now();
+        type_chars(&mut doc, "ab", start, Duration::from_millis(100));
+        type_chars(
+            &mut doc,
+            "cd",
+            start + UNDO_GROUP_PAUSE * 2,
+            Duration::from_millis(100),
+        );
+
+        assert_eq!(doc.undo().as_deref(), Some("ab"));
+        assert_eq!(doc.undo().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn a_line_break_starts_a_new_step() {
+        let mut doc = loaded("");
+        type_chars(
+            &mut doc,
+            "ab\ncd",
+            Instant::now(),
+            Duration::from_millis(100),
+        );
+
+        assert_eq!(doc.undo().as_deref(), Some("ab\n"));
+        assert_eq!(doc.undo().as_deref(), Some("ab"));
+        assert_eq!(doc.undo().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn backspacing_and_deleting_group_separately_from_typing() {
+        let mut doc = loaded("");
+        let now = Instant::now();
+        type_chars(&mut doc, "abcd", now, Duration::ZERO);
+        // Backspace twice, then Delete twice after moving to the start
+        doc.record_edit("abc".to_string(), now);
+        doc.record_edit("ab".to_string(), now);
+        doc.record_edit("b".to_string(), now);
+        doc.record_edit("".to_string(), now);
+
+        assert_eq!(doc.undo().as_deref(), Some("ab"));
+        assert_eq!(doc.undo().as_deref(), Some("abcd"));
+        assert_eq!(doc.undo().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn typing_somewhere_else_starts_a_new_step() {
+        let mut doc = loaded("middle");
+        let now = Instant::now();
+        doc.record_edit("middlex".to_string(), now);
+        doc.record_edit("xmiddlex".to_string(), now);
+
+        assert_eq!(doc.undo().as_deref(), Some("middlex"));
+    }
+
+    #[test]
+    fn a_paste_is_a_step_of_its_own() {
+        let mut doc = loaded("");
+        let now = Instant::now();
+        type_chars(&mut doc, "a", now, Duration::ZERO);
+        doc.record_edit("a pasted".to_string(), now);
+        type_chars(&mut doc, "é", now, Duration::ZERO);
+
+        assert_eq!(doc.undo().as_deref(), Some("a pasted"));
+        assert_eq!(doc.undo().as_deref(), Some("a"));
+        assert_eq!(doc.undo().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn typing_after_undo_or_save_starts_a_new_step() {
+        let mut doc = loaded("");
+        let now = Instant::now();
+        type_chars(&mut doc, "ab", now, Duration::ZERO);
+        doc.undo();
+        type_chars(&mut doc, "x", now, Duration::ZERO);
+        doc.mark_saved(PathBuf::from("notes.txt"), "x".to_string());
+        type_chars(&mut doc, "y", now, Duration::ZERO);
+
+        assert_eq!(doc.undo().as_deref(), Some("x"));
+        assert_eq!(doc.undo().as_deref(), Some(""));
+    }
+}
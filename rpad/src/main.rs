@@ -1,8 +1,13 @@
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use clap::{Parser, ValueEnum};
+use config::{Accels, Config, Session};
+use document::{Document, Mode};
 use gtk::glib;
 
 use gtk4 as gtk;
@@ -12,16 +17,191 @@ use sourceview5::prelude::*;
 
 use std::process::Command;
 
+mod config;
+mod document;
+mod markdown;
+mod recovery;
+mod search;
+mod text_ops;
+mod vim;
+
+const APP_ID: &str = "dev.rpad.app";
+
+// A tab's page, its document and the widgets helpers reach through it
+struct TabEntry {
+    page: glib::WeakRef<gtk::Widget>,
+    doc_state: Rc<DocumentState>,
+    widgets: TabWidgets,
+}
+
+#[derive(Clone)]
+struct TabWidgets {
+    // The main editor view; a split adds a second one (see views_of_page)
+    text_view: sv::View,
+    spell_adapter: libspelling::TextBufferAdapter,
+    minimap: sv::Map,
+}
+
+// A window and the widgets its helpers reach through it
+type WindowEntry = (
+    glib::WeakRef<gtk::ApplicationWindow>,
+    gtk::Notebook,
+    StatusBar,
+);
+
+thread_local! {
+    // Every open tab, found by page with doc_state_of and tab_widgets.
+    // Closures look states up rather than holding them, so a closed tab's
+    // state goes with its page.
+    static TABS: RefCell<Vec<TabEntry>> = const { RefCell::new(Vec::new()) };
+    // Every open window, found with notebook_from_window and
+    // status_bar_from_window
+    static WINDOWS: RefCell<Vec<WindowEntry>> = const { RefCell::new(Vec::new()) };
+}
+
+// Widget names of the extras added to each editor view
+const RELATIVE_LINE_NUMBERS: &str = "rpad-relative-line-numbers";
+const INDENT_GUIDES: &str = "rpad-indent-guides";
+
+// Files above this size get a confirmation before loading
+const LARGE_FILE_WARN_BYTES: u64 = 50 * 1024 * 1024;
+const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+
+// How often documents with unsaved changes are copied for crash recovery
+const RECOVERY_INTERVAL_SECS: u32 = 30;
+
+// Zoom bounds in percent, shared by the menu actions and the Zoom to… dialog
+const ZOOM_MIN: u32 = 10;
+const ZOOM_MAX: u32 = 1000;
+const ZOOM_DEFAULT: u32 = 100;
+const DEFAULT_ZOOM_STEP: u32 = 10;
+
+// Word/char counts are recomputed at most once per interval while typing
+const COUNTS_UPDATE_INTERVAL_MS: u64 = 250;
+
+// How long a status bar message such as "Path copied" stays up
+const STATUS_MESSAGE_SECS: u32 = 3;
+
+// Above this size sourceview highlighting makes typing lag noticeably
+const DEFAULT_HIGHLIGHT_LIMIT: usize = 2 * 1024 * 1024;
+
+// A line longer than this many bytes (minified JSON and the like) makes
+// highlighting and bracket matching crawl however small the file is
+const LONG_LINE_LIMIT: usize = 10_000;
+
+// GtkSourceView's own tab width, used until a file's indentation says otherwise
+const DEFAULT_TAB_WIDTH: u32 = 8;
+
+// Column of the right margin guide unless the config picks another
+const DEFAULT_RIGHT_MARGIN_COLUMN: u32 = 80;
+const MAX_RIGHT_MARGIN_COLUMN: u32 = 1000;
+
+// Source mark category of bookmarked lines
+const BOOKMARK_CATEGORY: &str = "rpad-bookmark";
+
+// The byte order mark some Windows programs start UTF-8 files with
+const BOM: char = '\u{feff}';
+
+// Text tag hiding folded markdown sections
+const FOLDED_TAG: &str = "rpad-folded";
+
+// The Find dialog's Count button, which reports matches without closing it
+const FIND_COUNT_RESPONSE: gtk::ResponseType = gtk::ResponseType::Other(1);
+const REPLACE_ALL_RESPONSE: gtk::ResponseType = gtk::ResponseType::Other(2);
+
+// The Statistics dialog's Copy and Save… buttons
+const STATS_COPY_RESPONSE: gtk::ResponseType = gtk::ResponseType::Other(3);
+const STATS_SAVE_RESPONSE: gtk::ResponseType = gtk::ResponseType::Other(4);
+
+// Most rows or columns Edit → Markdown → Table… offers
+const MAX_TABLE_SIZE: u32 = 50;
+
+// Markers listed by Tools → Tasks unless the config sets its own
+const DEFAULT_TASK_MARKERS: [&str; 4] = ["TODO", "FIXME", "XXX", "HACK"];
+
+// GtkSourceView style schemes used when the config doesn't pin one
+const LIGHT_STYLE_SCHEME: &str = "Adwaita";
+const DARK_STYLE_SCHEME: &str = "Adwaita-dark";
+
+// Plain black-on-white (or white-on-black) schemes for accessibility mode
+const HIGH_CONTRAST_LIGHT_STYLE_SCHEME: &str = "classic";
+const HIGH_CONTRAST_DARK_STYLE_SCHEME: &str = "classic-dark";
+
+// Accessibility mode scales the zoom level by this percentage
+const ACCESSIBLE_FONT_SCALE: u32 = 150;
+
+// Typing an opener inserts its closer too (when auto-pairing is on)
+const AUTO_PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('`', '`')];
+const APP_NAME: &str = "Rust Pad";
+const APP_ICON_NAME: &str = "rpad_icon";
+const FALLBACK_ICON_NAME: &str = "text-editor";
+
 #[derive(Parser, Debug)]
 #[command(name = "rpad", version, about = "rpad – A simple Rust notepad")]
 struct Args {
-    /// Optional file to open
+    /// Optional file to open; "FILE:LINE" opens it at that line, "-" reads stdin
     #[arg(value_name = "FILE")]
     file: Option<PathBuf>,
 
-    /// Editing mode: plain, markup, rich
-    #[arg(long, value_enum, default_value_t = ModeArg::Plain)]
-    mode: ModeArg,
+    /// Line to put the cursor on when FILE opens
+    #[arg(long, value_name = "N", requires = "file")]
+    line: Option<u32>,
+
+    /// Editing mode: plain, markup, rich [default: the config's mode, else plain]
+    #[arg(long, value_enum)]
+    mode: Option<ModeArg>,
+
+    /// Turn off syntax highlighting for files larger than this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_HIGHLIGHT_LIMIT)]
+    highlight_limit: usize,
+
+    /// Percentage added or removed by each Zoom In/Out step
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = DEFAULT_ZOOM_STEP,
+        value_parser = clap::value_parser!(u32).range(1..=100)
+    )]
+    zoom_step: u32,
+
+    /// Open FILE read-only and keep appending what's written to it, like tail -f
+    #[arg(long, requires = "file")]
+    follow: bool,
+
+    /// Start with Vim-style modal editing (Normal, Insert and Visual modes)
+    #[arg(long)]
+    vim: bool,
+
+    /// Start in accessibility mode: larger text, high contrast, current line highlighted
+    #[arg(long)]
+    accessible: bool,
+
+    /// Read and write FILE in this character encoding (e.g. ISO-8859-1) instead of UTF-8
+    #[arg(long, value_name = "NAME", requires = "file", value_parser = parse_encoding)]
+    encoding: Option<String>,
+
+    /// Convert FILE's line endings to these when it opens, and save it with them
+    #[arg(long, value_enum, requires = "file")]
+    eol: Option<EolArg>,
+
+    /// Apply transforms to FILE ("-" for stdin to stdout), save it and exit without a window.
+    /// Comma-separated: trim, final-newline, lf, crlf
+    #[arg(
+        long,
+        value_name = "TRANSFORMS",
+        value_delimiter = ',',
+        requires = "file",
+        value_parser = parse_transform
+    )]
+    apply: Vec<String>,
+
+    /// Start a separate rpad instead of handing FILE to the one already running
+    #[arg(long)]
+    new_instance: bool,
+
+    /// Install a desktop entry for the current user and exit
+    #[arg(long)]
+    install_desktop_file: bool,
 }
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
@@ -30,10 +210,98 @@ enum ModeArg {
     Markup,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Mode {
-    Plain,
-    Markup,
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum EolArg {
+    Lf,
+    Crlf,
+}
+
+impl From<EolArg> for text_ops::LineEnding {
+    fn from(eol: EolArg) -> Self {
+        match eol {
+            EolArg::Lf => text_ops::LineEnding::Lf,
+            EolArg::Crlf => text_ops::LineEnding::CrLf,
+        }
+    }
+}
+
+// --encoding only takes names iconv can convert from and to
+fn parse_encoding(name: &str) -> Result<String, String> {
+    glib::convert(b"", "UTF-8", name)
+        .map(|_| name.to_string())
+        .map_err(|_| format!("unknown encoding {name:?}"))
+}
+
+// A whole-text transform --apply can run
+type Transform = fn(&str) -> String;
+
+// Text transforms --apply runs, by name, in the order given
+const TRANSFORMS: &[(&str, Transform)] = &[
+    ("trim", text_ops::trim_trailing_whitespace),
+    ("final-newline", text_ops::ensure_final_newline),
+    ("lf", |text| {
+        text_ops::normalize_line_endings(text, text_ops::LineEnding::Lf)
+    }),
+    ("crlf", |text| {
+        text_ops::normalize_line_endings(text, text_ops::LineEnding::CrLf)
+    }),
+];
+
+fn parse_transform(name: &str) -> Result<String, String> {
+    if TRANSFORMS.iter().any(|(known, _)| *known == name) {
+        return Ok(name.to_string());
+    }
+    let known: Vec<&str> = TRANSFORMS.iter().map(|(known, _)| *known).collect();
+    Err(format!(
+        "unknown transform {name:?}; expected one of {}",
+        known.join(", ")
+    ))
+}
+
+// --apply: run the transforms over `path` and write the result back, or
+// from stdin to stdout for "-", honouring --encoding and --eol. The file is
+// only rewritten when something changed.
+fn apply_transforms(
+    path: &Path,
+    transforms: &[String],
+    encoding: Option<&str>,
+    eol: Option<text_ops::LineEnding>,
+) -> Result<(), String> {
+    use std::io::{Read, Write};
+
+    let stdio = path == Path::new("-");
+    let bytes = if stdio {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .map(|_| bytes)
+            .map_err(|err| format!("Failed to read stdin: {err}"))?
+    } else {
+        fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?
+    };
+    let original =
+        decode_text(bytes, encoding).map_err(|err| format!("{}: {err}", path.display()))?;
+
+    let mut text = original.clone();
+    for name in transforms {
+        if let Some((_, transform)) = TRANSFORMS.iter().find(|(known, _)| known == name) {
+            text = transform(&text);
+        }
+    }
+    if let Some(eol) = eol {
+        text = text_ops::normalize_line_endings(&text, eol);
+    }
+
+    let bytes = encode_text(&text, encoding)?;
+    if stdio {
+        std::io::stdout()
+            .write_all(&bytes)
+            .map_err(|err| format!("Failed to write stdout: {err}"))
+    } else if text != original {
+        fs::write(path, bytes).map_err(|err| format!("Failed to write {}: {err}", path.display()))
+    } else {
+        Ok(())
+    }
 }
 
 impl From<ModeArg> for Mode {
@@ -50,118 +318,548 @@ impl From<ModeArg> for Mode {
 struct AppConfig {
     mode: Mode,
     file: Option<PathBuf>,
+    line: Option<u32>,
+    // Text read from stdin to start with instead of a file
+    stdin_text: Option<String>,
+    highlight_limit: usize,
+    zoom: u32,
+    zoom_step: u32,
+    spell_check: bool,
+    spell_language: Option<String>,
+    auto_pair: bool,
+    smart_quotes: bool,
+    cursor_in_title: bool,
+    style_scheme: Option<String>,
+    minimap: bool,
+    show_whitespace: String,
+    line_numbers: String,
+    right_margin: bool,
+    right_margin_column: u32,
+    indent_guides: bool,
+    // Applies to the whole display; None leaves the desktop's setting
+    cursor_blink: Option<bool>,
+    match_case: bool,
+    preserve_case: bool,
+    undo_limit: usize,
+    // Forced with --encoding and --eol for the file opened at startup
+    encoding: Option<String>,
+    eol: Option<text_ops::LineEnding>,
+    follow: bool,
+    vim: bool,
+    accessible: bool,
+}
+
+// How one open request reads its file: in the encoding and with the line
+// endings --encoding and --eol forced, or UTF-8 as it is by default. The
+// tab that receives the file keeps them for saving.
+#[derive(Debug, Clone, Default)]
+struct FileFormat {
+    encoding: Option<String>,
+    eol: Option<text_ops::LineEnding>,
 }
 
 #[derive(Debug)]
 struct DocumentState {
-    path: RefCell<Option<PathBuf>>,
-    mode: RefCell<Mode>, // 🔹 NEW
-    undo_stack: RefCell<Vec<String>>,
-    redo_stack: RefCell<Vec<String>>,
-    last_text: RefCell<String>,
+    // File, mode, undo history and Sudo Mode; change it through
+    // update_document so the tab label keeps up
+    document: RefCell<Document>,
     is_programmatic: RefCell<bool>,
-    dirty: RefCell<bool>,
     find_text: RefCell<String>,
     match_case: RefCell<bool>,
+    // Replace's "Preserve case" option, last set in the dialog
+    preserve_case: RefCell<bool>,
+    // Earlier Find and Replace terms offered in the dialogs, newest first
+    find_history: RefCell<Vec<String>>,
+    replace_history: RefCell<Vec<String>>,
     zoom: RefCell<u32>,
+    zoom_step: u32,
+    highlight_limit: usize,
+    highlight_suppressed: RefCell<bool>,
+    // Style scheme pinned in the config; None follows the desktop
+    style_scheme: RefCell<Option<String>>,
+    auto_pair: RefCell<bool>,
+    // Typographic quotes, dashes and ellipses while typing prose
+    smart_quotes: RefCell<bool>,
+    // Line and column shown in the window title as well as the status bar
+    cursor_in_title: RefCell<bool>,
+    // Vim-style modal editing state; None while it's off
+    vim: RefCell<Option<vim::Vim>>,
+    // Larger text, a high-contrast scheme and the current line highlighted
+    accessible: RefCell<bool>,
+    // Whitespace drawn as glyphs: "none", "all", "leading" or "trailing"
+    show_whitespace: RefCell<String>,
+    // "absolute", "relative" (counted from the cursor line) or "none"
+    line_numbers: RefCell<String>,
+    // Where the last edit happened; a mark so it moves with later edits
+    last_edit: RefCell<Option<gtk::TextMark>>,
+    // Bookmarked lines, kept as source marks so they move with edits
+    bookmarks: RefCell<Vec<sv::Mark>>,
+    // Column guide drawn at `right_margin_column` when `right_margin` is on
+    right_margin: RefCell<bool>,
+    right_margin_column: RefCell<u32>,
+    // Lines at each indent level, drawn over views whose buffer has a language
+    indent_guides: RefCell<bool>,
+    // Indentation detected in the loaded text; None when nothing is indented
+    indent: RefCell<Option<text_ops::Indent>>,
+    // The file started with a byte order mark, which is kept out of the
+    // buffer and written back on save
+    bom: RefCell<bool>,
+    // Encoding the file is read and written in, when --encoding forced one;
+    // otherwise UTF-8
+    encoding: RefCell<Option<String>>,
+    // Line endings forced with --eol, applied on load and on save
+    eol: RefCell<Option<text_ops::LineEnding>>,
+    // Sits just before the closer auto-pairing inserted last, so typing that
+    // closer steps over it instead of doubling it
+    auto_pair_mark: RefCell<Option<gtk::TextMark>>,
     css_provider: gtk::CssProvider,
+    tab_label: gtk::Label,
+    status: StatusBar,
+
+    // Names this document's crash recovery copy; see recovery.rs
+    recovery_id: String,
+    // Hash of the text last copied there, to skip rewriting unchanged text
+    recovery_hash: RefCell<Option<u64>>,
+    // Advisory lock on the file, held from open or save until the tab
+    // closes or the document moves to another path; see lock_document
+    lock: RefCell<Option<(PathBuf, fs::File)>>,
+}
+
+// The window's status bar. It shows whichever tab is active, so every
+// tab's DocumentState holds a handle to the same widgets.
+#[derive(Debug, Clone)]
+struct StatusBar {
     label_line_col: gtk::Label,
     label_words_chars: gtk::Label,
+    label_selection: gtk::Label,
+    counts_timeout: Rc<RefCell<Option<glib::SourceId>>>,
+    // Ticks label_sudo's countdown while the active tab is in Sudo Mode
+    sudo_timeout: Rc<RefCell<Option<glib::SourceId>>>,
     label_mode: gtk::Label,
     label_sudo: gtk::Label,
+    label_vim: gtk::Label,
+    label_indent: gtk::Label,
+    // Brief feedback such as "Path copied", cleared by message_timeout
+    label_message: gtk::Label,
+    message_timeout: Rc<RefCell<Option<glib::SourceId>>>,
+    spinner: gtk::Spinner,
+    highlight_button: gtk::Button,
     status_box: gtk::Box,
-
-    // Sudo Mode
-    sudo_password: RefCell<Option<String>>,
-    sudo_expiry: RefCell<Option<std::time::Instant>>,
 }
 
-impl DocumentState {
-    fn new(initial: Option<PathBuf>, initial_mode: Mode) -> Self {
-        // 🔹 CHANGED
+impl StatusBar {
+    fn new() -> Self {
         Self {
-            path: RefCell::new(initial),
-            mode: RefCell::new(initial_mode), // 🔹 NEW
-            undo_stack: RefCell::new(Vec::new()),
-            redo_stack: RefCell::new(Vec::new()),
-            last_text: RefCell::new(String::new()),
-            is_programmatic: RefCell::new(false),
-            dirty: RefCell::new(false),
-            find_text: RefCell::new(String::new()),
-            match_case: RefCell::new(false),
-            zoom: RefCell::new(100),
-            css_provider: gtk::CssProvider::new(),
             label_line_col: gtk::Label::new(Some("Ln 1, Col 1")),
             label_words_chars: gtk::Label::new(Some("0 words, 0 chars")),
-            label_mode: gtk::Label::new(Some(match initial_mode {
-                Mode::Plain => "Plain Text",
-                Mode::Markup => "Markdown",
-            })),
+            label_selection: {
+                let l = gtk::Label::new(None);
+                l.set_visible(false);
+                l
+            },
+            counts_timeout: Rc::new(RefCell::new(None)),
+            sudo_timeout: Rc::new(RefCell::new(None)),
+            label_mode: gtk::Label::new(Some("Plain Text")),
             label_sudo: {
                 let l = gtk::Label::new(None);
                 l.set_markup("<span weight='bold' foreground='red'>SUDO</span>");
                 l.set_visible(false); // Hidden by default
                 l
             },
+            label_vim: {
+                let l = gtk::Label::new(None);
+                l.set_visible(false);
+                l
+            },
+            label_indent: {
+                let l = gtk::Label::new(None);
+                l.set_tooltip_text(Some("Indentation detected in the file"));
+                l.set_visible(false);
+                l
+            },
+            label_message: {
+                let l = gtk::Label::new(None);
+                l.add_css_class("dim-label");
+                l.set_ellipsize(gtk::pango::EllipsizeMode::Middle);
+                l.set_max_width_chars(60);
+                l.set_visible(false);
+                l
+            },
+            message_timeout: Rc::new(RefCell::new(None)),
+            spinner: {
+                let s = gtk::Spinner::new();
+                s.set_tooltip_text(Some("Loading…"));
+                s.set_visible(false);
+                s
+            },
+            highlight_button: {
+                let b = gtk::Button::with_label("Enable Highlighting");
+                b.set_has_frame(false);
+                b.set_tooltip_text(Some(
+                    "Highlighting was turned off because the file is large",
+                ));
+                b.set_visible(false);
+                b
+            },
             status_box: gtk::Box::new(gtk::Orientation::Horizontal, 12),
-            sudo_password: RefCell::new(None),
-            sudo_expiry: RefCell::new(None),
         }
     }
+}
+
+impl DocumentState {
+    fn new(initial: Option<PathBuf>, initial_mode: Mode, status: StatusBar) -> Self {
+        let doc_state = Self {
+            document: RefCell::new(Document::new(initial, initial_mode)),
+            is_programmatic: RefCell::new(false),
+            find_text: RefCell::new(String::new()),
+            find_history: RefCell::new(Vec::new()),
+            replace_history: RefCell::new(Vec::new()),
+            match_case: RefCell::new(false),
+            preserve_case: RefCell::new(false),
+            zoom: RefCell::new(ZOOM_DEFAULT),
+            zoom_step: DEFAULT_ZOOM_STEP,
+            highlight_limit: DEFAULT_HIGHLIGHT_LIMIT,
+            highlight_suppressed: RefCell::new(false),
+            style_scheme: RefCell::new(None),
+            auto_pair: RefCell::new(true),
+            smart_quotes: RefCell::new(false),
+            cursor_in_title: RefCell::new(false),
+            vim: RefCell::new(None),
+            accessible: RefCell::new(false),
+            show_whitespace: RefCell::new("none".to_string()),
+            line_numbers: RefCell::new("none".to_string()),
+            last_edit: RefCell::new(None),
+            bookmarks: RefCell::new(Vec::new()),
+            right_margin: RefCell::new(false),
+            right_margin_column: RefCell::new(DEFAULT_RIGHT_MARGIN_COLUMN),
+            indent_guides: RefCell::new(false),
+            bom: RefCell::new(false),
+            encoding: RefCell::new(None),
+            eol: RefCell::new(None),
+            indent: RefCell::new(None),
+            auto_pair_mark: RefCell::new(None),
+            css_provider: gtk::CssProvider::new(),
+            tab_label: gtk::Label::new(None),
+            status,
+            recovery_id: recovery::new_id(),
+            recovery_hash: RefCell::new(None),
+            lock: RefCell::new(None),
+        };
+        doc_state.update_tab_label();
+        doc_state
+    }
+
+    // Change the document, then bring the tab label in line with its path
+    // and unsaved state. A document that became clean needs no recovery copy.
+    fn update_document<R>(&self, f: impl FnOnce(&mut Document) -> R) -> R {
+        let (was_dirty, old_path) = {
+            let document = self.document.borrow();
+            (document.is_dirty(), document.path().map(Path::to_path_buf))
+        };
+        let result = f(&mut self.document.borrow_mut());
+
+        let (dirty, path_changed) = {
+            let document = self.document.borrow();
+            (document.is_dirty(), document.path() != old_path.as_deref())
+        };
+        if dirty != was_dirty || path_changed {
+            self.update_tab_label();
+        }
+        if was_dirty && !dirty {
+            self.forget_recovery();
+        }
+        result
+    }
 
     fn set_path(&self, new_path: Option<PathBuf>) {
-        *self.path.borrow_mut() = new_path;
+        self.update_document(|document| document.set_path(new_path));
     }
 
     fn path(&self) -> Option<PathBuf> {
-        self.path.borrow().clone()
+        self.document.borrow().path().map(Path::to_path_buf)
     }
 
     fn mode(&self) -> Mode {
-        // 🔹 NEW
-        *self.mode.borrow()
+        self.document.borrow().mode()
     }
 
     fn set_mode(&self, value: Mode) {
-        // 🔹 NEW
-        *self.mode.borrow_mut() = value;
+        self.document.borrow_mut().set_mode(value);
     }
 
     fn set_dirty(&self, value: bool) {
-        *self.dirty.borrow_mut() = value;
+        self.update_document(|document| document.set_dirty(value));
+    }
+
+    fn sudo_active(&self) -> bool {
+        self.document.borrow().sudo_active()
+    }
+
+    // Copy the text aside if it changed since the last copy
+    fn save_recovery(&self) {
+        use std::hash::{Hash, Hasher};
+
+        let document = self.document.borrow();
+        let text = document.text();
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        text.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if *self.recovery_hash.borrow() != Some(hash) {
+            recovery::save(&self.recovery_id, document.path(), text);
+            *self.recovery_hash.borrow_mut() = Some(hash);
+        }
+    }
+
+    fn forget_recovery(&self) {
+        if self.recovery_hash.take().is_some() {
+            recovery::remove(&self.recovery_id);
+        }
     }
 
     fn is_dirty(&self) -> bool {
-        *self.dirty.borrow()
+        self.document.borrow().is_dirty()
+    }
+
+    // File name, marked with * while there are unsaved changes
+    fn update_tab_label(&self) {
+        let path = self.path();
+        let name = path
+            .as_deref()
+            .and_then(|p| p.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Untitled".to_string());
+        let marker = if self.is_dirty() { "*" } else { "" };
+        self.tab_label.set_text(&format!("{marker}{name}"));
+        self.tab_label
+            .set_tooltip_text(path.as_deref().map(|p| p.display().to_string()).as_deref());
     }
 }
 
 fn main() {
     // 1. Parse CLI args
     let args = Args::parse();
-    let initial_mode: Mode = args.mode.into();
+
+    if args.install_desktop_file {
+        match install_desktop_file() {
+            Ok(path) => println!("Installed desktop entry to {}", path.display()),
+            Err(err) => {
+                eprintln!("Error installing desktop entry: {err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Scripted use: transform FILE and exit without starting GTK
+    if let (false, Some(file)) = (args.apply.is_empty(), &args.file) {
+        let eol = args.eol.map(Into::into);
+        if let Err(err) = apply_transforms(file, &args.apply, args.encoding.as_deref(), eol) {
+            eprintln!("Error: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    install_panic_hook();
+
+    // "-", or input piped in without a file, becomes an untitled document
+    let stdin_requested = args.file.as_deref() == Some(Path::new("-"));
+    let stdin_piped = args.file.is_none() && !std::io::stdin().is_terminal();
+    let stdin_text = if stdin_requested || stdin_piped {
+        match std::io::read_to_string(std::io::stdin()) {
+            Ok(text) => Some(text).filter(|text| stdin_requested || !text.is_empty()),
+            Err(err) => {
+                eprintln!("Error reading standard input: {err}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let file = args.file.filter(|_| !stdin_requested);
+
+    // "file.txt:120" opens at line 120, unless that is the file's real name
+    let (file, suffix_line) = match file {
+        Some(path) => {
+            let (path, line) = split_line_suffix(path);
+            (Some(path), line)
+        }
+        None => (None, None),
+    };
+
+    // Saved preferences
+    let prefs = Config::load();
+
+    // --mode wins over the config's default mode
+    let initial_mode = args
+        .mode
+        .or_else(|| {
+            let name = prefs.mode.as_deref()?;
+            ModeArg::from_str(name, true)
+                .map_err(|_| eprintln!("Unknown mode {name:?} in config; using plain"))
+                .ok()
+        })
+        .map_or(Mode::Plain, Mode::from);
 
     let config = AppConfig {
         mode: initial_mode,
-        file: args.file,
+        file,
+        line: args.line.or(suffix_line),
+        stdin_text,
+        highlight_limit: args.highlight_limit,
+        zoom: prefs.zoom.unwrap_or(ZOOM_DEFAULT).clamp(ZOOM_MIN, ZOOM_MAX),
+        zoom_step: args.zoom_step,
+        spell_check: prefs.spell_check.unwrap_or(false),
+        spell_language: prefs.spell_language,
+        auto_pair: prefs.auto_pair.unwrap_or(true),
+        smart_quotes: prefs.smart_quotes.unwrap_or(false),
+        cursor_in_title: prefs.cursor_in_title.unwrap_or(false),
+        style_scheme: prefs.style_scheme,
+        minimap: prefs.minimap.unwrap_or(false),
+        show_whitespace: prefs.show_whitespace.unwrap_or_else(|| "none".to_string()),
+        line_numbers: prefs.line_numbers.unwrap_or_else(|| "none".to_string()),
+        right_margin: prefs.right_margin.unwrap_or(false),
+        right_margin_column: prefs
+            .right_margin_column
+            .unwrap_or(DEFAULT_RIGHT_MARGIN_COLUMN)
+            .clamp(1, MAX_RIGHT_MARGIN_COLUMN),
+        indent_guides: prefs.indent_guides.unwrap_or(false),
+        cursor_blink: prefs.cursor_blink,
+        match_case: prefs.match_case.unwrap_or(false),
+        preserve_case: prefs.preserve_case.unwrap_or(false),
+        undo_limit: prefs.undo_limit.unwrap_or(document::DEFAULT_UNDO_LIMIT),
+        encoding: args.encoding,
+        eol: args.eol.map(Into::into),
+        follow: args.follow,
+        vim: args.vim,
+        accessible: args.accessible,
     };
 
-    // 2. Create GTK application
+    // 2. Create GTK application. One instance serves every invocation;
+    // piped text belongs to this process, though, so it always gets its own,
+    // as does a file with a forced encoding or line ending
+    glib::set_application_name(APP_NAME);
+    let mut flags = gtk::gio::ApplicationFlags::HANDLES_OPEN;
+    let forced_format = config.encoding.is_some() || config.eol.is_some();
+    if args.new_instance || config.stdin_text.is_some() || forced_format {
+        flags |= gtk::gio::ApplicationFlags::NON_UNIQUE;
+    }
     let app = gtk::Application::builder()
-        .application_id("dev.rpad.app")
+        .application_id(APP_ID)
+        .flags(flags)
+        .register_session(true)
         .build();
 
-    // 3. Pass config into the activate handler (clone into closure)
+    // Quit and Save All belong to the application; the rest to each window
+    app.connect_startup(register_app_actions);
+
+    // 3. Pass config into the activate handler (clone into closure). Only
+    // the first activation is this invocation's; later ones come from rpad
+    // being run again without a file, and get an empty window.
     let config_clone = config.clone();
+    let first_activation = Rc::new(std::cell::Cell::new(true));
     app.connect_activate(move |app| {
-        build_ui(app, config_clone.clone());
+        let mut config = config_clone.clone();
+        if !first_activation.replace(false) {
+            config.file = None;
+            config.line = None;
+            config.stdin_text = None;
+            config.follow = false;
+        }
+        build_ui(app, config);
+    });
+
+    // Logging out: hold the session until unsaved documents are dealt with
+    app.connect_query_end(|app| {
+        let unsaved = unsaved_documents(app);
+        if unsaved.is_empty() {
+            return;
+        }
+        let cookie = app.inhibit(
+            app.active_window().as_ref(),
+            gtk::ApplicationInhibitFlags::LOGOUT,
+            Some("There are unsaved documents"),
+        );
+        let app_clone = app.clone();
+        confirm_save_all(app, unsaved, move || app_clone.uninhibit(cookie));
+    });
+
+    // Files handed over by later invocations open as tabs in the front window
+    app.connect_open(|app, files, hint| {
+        let (line, follow) = parse_open_hint(hint);
+        let mut window = app
+            .active_window()
+            .or_else(|| app.windows().into_iter().next());
+        if window.is_none() {
+            app.activate();
+            window = app.windows().into_iter().next();
+        }
+        let Some(window) = window.and_downcast::<gtk::ApplicationWindow>() else {
+            return;
+        };
+
+        for path in files.iter().filter_map(|file| file.path()) {
+            if follow {
+                follow_file(&window, &path, FileFormat::default());
+            } else {
+                open_file(&window, &path, line, FileFormat::default());
+            }
+        }
+        window.present();
     });
 
-    // 4. Run
-    app.run();
+    // 4. Hand this invocation to an rpad that is already running, or run
+    if let Err(err) = app.register(gtk::gio::Cancellable::NONE) {
+        eprintln!("Failed to register application: {err}");
+    }
+    if app.is_remote() {
+        match &config.file {
+            Some(path) => app.open(&[gtk::gio::File::for_path(path)], &open_hint(&config)),
+            None => app.activate(),
+        }
+        // The request is only queued until the connection is flushed
+        if let Some(connection) = app.dbus_connection() {
+            if let Err(err) = connection.flush_sync(gtk::gio::Cancellable::NONE) {
+                eprintln!("Failed to reach the running rpad: {err}");
+            }
+        }
+        return;
+    }
+
+    // Arguments were parsed above, so GTK gets none of its own
+    app.run_with_args::<&str>(&[]);
+}
+
+// Options that travel with a file handed to the running instance, as the
+// hint of its "open" signal: "line=N" and "follow", comma-separated
+fn open_hint(config: &AppConfig) -> String {
+    let mut parts = Vec::new();
+    if let Some(line) = config.line {
+        parts.push(format!("line={line}"));
+    }
+    if config.follow {
+        parts.push("follow".to_string());
+    }
+    parts.join(",")
+}
+
+fn parse_open_hint(hint: &str) -> (Option<u32>, bool) {
+    let mut line = None;
+    let mut follow = false;
+    for part in hint.split(',') {
+        if part == "follow" {
+            follow = true;
+        } else if let Some(value) = part.strip_prefix("line=") {
+            line = value.parse().ok();
+        }
+    }
+    (line, follow)
 }
 
 fn build_ui(app: &gtk::Application, config: AppConfig) {
+    libspelling::init();
+
     // Window
     let title = match &config.file {
         Some(path) => format!("rpad - {}", path.display()),
@@ -175,1540 +873,6813 @@ fn build_ui(app: &gtk::Application, config: AppConfig) {
         .default_height(700)
         .build();
 
-    // Register custom icon
+    // Register custom icon, falling back to a themed one if it can't be found
+    let mut icon_name = FALLBACK_ICON_NAME;
     if let Some(display) = gtk::gdk::Display::default() {
         let icon_theme = gtk::IconTheme::for_display(&display);
-        if let Ok(cwd) = std::env::current_dir() {
-            icon_theme.add_search_path(cwd.join("assets"));
-        } else {
-            // Fallback if current_dir fails?
-            icon_theme.add_search_path("assets");
+        register_icon_search_paths(&icon_theme);
+        if icon_theme.has_icon(APP_ICON_NAME) {
+            icon_name = APP_ICON_NAME;
         }
     }
-    window.set_icon_name(Some("rpad_icon"));
+    window.set_icon_name(Some(icon_name));
+    gtk::Window::set_default_icon_name(icon_name);
+
+    // Documents live in the pages of this notebook, each page carrying its
+    // own view and DocumentState; window-level helpers use the current page
+    let notebook = gtk::Notebook::builder()
+        .scrollable(true)
+        .show_border(false)
+        .show_tabs(false)
+        .vexpand(true)
+        .build();
+    let status = StatusBar::new();
+    attach_window_widgets(&window, &notebook, &status);
 
-    // Track current file path + mode in window data
-    let doc_state = DocumentState::new(config.file.clone(), config.mode);
-    unsafe {
-        window.set_data("rpad-doc-state", doc_state);
+    // Keep the title, status bar and actions on whichever tab is in front
+    {
+        let window_clone = window.clone();
+        notebook.connect_page_notify(move |_| sync_ui_with_active_tab(&window_clone));
     }
 
-    // Main text area using GtkSourceView5
-    let buffer = sv::Buffer::new(None); // no language yet
-    let text_view = sv::View::with_buffer(&buffer);
-
-    text_view.set_monospace(true);
-    text_view.set_wrap_mode(gtk::WrapMode::WordChar);
-
-    apply_language_for_mode(&buffer, config.mode);
+    // The tab strip only appears once there's more than one document
+    notebook.connect_page_added(|notebook, _, _| notebook.set_show_tabs(notebook.n_pages() > 1));
+    notebook.connect_page_removed(|notebook, _, _| notebook.set_show_tabs(notebook.n_pages() > 1));
 
-    // Apply initial zoom
-    unsafe {
-        if let Some(doc_state_ptr) = window.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
-            text_view.style_context().add_provider(
-                &doc_state.css_provider,
-                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-            );
-            update_zoom_css(doc_state);
-        }
-    }
+    add_tab(&window, &config);
 
-    // Store the editor view on the window so helpers can find its buffer
-    unsafe {
-        window.set_data("rpad-text-view", text_view.clone());
+    if config.cursor_blink.is_some() {
+        apply_cursor_blink(config.cursor_blink);
     }
 
-    // Padding inside the editor
-    text_view.set_left_margin(12);
-    text_view.set_right_margin(12);
-    text_view.set_top_margin(8);
-    text_view.set_bottom_margin(8);
-
-    // Track edits for undo/redo *and* dirty flag
-    {
-        let window_clone_1 = window.clone();
-        let window_clone_2 = window.clone();
-        buffer.connect_changed(move |buf| unsafe {
-            if let Some(doc_state_ptr) = window_clone_1.data::<DocumentState>("rpad-doc-state") {
-                let doc_state: &DocumentState = doc_state_ptr.as_ref();
-
-                if *doc_state.is_programmatic.borrow() {
-                    return;
-                }
-
-                let (start, end) = buf.bounds();
-                let text = buf.text(&start, &end, false).to_string();
-
-                let mut last_text = doc_state.last_text.borrow_mut();
-                if text != *last_text {
-                    doc_state.undo_stack.borrow_mut().push(last_text.clone());
-                    doc_state.redo_stack.borrow_mut().clear();
-                    *last_text = text;
-                    doc_state.set_dirty(true);
-                    update_counts(doc_state, buf.upcast_ref());
-                }
+    // Switch between the light and dark style schemes along with the desktop
+    if let Some(settings) = gtk::Settings::default() {
+        let window_weak = window.downgrade();
+        let on_change = move |_: &gtk::Settings| {
+            if let Some(window) = window_weak.upgrade() {
+                refresh_style_schemes(&window);
+            }
+        };
+        let handlers = RefCell::new(vec![
+            settings.connect_gtk_application_prefer_dark_theme_notify(on_change.clone()),
+            settings.connect_gtk_theme_name_notify(on_change),
+        ]);
+        window.connect_destroy(move |_| {
+            for handler in handlers.take() {
+                settings.disconnect(handler);
             }
         });
+    }
 
-        // 2) Track cursor movement for Line/Col
-        buffer.connect_mark_set(move |buf, _iter, mark| {
-            unsafe {
-                if let Some(doc_state_ptr) = window_clone_2.data::<DocumentState>("rpad-doc-state")
-                {
-                    let doc_state: &DocumentState = doc_state_ptr.as_ref();
-                    // Only update if "insert" mark moved
-                    if mark.name().as_deref() == Some("insert") {
-                        update_cursor(doc_state, buf.upcast_ref());
-                    }
-                }
-            }
+    // Apply hand edits of the config file as they are saved
+    let window_weak = window.downgrade();
+    if let Some(monitor) = Config::watch(move |old, new| {
+        if let Some(window) = window_weak.upgrade() {
+            apply_config_change(&window, old, new);
+        }
+    }) {
+        // Held by the handler until the window goes
+        window.connect_destroy(move |_| {
+            monitor.cancel();
         });
     }
 
-    let scrolled = gtk::ScrolledWindow::builder()
-        .child(&text_view)
-        .hexpand(true)
-        .vexpand(true)
-        .build();
+    // Menu bar, with the application menu pinned to its right end
+    let menubar = build_menubar();
+    menubar.set_hexpand(true);
 
-    scrolled.set_margin_top(4);
-    scrolled.set_margin_bottom(4);
-    scrolled.set_margin_start(4);
-    scrolled.set_margin_end(4);
+    let app_menu_button = gtk::MenuButton::builder()
+        .icon_name("open-menu-symbolic")
+        .menu_model(&build_app_menu())
+        .tooltip_text("Main Menu")
+        .has_frame(false)
+        .build();
 
-    // Menu bar
-    let menubar = build_menubar();
+    let menubar_row = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    menubar_row.append(&menubar);
+    menubar_row.append(&app_menu_button);
 
     // Main container (vertical: menubar on top, editor below, status bar bottom)
     let vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
-    vbox.append(&menubar);
-    vbox.append(&scrolled);
+    vbox.append(&menubar_row);
+    vbox.append(&notebook);
+
+    // Status Bar
+    let status_box = &status.status_box;
+
+    status_box.set_margin_start(6);
+    status_box.set_margin_end(6);
+    status_box.set_margin_top(2);
+    status_box.set_margin_bottom(2);
+
+    // Add items to status box
+    status_box.append(&status.label_vim);
+    status_box.append(&status.spinner);
+    status_box.append(&status.label_sudo);
+    status_box.append(&gtk::Separator::new(gtk::Orientation::Vertical));
+    status_box.append(&status.label_mode);
+    status
+        .label_mode
+        .set_tooltip_text(Some("Click to change mode"));
+    attach_status_menu(&status.label_mode, &build_mode_menu());
+    status_box.append(&gtk::Separator::new(gtk::Orientation::Vertical));
+    status_box.append(&status.label_line_col);
+    status_box.append(&gtk::Box::new(gtk::Orientation::Horizontal, 0)); // spacer
+    status_box.append(&status.label_message);
+
+    // Push words/chars to the right
+    let spacer = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    spacer.set_hexpand(true);
+    status_box.append(&spacer);
+
+    status_box.append(&status.highlight_button);
+    status_box.append(&status.label_indent);
+    status_box.append(&status.label_selection);
+    status_box.append(&status.label_words_chars);
+
+    // Right-click anywhere on the status bar for document shortcuts
+    let status_menu = gtk::gio::Menu::new();
+    status_menu.append(Some("Copy File Path"), Some("win.copy_path"));
+    status_menu.append(
+        Some("Open Containing Folder"),
+        Some("win.open_containing_folder"),
+    );
+    let status_popover = gtk::PopoverMenu::from_model(Some(&status_menu));
+    status_popover.set_parent(status_box);
+    status_popover.set_has_arrow(false);
+    let status_gesture = gtk::GestureClick::new();
+    status_gesture.set_button(gtk::gdk::BUTTON_SECONDARY);
+    let status_popover_clone = status_popover.clone();
+    status_gesture.connect_pressed(move |_, _, x, y| {
+        status_popover_clone
+            .set_pointing_to(Some(&gtk::gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+        status_popover_clone.popup();
+    });
+    status_box.add_controller(status_gesture);
+    status_box.connect_destroy(move |_| {
+        status_popover.unparent();
+    });
 
-    // Status Bar (retrieved from State)
-    if let Some(doc_state_ptr) = unsafe { window.data::<DocumentState>("rpad-doc-state") } {
-        unsafe {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
-            let status_box = &doc_state.status_box;
+    // One-click override for the large-file highlighting cutoff
+    let window_clone = window.clone();
+    status.highlight_button.connect_clicked(move |button| {
+        if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+            *doc_state.highlight_suppressed.borrow_mut() = false;
+            if let Some(buffer) = get_source_buffer_from_window(&window_clone) {
+                apply_highlighting(&buffer, doc_state);
+            }
+        }
+        button.set_visible(false);
+    });
 
-            status_box.set_margin_start(6);
-            status_box.set_margin_end(6);
-            status_box.set_margin_top(2);
-            status_box.set_margin_bottom(2);
+    vbox.append(status_box);
 
-            // Add items to status box
-            status_box.append(&doc_state.label_sudo);
-            status_box.append(&gtk::Separator::new(gtk::Orientation::Vertical));
-            status_box.append(&doc_state.label_mode);
-            status_box.append(&gtk::Separator::new(gtk::Orientation::Vertical));
-            status_box.append(&doc_state.label_line_col);
-            status_box.append(&gtk::Box::new(gtk::Orientation::Horizontal, 0)); // spacer
+    window.set_child(Some(&vbox));
+
+    // Paste only makes sense with text on the clipboard
+    {
+        let window_clone = window.clone();
+        window.clipboard().connect_changed(move |clipboard| {
+            update_paste_actions(&window_clone, clipboard_has_text(clipboard));
+        });
+    }
 
-            // Push words/chars to the right
-            let spacer = gtk::Box::new(gtk::Orientation::Horizontal, 0);
-            spacer.set_hexpand(true);
-            status_box.append(&spacer);
+    // Ask for confirmation when closing if any tab has unsaved changes.
+    // Tabs answered with Don't Save are skipped for the rest of that close
+    // only; they stay unsaved in case a later tab's prompt is cancelled.
+    let discarded_pages: Rc<RefCell<Vec<gtk::Widget>>> = Rc::default();
+    window.connect_close_request(move |win| {
+        let discarded = discarded_pages.take();
+        let pages = tab_pages(win);
+        let dirty_page = pages.iter().find(|page| {
+            !discarded.contains(page)
+                && doc_state_of(*page).is_some_and(|doc_state| doc_state.is_dirty())
+        });
 
-            status_box.append(&doc_state.label_words_chars);
+        // Dirty tab → prompt about it, closing again once it's dealt with
+        // (which moves on to the next dirty tab, if any)
+        if let Some(page) = dirty_page {
+            select_tab(win, page);
+            let win_for_close = win.clone();
+            let page = page.clone();
+            let discarded_pages = discarded_pages.clone();
+            confirm_discard(win, move |was_discarded| {
+                let mut discarded = discarded.clone();
+                if was_discarded {
+                    discarded.push(page.clone());
+                }
+                *discarded_pages.borrow_mut() = discarded;
+                win_for_close.close();
+            });
 
-            vbox.append(status_box);
+            // We handled the event asynchronously; prevent immediate close
+            return glib::Propagation::Stop;
         }
-    }
 
-    window.set_child(Some(&vbox));
+        // The current tab goes last so its zoom becomes the default
+        let current = current_page(win);
+        for page in pages.iter().filter(|page| Some(*page) != current.as_ref()) {
+            if let Some(doc_state) = doc_state_of(page).as_deref() {
+                remember_zoom(doc_state);
+            }
+        }
+        if let Some(doc_state) = active_doc_state(win).as_deref() {
+            remember_zoom(doc_state);
+        }
+        for page in &pages {
+            if let Some(doc_state) = doc_state_of(page).as_deref() {
+                doc_state.forget_recovery();
+            }
+        }
+        glib::Propagation::Proceed
+    });
 
-    // Ask for confirmation when closing if there are unsaved changes
+    // Copy unsaved documents aside now and then, so a crash doesn't lose them
     {
-        let _window_clone = window.clone();
-        window.connect_close_request(move |win| {
-            unsafe {
-                if let Some(doc_state_ptr) = win.data::<DocumentState>("rpad-doc-state") {
-                    let doc_state: &DocumentState = doc_state_ptr.as_ref();
-
-                    // If not dirty, allow normal close
-                    if !doc_state.is_dirty() {
-                        return glib::Propagation::Proceed;
+        let window_weak = window.downgrade();
+        glib::timeout_add_seconds_local(RECOVERY_INTERVAL_SECS, move || {
+            let Some(window) = window_weak.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            for page in tab_pages(&window) {
+                if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                    if doc_state.is_dirty() {
+                        doc_state.save_recovery();
                     }
-
-                    // Document is dirty → prompt
-                    let win_for_dialog = win.clone();
-
-                    let dialog = gtk::MessageDialog::builder()
-                        .transient_for(&win_for_dialog)
-                        .modal(true)
-                        .message_type(gtk::MessageType::Question)
-                        .buttons(gtk::ButtonsType::None)
-                        .text("Do you want to save changes to this document before closing?")
-                        .secondary_text("If you don’t save, your changes will be lost.")
-                        .build();
-
-                    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
-                    dialog.add_button("Don't Save", gtk::ResponseType::Reject);
-                    dialog.add_button("Save", gtk::ResponseType::Accept);
-
-                    dialog.connect_response(move |dialog, response| {
-                        match response {
-                            gtk::ResponseType::Accept => {
-                                // Save then close
-                                if let Some(doc_state_ptr) =
-                                    win_for_dialog.data::<DocumentState>("rpad-doc-state")
-                                {
-                                    let doc_state: &DocumentState = doc_state_ptr.as_ref();
-
-                                    if let Some(path) = doc_state.path() {
-                                        // Save to existing path
-                                        if let Err(err) =
-                                            save_buffer_to_path(&win_for_dialog, &path)
-                                        {
-                                            eprintln!("Error saving file: {err}");
-                                        } else {
-                                            win_for_dialog.close();
-                                        }
-                                    } else {
-                                        // No path yet → Save As + close
-                                        save_as_with_dialog_and_then_close(&win_for_dialog);
-                                    }
-                                }
-                            }
-                            gtk::ResponseType::Reject => {
-                                // Don't save: mark as clean so close_request won't re-prompt
-                                if let Some(doc_state_ptr) =
-                                    win_for_dialog.data::<DocumentState>("rpad-doc-state")
-                                {
-                                    let doc_state: &DocumentState = doc_state_ptr.as_ref();
-                                    doc_state.set_dirty(false);
-                                }
-
-                                win_for_dialog.close();
-                            }
-                            _ => {
-                                // Cancel → do nothing, keep window open
-                            }
-                        }
-
-                        dialog.close();
-                    });
-
-                    dialog.show();
-
-                    // We handled the event asynchronously; prevent immediate close
-                    glib::Propagation::Stop
-                } else {
-                    // No doc state → just close
-                    glib::Propagation::Proceed
                 }
             }
+            glib::ControlFlow::Continue
         });
     }
 
     // If a file was passed via CLI, load it now
     if let Some(ref path) = config.file {
-        if let Err(err) = load_file_into_window(&window, path) {
-            eprintln!("Error loading file {}: {err}", path.display());
+        let format = FileFormat {
+            encoding: config.encoding.clone(),
+            eol: config.eol,
+        };
+        if config.follow {
+            follow_file(&window, path, format);
+        } else {
+            open_file(&window, path, config.line, format);
         }
+    } else if let Some(ref text) = config.stdin_text {
+        set_untitled_contents(&window, text);
     }
 
     // Register actions
-    register_actions(app, &window, &text_view);
+    register_actions(app, &window);
 
     window.present();
-}
-
-fn build_menubar() -> gtk::PopoverMenuBar {
-    use gtk::gio;
 
-    // Top-level menu model
-    let root = gio::Menu::new();
+    offer_recovery(&window);
+}
 
-    // ----- File menu -----
-    let file_menu = gio::Menu::new();
-    file_menu.append(Some("New"), Some("app.new"));
-    file_menu.append(Some("New Window"), Some("app.new_window"));
-    file_menu.append(Some("Open…"), Some("app.open"));
-    file_menu.append(Some("Save"), Some("app.save"));
-    file_menu.append(Some("Save As…"), Some("app.save_as"));
-    file_menu.append(Some("Print…"), Some("app.print"));
-    file_menu.append(Some("Exit"), Some("app.quit"));
-    root.append_submenu(Some("File"), &file_menu);
+// A panic in a callback takes the whole process down, so before it goes,
+// copy every unsaved document aside for the next start to offer back
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        save_recovery_copies();
+        default_hook(info);
+    }));
+}
 
-    // ----- Edit menu -----
-    let edit_menu = gio::Menu::new();
+// Runs inside the panic hook: only on the thread that owns GTK, and with
+// try_borrow, as the code that panicked may still hold a borrow
+fn save_recovery_copies() {
+    if !glib::MainContext::default().is_owner() {
+        return;
+    }
+    let Some(app) = gtk::gio::Application::default().and_downcast::<gtk::Application>() else {
+        return;
+    };
 
-    //
-    // Group 1: Undo / Redo
-    //
-    let group1 = gio::Menu::new();
-    group1.append(Some("Undo"), Some("app.undo"));
-    group1.append(Some("Redo"), Some("app.redo"));
-    edit_menu.append_section(None, &group1);
+    for window in app.windows() {
+        let Ok(window) = window.downcast::<gtk::ApplicationWindow>() else {
+            continue;
+        };
+        for page in tab_pages(&window) {
+            let Some(doc_state) = doc_state_of(&page) else {
+                continue;
+            };
+            let Ok(document) = doc_state.document.try_borrow() else {
+                continue;
+            };
+            if !document.is_dirty() {
+                continue;
+            }
+            if let Some(buffer) = source_buffer_of_page(&page) {
+                let (start, end) = buffer.bounds();
+                recovery::save(
+                    &doc_state.recovery_id,
+                    document.path(),
+                    &buffer.text(&start, &end, true),
+                );
+            }
+        }
+    }
+}
 
-    //
-    // Group 2: Cut / Copy / Paste / Delete
-    //
-    let group2 = gio::Menu::new();
-    group2.append(Some("Cut"), Some("app.cut"));
-    group2.append(Some("Copy"), Some("app.copy"));
-    group2.append(Some("Paste"), Some("app.paste"));
-    group2.append(Some("Delete"), Some("app.delete"));
-    edit_menu.append_section(None, &group2);
+// After a crash, offer to bring back the documents that had unsaved changes
+fn offer_recovery(window: &gtk::ApplicationWindow) {
+    let leftovers = recovery::leftovers();
+    if leftovers.is_empty() {
+        return;
+    }
 
-    //
-    // Group 3: Find / Find Next / Find Previous / Replace / Go To
-    //
-    let group3 = gio::Menu::new();
-    group3.append(Some("Find…"), Some("app.find"));
-    group3.append(Some("Find Next"), Some("app.find_next"));
-    group3.append(Some("Find Previous"), Some("app.find_prev"));
-    group3.append(Some("Replace…"), Some("app.replace"));
-    group3.append(Some("Go To…"), Some("app.goto"));
-    edit_menu.append_section(None, &group3);
+    let names: Vec<String> = leftovers
+        .iter()
+        .map(|recovered| match &recovered.path {
+            Some(path) => path.display().to_string(),
+            None => "Untitled".to_string(),
+        })
+        .collect();
 
-    //
-    // Group 4: Select All / Time/Date
-    //
-    let group4 = gio::Menu::new();
-    group4.append(Some("Select All"), Some("app.select_all"));
-    group4.append(Some("Time/Date"), Some("app.time_date"));
-    edit_menu.append_section(None, &group4);
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::None)
+        .text("Recover unsaved documents?")
+        .secondary_text(format!(
+            "rpad didn't close properly last time. Unsaved changes to these documents can be restored:\n\n{}",
+            names.join("\n")
+        ))
+        .build();
 
-    root.append_submenu(Some("Edit"), &edit_menu);
+    dialog.add_button("Discard", gtk::ResponseType::Reject);
+    dialog.add_button("Restore", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
 
-    // ----- View menu -----
-    let view_menu = gio::Menu::new();
+    let window_clone = window.clone();
+    dialog.connect_response(move |dialog, response| {
+        for recovered in &leftovers {
+            if response == gtk::ResponseType::Accept {
+                restore_recovered(&window_clone, recovered);
+            }
+            // Closing the dialog any other way keeps them for next time
+            if response == gtk::ResponseType::Accept || response == gtk::ResponseType::Reject {
+                recovery::discard(recovered);
+            }
+        }
+        dialog.close();
+    });
 
-    let zoom_menu = gio::Menu::new();
-    zoom_menu.append(Some("Zoom In"), Some("app.zoom_in"));
-    zoom_menu.append(Some("Zoom Out"), Some("app.zoom_out"));
-    zoom_menu.append(Some("Restore Default Zoom"), Some("app.zoom_reset"));
+    dialog.show();
+}
 
-    view_menu.append_submenu(Some("Zoom"), &zoom_menu);
-    view_menu.append(Some("Status Bar"), Some("app.status_bar"));
-    root.append_submenu(Some("View"), &view_menu);
+// A recovered document opens in a tab of its own with its unsaved changes
+// pending, under its old path if it had one
+fn restore_recovered(window: &gtk::ApplicationWindow, recovered: &recovery::Recovered) {
+    if tab_for_opening(window).is_none() {
+        return;
+    }
+    set_untitled_contents(window, &recovered.text);
 
-    // ----- Mode menu (your custom feature) -----
-    let mode_menu = gio::Menu::new();
-    mode_menu.append(Some("Plain Text"), Some("app.mode('plain')"));
-    mode_menu.append(Some("Markup"), Some("app.mode('markup')"));
-    mode_menu.append(Some("Sudo Mode"), Some("app.sudo_mode"));
-    root.append_submenu(Some("Mode"), &mode_menu);
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        doc_state.set_path(recovered.path.clone());
+        doc_state.set_dirty(true);
+        doc_state.save_recovery();
+    }
+    sync_ui_with_active_tab(window);
+}
 
-    // ----- Help menu -----
-    let help_menu = gio::Menu::new();
-    help_menu.append(Some("About rpad"), Some("app.about"));
-    root.append_submenu(Some("Help"), &help_menu);
+// Add a tab for an empty document set up from `config`, and bring it to the
+// front. `config.file` only becomes the tab's path; loading it is up to the
+// caller.
+fn add_tab(window: &gtk::ApplicationWindow, config: &AppConfig) {
+    let (Some(notebook), Some(status)) =
+        (notebook_from_window(window), status_bar_from_window(window))
+    else {
+        return;
+    };
 
-    gtk::PopoverMenuBar::from_model(Some(&root))
-}
+    // Main text area using GtkSourceView5
+    let buffer = sv::Buffer::new(None); // no language yet
+    apply_language_for_mode(&buffer, config.mode);
 
-fn get_text_buffer_from_window(window: &gtk::ApplicationWindow) -> Option<gtk::TextBuffer> {
-    unsafe {
-        if let Some(view_ptr) = window.data::<sv::View>("rpad-text-view") {
-            let view: &sv::View = view_ptr.as_ref();
-            return Some(view.buffer().upcast::<gtk::TextBuffer>());
+    // Folded markdown sections are hidden under this tag; leaving markdown
+    // shows everything again, since the fold markers go away with it
+    buffer.create_tag(Some(FOLDED_TAG), &[("invisible", &true)]);
+    buffer.connect_language_notify(|buffer| {
+        if !is_markdown_buffer(buffer) {
+            let (start, end) = buffer.bounds();
+            buffer.remove_tag_by_name(FOLDED_TAG, &start, &end);
         }
-    }
-    None
-}
-
-fn buffer_is_empty<P: IsA<gtk::TextBuffer>>(buffer: &P) -> bool {
-    let start = buffer.start_iter();
-    let end = buffer.end_iter();
-    buffer.text(&start, &end, true).is_empty()
-}
+    });
 
-fn save_buffer_to_path(
-    window: &gtk::ApplicationWindow,
-    path: &std::path::Path,
-) -> Result<(), String> {
-    let buffer = get_text_buffer_from_window(window)
-        .ok_or_else(|| "Could not find text buffer".to_string())?;
-    let (start, end) = buffer.bounds();
-    let text = buffer.text(&start, &end, false);
+    // Track the file path + mode on the page
+    let mut doc_state = DocumentState::new(config.file.clone(), config.mode, status);
+    let session = Session::load();
+    *doc_state.find_history.borrow_mut() = session.find_history;
+    *doc_state.replace_history.borrow_mut() = session.replace_history;
+    *doc_state.match_case.borrow_mut() = config.match_case;
+    *doc_state.preserve_case.borrow_mut() = config.preserve_case;
+    doc_state
+        .document
+        .borrow_mut()
+        .set_undo_limit(config.undo_limit);
+    doc_state.highlight_limit = config.highlight_limit;
+    *doc_state.style_scheme.borrow_mut() = config.style_scheme.clone();
+    *doc_state.accessible.borrow_mut() = config.accessible;
+    apply_style_scheme(&buffer, &doc_state);
+    doc_state.zoom_step = config.zoom_step;
+    *doc_state.auto_pair.borrow_mut() = config.auto_pair;
+    *doc_state.smart_quotes.borrow_mut() = config.smart_quotes;
+    *doc_state.cursor_in_title.borrow_mut() = config.cursor_in_title;
+    if config.vim {
+        *doc_state.vim.borrow_mut() = Some(vim::Vim::default());
+    }
+    *doc_state.show_whitespace.borrow_mut() = config.show_whitespace.clone();
+    *doc_state.line_numbers.borrow_mut() = config.line_numbers.clone();
+    *doc_state.right_margin.borrow_mut() = config.right_margin;
+    *doc_state.right_margin_column.borrow_mut() = config.right_margin_column;
+    *doc_state.indent_guides.borrow_mut() = config.indent_guides;
+    *doc_state.zoom.borrow_mut() = config.zoom;
+    update_zoom_css(&doc_state);
+
+    // Spell checking underlines misspelled words and puts suggestions in
+    // the right-click menu; it starts off unless the config turns it on
+    let checker = libspelling::Checker::default();
+    if let Some(language) = &config.spell_language {
+        if checker.provider().supports_language(language) {
+            checker.set_language(language);
+        }
+    }
+    let spell_adapter = libspelling::TextBufferAdapter::new(&buffer, &checker);
+    spell_adapter.set_enabled(config.spell_check);
+
+    // The page is a Paned so View → Split can add a second view of the
+    // same buffer below this one
+    let page = gtk::Paned::new(gtk::Orientation::Vertical);
+    let (text_view, pane) = new_editor_pane(&page, &buffer, &doc_state, &spell_adapter);
+
+    // Overview of the whole document beside the editor (View → Minimap)
+    let minimap = sv::Map::new();
+    minimap.set_view(&text_view);
+    minimap.set_visible(config.minimap);
+
+    let editor_row = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    editor_row.append(&pane);
+    editor_row.append(&minimap);
+    page.set_start_child(Some(&editor_row));
+
+    // Tab header: name plus a close button
+    let tab_header = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    tab_header.append(&doc_state.tab_label);
+    let close_button = gtk::Button::from_icon_name("window-close-symbolic");
+    close_button.set_has_frame(false);
+    close_button.set_tooltip_text(Some("Close Tab"));
+    tab_header.append(&close_button);
+    {
+        let window_clone = window.clone();
+        let page_weak = page.downgrade();
+        close_button.connect_clicked(move |_| {
+            if let Some(page) = page_weak.upgrade() {
+                close_tab(&window_clone, page.upcast_ref());
+            }
+        });
+    }
 
-    unsafe {
-        if let Some(doc_state_ptr) = window.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
+    // Register the state and editor widgets under the page so helpers can
+    // find them. The handlers below hold the page weakly: it owns them
+    // (through the view), so a strong reference would keep a closed tab alive.
+    attach_tab(
+        page.upcast_ref(),
+        doc_state,
+        TabWidgets {
+            text_view: text_view.clone(),
+            spell_adapter,
+            minimap,
+        },
+    );
 
-            // Check Sudo Mode
-            let mut use_sudo = false;
-            let mut sudo_pass = None;
+    // Track edits for undo/redo *and* dirty flag
+    {
+        let window_clone_1 = window.clone();
+        let page_weak_1 = page.downgrade();
+        buffer.connect_changed(move |buf| {
+            let Some(page) = page_weak_1.upgrade() else {
+                return;
+            };
+            if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                if *doc_state.is_programmatic.borrow() {
+                    return;
+                }
 
-            if let Some(pass) = doc_state.sudo_password.borrow().clone() {
-                // Check expiry
-                let expired = if let Some(expiry) = *doc_state.sudo_expiry.borrow() {
-                    std::time::Instant::now() > expiry
-                } else {
-                    true
-                };
+                let (start, end) = buf.bounds();
+                let text = buf.text(&start, &end, true).to_string();
+
+                if doc_state.update_document(|document| {
+                    document.record_edit(text, std::time::Instant::now())
+                }) {
+                    remember_last_edit(buf.upcast_ref(), doc_state);
+                    schedule_count_update(&window_clone_1, doc_state);
+                    update_undo_redo_actions(&window_clone_1, doc_state);
+                    update_save_action(&window_clone_1, doc_state);
+                }
+            }
+        });
 
-                if expired {
-                    // Re-prompt
-                    if let Some(new_pass) = prompt_for_password(window) {
-                        if validate_sudo_password(&new_pass).is_ok() {
-                            *doc_state.sudo_password.borrow_mut() = Some(new_pass.clone());
-                            *doc_state.sudo_expiry.borrow_mut() = Some(
-                                std::time::Instant::now() + std::time::Duration::from_secs(300),
-                            );
-                            use_sudo = true;
-                            sudo_pass = Some(new_pass);
-                        } else {
-                            return Err("Sudo re-authentication failed".to_string());
+        // 2) Track cursor movement for Line/Col
+        let page_weak_2 = page.downgrade();
+        buffer.connect_mark_set(move |buf, _iter, mark| {
+            let Some(page) = page_weak_2.upgrade() else {
+                return;
+            };
+            if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                // Only update if the cursor or the selection's other end moved
+                if matches!(mark.name().as_deref(), Some("insert" | "selection_bound")) {
+                    update_cursor(doc_state, buf.upcast_ref());
+                    // Relative numbers count from the cursor line
+                    if *doc_state.line_numbers.borrow() == "relative" {
+                        for text_view in views_of_page(page.upcast_ref()) {
+                            if let Some(renderer) = relative_line_numbers_of(&text_view) {
+                                renderer.queue_draw();
+                            }
                         }
-                    } else {
-                        return Err("Sudo re-authentication cancelled".to_string());
                     }
-                } else {
-                    use_sudo = true;
-                    sudo_pass = Some(pass);
                 }
             }
+        });
 
-            if use_sudo {
-                if let Some(pass) = sudo_pass {
-                    perform_sudo_save(path, &text, &pass)?;
-                } else {
-                    return Err("Sudo password missing logic error".to_string());
+        // 3) Cut/Copy/Delete only make sense with a selection
+        let window_clone_3 = window.clone();
+        buffer.connect_has_selection_notify(move |buf| {
+            update_selection_actions(&window_clone_3, buf.has_selection());
+        });
+
+        // 4) Smart Quotes swaps in typographic characters; otherwise
+        // auto-pairing replaces a typed opener with the whole pair
+        let page_weak_4 = page.downgrade();
+        buffer.connect_insert_text(move |buf, location, text| {
+            let Some(page) = page_weak_4.upgrade() else {
+                return;
+            };
+            if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                if *doc_state.is_programmatic.borrow() {
+                    return;
                 }
-            } else {
-                // Normal Save
-                if let Err(e) = fs::write(path, &text) {
-                    return Err(format!("Failed to write file: {}", e));
+                let handled = (*doc_state.smart_quotes.borrow()
+                    && smart_quotes_insert(buf, doc_state, location, text))
+                    || (*doc_state.auto_pair.borrow()
+                        && auto_pair_insert(buf.upcast_ref(), doc_state, location, text));
+                if handled {
+                    buf.stop_signal_emission_by_name("insert-text");
                 }
             }
+        });
 
-            // Mark as not dirty only on success
-            doc_state.set_dirty(false);
-            *doc_state.last_text.borrow_mut() = text.to_string();
-
-            // Reset title (preserving [SUDO] tag if active)
-            let base_title = format!("rpad - {}", path.display());
-            let suffix = if doc_state.sudo_password.borrow().is_some() {
-                " [SUDO]"
-            } else {
-                ""
+        // 5) In Markup mode, Enter carries a list item or quote on
+        let page_weak_5 = page.downgrade();
+        buffer.connect_insert_text(move |buf, location, text| {
+            let Some(page) = page_weak_5.upgrade() else {
+                return;
             };
+            if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                if text != "\n"
+                    || *doc_state.is_programmatic.borrow()
+                    || doc_state.mode() != Mode::Markup
+                {
+                    return;
+                }
+                if continue_markdown_list(buf.upcast_ref(), location) {
+                    buf.stop_signal_emission_by_name("insert-text");
+                }
+            }
+        });
+    }
 
-            // Also append mode
-            let mode_suffix = match doc_state.mode() {
-                Mode::Plain => " [Plain]",
-                Mode::Markup => " [Markdown]",
-            };
+    let index = notebook.append_page(&page, Some(&tab_header));
+    notebook.set_tab_reorderable(&page, true);
+    notebook.set_current_page(Some(index));
+    text_view.grab_focus();
+}
 
-            window.set_title(Some(&format!("{}{}{}", base_title, suffix, mode_suffix)));
+// An editor view on `buffer` for one half of a tab's `page`, wrapped in
+// its scroller and an overlay for the indent guides. Zoom, spelling and
+// key handling come from the tab, so both halves of a split behave the
+// same.
+fn new_editor_pane(
+    page: &gtk::Paned,
+    buffer: &sv::Buffer,
+    doc_state: &DocumentState,
+    spell_adapter: &libspelling::TextBufferAdapter,
+) -> (sv::View, gtk::Overlay) {
+    let text_view = sv::View::with_buffer(buffer);
 
-            return Ok(());
-        }
-    }
+    text_view.set_monospace(true);
+    text_view.set_wrap_mode(gtk::WrapMode::WordChar);
 
-    // Fallback if doc_state missing (shouldn't happen)
-    if let Err(e) = fs::write(path, text.as_str()) {
-        Err(format!("Failed to write file: {}", e))
-    } else {
-        Ok(())
+    // Padding inside the editor
+    text_view.set_left_margin(12);
+    text_view.set_right_margin(12);
+    text_view.set_top_margin(8);
+    text_view.set_bottom_margin(8);
+
+    text_view.style_context().add_provider(
+        &doc_state.css_provider,
+        gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+    );
+
+    apply_show_whitespace(&text_view, &doc_state.show_whitespace.borrow());
+    text_view.set_highlight_current_line(*doc_state.accessible.borrow());
+    add_fold_gutter(&text_view);
+    add_relative_line_numbers(&text_view);
+    apply_line_numbers(&text_view, &doc_state.line_numbers.borrow());
+
+    // Bookmarks appear in the line marks gutter
+    let bookmark_attributes = sv::MarkAttributes::new();
+    bookmark_attributes.set_icon_name("user-bookmarks-symbolic");
+    text_view.set_mark_attributes(BOOKMARK_CATEGORY, &bookmark_attributes, 0);
+    text_view.set_show_line_marks(true);
+    apply_right_margin(&text_view, doc_state);
+    apply_indentation(&text_view, *doc_state.indent.borrow());
+
+    text_view.insert_action_group("spelling", Some(spell_adapter));
+
+    // rpad's own entries in the right-click menu
+    text_view.set_extra_menu(Some(&build_context_menu(&spell_adapter.menu_model())));
+
+    // Ctrl+scroll over the editor zooms instead of scrolling
+    {
+        let scroll = gtk::EventControllerScroll::new(gtk::EventControllerScrollFlags::VERTICAL);
+        let page_weak = page.downgrade();
+        scroll.connect_scroll(move |controller, _dx, dy| {
+            let ctrl_held = controller
+                .current_event_state()
+                .contains(gtk::gdk::ModifierType::CONTROL_MASK);
+            if !ctrl_held || dy == 0.0 {
+                return glib::Propagation::Proceed;
+            }
+
+            let Some(page) = page_weak.upgrade() else {
+                return glib::Propagation::Proceed;
+            };
+            if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                let current = *doc_state.zoom.borrow();
+                if dy < 0.0 {
+                    set_zoom(doc_state, current + doc_state.zoom_step);
+                } else {
+                    set_zoom(doc_state, current.saturating_sub(doc_state.zoom_step));
+                }
+            }
+            glib::Propagation::Stop
+        });
+        text_view.add_controller(scroll);
     }
-}
 
-fn load_file_into_window(
-    window: &gtk::ApplicationWindow,
-    path: &Path,
-) -> Result<(), std::io::Error> {
-    let contents = fs::read_to_string(path)?;
+    // Smart Home: first press goes to the line's first non-blank character,
+    // the next one to column 0. Runs in the capture phase so it comes before
+    // the view's own Home binding.
+    {
+        let keys = gtk::EventControllerKey::new();
+        keys.set_propagation_phase(gtk::PropagationPhase::Capture);
+        keys.connect_key_pressed(move |controller, key, _, modifiers| {
+            let is_home = key == gtk::gdk::Key::Home || key == gtk::gdk::Key::KP_Home;
+            let other_modifiers = modifiers.intersects(
+                gtk::gdk::ModifierType::CONTROL_MASK | gtk::gdk::ModifierType::ALT_MASK,
+            );
+            if !is_home || other_modifiers {
+                return glib::Propagation::Proceed;
+            }
 
-    unsafe {
-        if let Some(doc_state_ptr) = window.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
+            let Some(text_view) = controller
+                .widget()
+                .and_then(|widget| widget.downcast::<sv::View>().ok())
+            else {
+                return glib::Propagation::Proceed;
+            };
+            let extend = modifiers.contains(gtk::gdk::ModifierType::SHIFT_MASK);
+            smart_home(&text_view, extend);
+            glib::Propagation::Stop
+        });
+        text_view.add_controller(keys);
+    }
 
-            *doc_state.is_programmatic.borrow_mut() = true;
+    // Vim-style modal editing: outside Insert mode keys are commands, not
+    // text. Capture phase, so they never reach the view.
+    {
+        let keys = gtk::EventControllerKey::new();
+        keys.set_propagation_phase(gtk::PropagationPhase::Capture);
+        let page_weak = page.downgrade();
+        keys.connect_key_pressed(move |controller, key, _, modifiers| {
+            let (Some(page), Some(text_view)) = (
+                page_weak.upgrade(),
+                controller
+                    .widget()
+                    .and_then(|widget| widget.downcast::<sv::View>().ok()),
+            ) else {
+                return glib::Propagation::Proceed;
+            };
+            let Some(doc_state) = doc_state_of(&page) else {
+                return glib::Propagation::Proceed;
+            };
 
-            if let Some(buffer) = get_text_buffer_from_window(window) {
-                buffer.set_text(&contents);
+            if vim_key(page.upcast_ref(), &text_view, &doc_state, key, modifiers) {
+                glib::Propagation::Stop
+            } else {
+                glib::Propagation::Proceed
             }
+        });
+        text_view.add_controller(keys);
+    }
+    text_view.set_overwrite(
+        doc_state
+            .vim
+            .borrow()
+            .as_ref()
+            .is_some_and(|vim| vim.mode != vim::VimMode::Insert),
+    );
 
-            // reset undo/redo and last_text for this new file
-            doc_state.undo_stack.borrow_mut().clear();
-            doc_state.redo_stack.borrow_mut().clear();
-            *doc_state.last_text.borrow_mut() = contents.clone();
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&text_view)
+        .hexpand(true)
+        .vexpand(true)
+        .build();
 
-            doc_state.set_path(Some(path.to_path_buf()));
-            doc_state.set_dirty(false);
-            *doc_state.last_text.borrow_mut() = contents.clone();
+    scrolled.set_margin_top(4);
+    scrolled.set_margin_bottom(4);
+    scrolled.set_margin_start(4);
+    scrolled.set_margin_end(4);
 
-            // Reset Sudo
-            *doc_state.sudo_password.borrow_mut() = None;
-            *doc_state.sudo_expiry.borrow_mut() = None;
+    let overlay = gtk::Overlay::new();
+    overlay.set_child(Some(&scrolled));
+    add_indent_guides(&overlay, &scrolled, &text_view);
+    apply_indent_guides(&text_view, *doc_state.indent_guides.borrow());
 
-            // Update UI state
-            set_sudo_state(window, false);
+    (text_view, overlay)
+}
 
-            window.set_title(Some(&format!("rpad - {}", path.display())));
+// Show or hide the second view in the current tab. It shares the first
+// one's buffer, so edits (and the cursor and selection) appear in both.
+fn set_split(window: &gtk::ApplicationWindow, split: bool) {
+    let Some(page) = current_page(window).and_then(|page| page.downcast::<gtk::Paned>().ok())
+    else {
+        return;
+    };
 
-            *doc_state.is_programmatic.borrow_mut() = false;
+    if !split {
+        page.set_end_child(None::<&gtk::Widget>);
+        if let Some(text_view) = get_text_view_from_window(window) {
+            text_view.grab_focus();
         }
+        return;
     }
 
-    Ok(())
+    if page.end_child().is_some() {
+        return;
+    }
+    let (Some(buffer), Some(spell_adapter)) = (
+        get_source_buffer_from_window(window),
+        get_spell_adapter_from_window(window),
+    ) else {
+        return;
+    };
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        let (_, pane) = new_editor_pane(&page, &buffer, doc_state, &spell_adapter);
+        page.set_end_child(Some(&pane));
+        page.set_position(page.height() / 2);
+    }
 }
 
-fn open_with_dialog(window: &gtk::ApplicationWindow) {
-    use gtk::{FileChooserAction, FileFilter, ResponseType};
+// Every editor view in a tab: the main one plus the split one, if shown
+fn views_of_page(page: &gtk::Widget) -> Vec<sv::View> {
+    let mut views: Vec<sv::View> = tab_widgets(page)
+        .map(|widgets| widgets.text_view)
+        .into_iter()
+        .collect();
+    let split_view = page
+        .downcast_ref::<gtk::Paned>()
+        .and_then(|paned| paned.end_child())
+        .and_then(|child| child.downcast::<gtk::Overlay>().ok())
+        .and_then(|overlay| overlay.child())
+        .and_then(|child| child.downcast::<gtk::ScrolledWindow>().ok())
+        .and_then(|scrolled| scrolled.child())
+        .and_then(|child| child.downcast::<sv::View>().ok());
+    views.extend(split_view);
+    views
+}
 
-    let dialog = gtk::FileChooserDialog::new(
-        Some("Open File"),
-        Some(window),
-        FileChooserAction::Open,
-        &[
-            ("_Cancel", ResponseType::Cancel),
-            ("_Open", ResponseType::Accept),
-        ],
-    );
+fn is_split(window: &gtk::ApplicationWindow) -> bool {
+    current_page(window)
+        .and_then(|page| page.downcast::<gtk::Paned>().ok())
+        .is_some_and(|page| page.end_child().is_some())
+}
 
-    let text_filter = FileFilter::new();
-    text_filter.set_name(Some("Text Files (*.txt, *.md, *.markdown)"));
-    text_filter.add_pattern("*.txt");
-    text_filter.add_pattern("*.md");
-    text_filter.add_pattern("*.markdown");
-    dialog.add_filter(&text_filter);
+// Settings for a new tab, carried over from the current one
+fn active_tab_config(window: &gtk::ApplicationWindow) -> Option<AppConfig> {
+    let spell_adapter = get_spell_adapter_from_window(window)?;
+    let doc_state = active_doc_state(window)?;
+    let config = AppConfig {
+        mode: doc_state.mode(),
+        file: None,
+        line: None,
+        stdin_text: None,
+        highlight_limit: doc_state.highlight_limit,
+        zoom: *doc_state.zoom.borrow(),
+        zoom_step: doc_state.zoom_step,
+        spell_check: spell_adapter.is_enabled(),
+        spell_language: Some(spell_adapter.language().to_string()),
+        auto_pair: *doc_state.auto_pair.borrow(),
+        smart_quotes: *doc_state.smart_quotes.borrow(),
+        cursor_in_title: *doc_state.cursor_in_title.borrow(),
+        style_scheme: doc_state.style_scheme.borrow().clone(),
+        show_whitespace: doc_state.show_whitespace.borrow().clone(),
+        line_numbers: doc_state.line_numbers.borrow().clone(),
+        right_margin: *doc_state.right_margin.borrow(),
+        right_margin_column: *doc_state.right_margin_column.borrow(),
+        indent_guides: *doc_state.indent_guides.borrow(),
+        // Already applied to the display by the first window
+        cursor_blink: None,
+        match_case: *doc_state.match_case.borrow(),
+        preserve_case: *doc_state.preserve_case.borrow(),
+        undo_limit: doc_state.document.borrow().undo_limit(),
+        // Forced only for the file opened at startup, not for new tabs
+        encoding: None,
+        eol: None,
+        follow: false,
+        vim: doc_state.vim.borrow().is_some(),
+        accessible: *doc_state.accessible.borrow(),
+        minimap: minimap_of_page(&current_page(window)?).is_some_and(|map| map.is_visible()),
+    };
+    Some(config)
+}
 
-    let all_filter = FileFilter::new();
-    all_filter.set_name(Some("All Files"));
-    all_filter.add_pattern("*");
-    dialog.add_filter(&all_filter);
+// Close a tab, offering to save it first. Closing the last tab closes the
+// window.
+fn close_tab(window: &gtk::ApplicationWindow, page: &gtk::Widget) {
+    if !select_tab(window, page) {
+        return;
+    }
 
     let window_clone = window.clone();
-    dialog.connect_response(move |dialog, response| {
-        if response == ResponseType::Accept {
-            if let Some(file) = dialog.file() {
-                if let Some(path) = file.path() {
-                    if let Err(err) = load_file_into_window(&window_clone, path.as_ref()) {
-                        eprintln!("Error opening file: {err}");
-                    }
+    let page = page.clone();
+    confirm_discard(window, move |discarded| {
+        let Some(notebook) = notebook_from_window(&window_clone) else {
+            return;
+        };
+        if notebook.n_pages() <= 1 {
+            // The only tab, so nothing else can stop the window closing
+            if discarded {
+                if let Some(doc_state) = doc_state_of(&page) {
+                    doc_state.set_dirty(false);
                 }
             }
+            window_clone.close();
+            return;
         }
 
-        dialog.close();
+        if let Some(doc_state) = doc_state_of(&page).as_deref() {
+            remember_zoom(doc_state);
+            doc_state.forget_recovery();
+        }
+        notebook.remove_page(notebook.page_num(&page));
     });
-
-    dialog.show();
 }
 
-fn register_actions(app: &gtk::Application, window: &gtk::ApplicationWindow, text_view: &sv::View) {
-    use gtk::gio::SimpleAction;
+// Bring the window-level UI (title, status bar, action states) in line with
+// the tab that just came to the front
+fn sync_ui_with_active_tab(window: &gtk::ApplicationWindow) {
+    let Some(buffer) = get_text_buffer_from_window(window) else {
+        return;
+    };
 
-    // ----- File actions -----
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        let (mode_label, mode_str) = match doc_state.mode() {
+            Mode::Plain => ("Plain Text", "plain"),
+            Mode::Markup => ("Markdown", "markup"),
+        };
+        doc_state.status.label_mode.set_text(mode_label);
+        doc_state
+            .status
+            .highlight_button
+            .set_visible(*doc_state.highlight_suppressed.borrow());
+        update_cursor(doc_state, &buffer);
+        update_counts(doc_state, &buffer);
+
+        update_undo_redo_actions(window, doc_state);
+        update_save_action(window, doc_state);
+        update_selection_actions(window, buffer.has_selection());
+        update_markdown_actions(window, doc_state);
+        set_action_state(window, "mode", &mode_str.to_variant());
+        set_action_state(window, "split_view", &is_split(window).to_variant());
+        set_action_state(
+            window,
+            "vim",
+            &doc_state.vim.borrow().is_some().to_variant(),
+        );
+        update_vim_status(doc_state);
+        update_indent_label(doc_state);
+        set_action_state(window, "bom", &doc_state.bom.borrow().to_variant());
+
+        // Also sets the title
+        set_sudo_state(window, doc_state.sudo_active());
+    }
+}
 
-    // Quit / Exit: go through window.close() so dirty-check runs
-    let quit = SimpleAction::new("quit", None);
-    let window_for_quit = window.clone();
-    quit.connect_activate(move |_, _| {
-        window_for_quit.close();
-    });
-    app.add_action(&quit);
+// Look for the app icon next to the executable first, then in the XDG data dirs
+fn register_icon_search_paths(icon_theme: &gtk::IconTheme) {
+    if let Ok(exe_path) = std::env::current_exe() {
+        if let Some(exe_dir) = exe_path.parent() {
+            icon_theme.add_search_path(exe_dir.join("assets"));
+            icon_theme.add_search_path(exe_dir.join("../share/icons"));
+        }
+    }
 
-    // New (clear current document)
-    let new_doc = SimpleAction::new("new", None);
-    let window_clone = window.clone();
-    new_doc.connect_activate(move |_, _| unsafe {
-        if let Some(text_buffer) = get_text_buffer_from_window(&window_clone) {
-            text_buffer.set_text("");
+    // Source tree, so `cargo run` finds the icon from any directory
+    #[cfg(debug_assertions)]
+    icon_theme.add_search_path(concat!(env!("CARGO_MANIFEST_DIR"), "/assets"));
 
-            if let Some(doc_state_ptr) = window_clone.data::<DocumentState>("rpad-doc-state") {
-                let doc_state: &DocumentState = doc_state_ptr.as_ref();
-                doc_state.set_path(None);
-                doc_state.set_dirty(false);
-                *doc_state.last_text.borrow_mut() = String::new();
-                // Reset Sudo
-                *doc_state.sudo_password.borrow_mut() = None;
-                *doc_state.sudo_expiry.borrow_mut() = None;
+    // $XDG_DATA_HOME/icons, then $XDG_DATA_DIRS (/usr/local/share, /usr/share)
+    icon_theme.add_search_path(glib::user_data_dir().join("icons"));
+    for data_dir in glib::system_data_dirs() {
+        icon_theme.add_search_path(data_dir.join("icons"));
+    }
+}
 
-                // Update UI state
-                set_sudo_state(&window_clone, false);
+fn build_menubar() -> gtk::PopoverMenuBar {
+    use gtk::gio;
 
-                window_clone.set_title(Some("rpad - Untitled"));
+    // Top-level menu model
+    let root = gio::Menu::new();
 
-                // Also clear undo/redo stacks
-                doc_state.undo_stack.borrow_mut().clear();
-                doc_state.redo_stack.borrow_mut().clear();
-                *doc_state.is_programmatic.borrow_mut() = false;
-            }
-        }
-    });
-    app.add_action(&new_doc);
-
-    // New Window – spawn a new rpad process
-    let new_window = SimpleAction::new("new_window", None);
-    new_window.connect_activate(|_, _| {
-        // Try to get the current executable path
-        match std::env::current_exe() {
-            Ok(exe_path) => {
-                if let Err(err) = Command::new(exe_path).spawn() {
-                    eprintln!("Failed to open new window: {err}");
-                }
-            }
-            Err(err) => {
-                eprintln!("Could not determine current executable for New Window: {err}");
-            }
-        }
-    });
-    app.add_action(&new_window);
+    // ----- File menu -----
+    let file_menu = gio::Menu::new();
+    file_menu.append(Some("New"), Some("win.new"));
+    file_menu.append(Some("New Tab"), Some("win.new_tab"));
+    file_menu.append(Some("New Window"), Some("win.new_window"));
+    file_menu.append(Some("Open…"), Some("win.open"));
+    file_menu.append(Some("Save"), Some("win.save"));
+    file_menu.append(Some("Save As…"), Some("win.save_as"));
+    file_menu.append(Some("Save All"), Some("app.save_all"));
+    file_menu.append(
+        Some("Open Containing Folder"),
+        Some("win.open_containing_folder"),
+    );
+    file_menu.append(Some("Copy File Path"), Some("win.copy_path"));
+    file_menu.append(Some("Byte Order Mark"), Some("win.bom"));
+    file_menu.append(Some("Print…"), Some("win.print"));
+    file_menu.append(Some("Close Tab"), Some("win.close_tab"));
+    file_menu.append(Some("Exit"), Some("app.quit"));
+    root.append_submenu(Some("File"), &file_menu);
 
-    // Save
-    let save = SimpleAction::new("save", None);
-    let window_clone = window.clone();
-    save.connect_activate(move |_, _| {
-        unsafe {
-            if let Some(doc_state_ptr) = window_clone.data::<DocumentState>("rpad-doc-state") {
-                let doc_state: &DocumentState = doc_state_ptr.as_ref();
-                if let Some(path) = doc_state.path() {
-                    if let Err(err) = save_buffer_to_path(&window_clone, &path) {
-                        eprintln!("Error saving file: {err}");
-                    }
-                } else {
-                    // No path yet → behave like "Save As"
-                    save_as_with_dialog(&window_clone);
-                }
-            } else {
-                // No state stored? Fallback to "Save As"
-                save_as_with_dialog(&window_clone);
-            }
-        }
-    });
-    app.add_action(&save);
+    // ----- Edit menu -----
+    let edit_menu = gio::Menu::new();
 
-    // Save As…
-    let save_as = SimpleAction::new("save_as", None);
-    let window_clone = window.clone();
-    save_as.connect_activate(move |_, _| {
-        save_as_with_dialog(&window_clone);
-    });
-    app.add_action(&save_as);
+    //
+    // Group 1: Undo / Redo
+    //
+    let group1 = gio::Menu::new();
+    group1.append(Some("Undo"), Some("win.undo"));
+    group1.append(Some("Redo"), Some("win.redo"));
+    edit_menu.append_section(None, &group1);
 
-    // Open
-    let open = SimpleAction::new("open", None);
-    let window_clone = window.clone();
-    open.connect_activate(move |_, _| {
-        open_with_dialog(&window_clone);
-    });
-    app.add_action(&open);
+    //
+    // Group 2: Cut / Copy / Paste / Delete
+    //
+    let group2 = gio::Menu::new();
+    group2.append(Some("Cut"), Some("win.cut"));
+    group2.append(Some("Copy"), Some("win.copy"));
+    group2.append(Some("Paste"), Some("win.paste"));
+    let paste_special_menu = gio::Menu::new();
+    paste_special_menu.append(Some("Paste as Plain Text"), Some("win.paste_plain"));
+    paste_special_menu.append(Some("Paste as Quote"), Some("win.paste_quote"));
+    group2.append_submenu(Some("Paste Special"), &paste_special_menu);
+    group2.append(Some("Delete"), Some("win.delete"));
+    edit_menu.append_section(None, &group2);
 
-    // Print
-    let print = SimpleAction::new("print", None);
-    {
-        let window_clone = window.clone();
-        let text_view_clone = text_view.clone();
-        print.connect_activate(move |_, _| {
-            let op = gtk::PrintOperation::new();
-            op.set_job_name("rpad-print-job");
+    //
+    // Group 3: Find / Find Next / Find Previous / Replace / Go To
+    //
+    let group3 = gio::Menu::new();
+    group3.append(Some("Find…"), Some("win.find"));
+    group3.append(Some("Find Next"), Some("win.find_next"));
+    group3.append(Some("Find Previous"), Some("win.find_prev"));
+    group3.append(Some("Replace…"), Some("win.replace"));
+    group3.append(Some("Go To…"), Some("win.goto"));
+    group3.append(Some("Go to Last Edit"), Some("win.last_edit"));
+    group3.append(Some("Go to Matching Bracket"), Some("win.matching_bracket"));
+    edit_menu.append_section(None, &group3);
 
-            let compositor = sv::PrintCompositor::from_view(&text_view_clone);
+    let bookmarks_section = gio::Menu::new();
+    bookmarks_section.append(Some("Toggle Bookmark"), Some("win.toggle_bookmark"));
+    bookmarks_section.append(Some("Next Bookmark"), Some("win.next_bookmark"));
+    bookmarks_section.append(Some("Previous Bookmark"), Some("win.previous_bookmark"));
+    edit_menu.append_section(None, &bookmarks_section);
 
-            let compositor_clone = compositor.clone();
-            op.connect_begin_print(move |op, context| {
-                let compositor = compositor_clone.clone();
-                while !compositor.paginate(context) {
-                    // spin loop or rely on internal iterations?
-                    // Documentation suggests paginate() does a chunk of work.
-                    // Usually we need to keep calling it until TRUE.
-                }
-                op.set_n_pages(compositor.n_pages());
-            });
+    //
+    // Group 4: Select All / Time/Date / Change Case
+    //
+    let group4 = gio::Menu::new();
+    group4.append(Some("Select All"), Some("win.select_all"));
+    group4.append(Some("Clear"), Some("win.clear"));
+    group4.append(Some("Time/Date"), Some("win.time_date"));
+    group4.append(Some("Insert File…"), Some("win.insert_file"));
+    group4.append_submenu(Some("Change Case"), &build_change_case_menu());
+    group4.append_submenu(Some("Lines"), &build_lines_menu());
+    group4.append_submenu(Some("Transpose"), &build_transpose_menu());
+    group4.append_submenu(Some("Markdown"), &build_markdown_menu());
+    group4.append_submenu(
+        Some("Convert Indentation"),
+        &build_convert_indentation_menu(),
+    );
+    edit_menu.append_section(None, &group4);
 
-            let compositor_clone = compositor.clone();
-            op.connect_draw_page(move |_op, context, page_nr| {
-                compositor_clone.draw_page(context, page_nr);
-            });
+    let group5 = gio::Menu::new();
+    group5.append(
+        Some("Auto-Close Brackets and Quotes"),
+        Some("win.auto_pair"),
+    );
+    group5.append(Some("Smart Quotes"), Some("win.smart_quotes"));
+    group5.append(Some("Vim Mode"), Some("win.vim"));
+    edit_menu.append_section(None, &group5);
 
-            let res = op.run(gtk::PrintOperationAction::PrintDialog, Some(&window_clone));
-            if let Err(e) = res {
-                eprintln!("Error printing: {}", e);
-            }
-        });
-    }
-    app.add_action(&print);
+    root.append_submenu(Some("Edit"), &edit_menu);
 
-    // ----- Edit actions (stubs) -----
-    // Undo
-    let undo = SimpleAction::new("undo", None);
-    let window_clone = window.clone();
-    undo.connect_activate(move |_, _| {
-        unsafe {
-            if let Some(doc_state_ptr) = window_clone.data::<DocumentState>("rpad-doc-state") {
-                let doc_state: &DocumentState = doc_state_ptr.as_ref();
+    // ----- View menu -----
+    let view_menu = gio::Menu::new();
 
-                let mut undo_stack = doc_state.undo_stack.borrow_mut();
-                if let Some(prev_text) = undo_stack.pop() {
-                    let current_text = doc_state.last_text.borrow().clone();
+    let zoom_menu = gio::Menu::new();
+    zoom_menu.append(Some("Zoom In"), Some("win.zoom_in"));
+    zoom_menu.append(Some("Zoom Out"), Some("win.zoom_out"));
+    zoom_menu.append(Some("Restore Default Zoom"), Some("win.zoom_reset"));
+    zoom_menu.append(Some("Zoom to…"), Some("win.zoom_to"));
 
-                    // Push current text to redo stack
-                    doc_state.redo_stack.borrow_mut().push(current_text.clone());
+    view_menu.append_submenu(Some("Zoom"), &zoom_menu);
+    view_menu.append(Some("Status Bar"), Some("win.status_bar"));
+    view_menu.append(Some("Minimap"), Some("win.minimap"));
+    view_menu.append(Some("Split"), Some("win.split_view"));
+    view_menu.append(Some("Blinking Cursor"), Some("win.cursor_blink"));
+    view_menu.append(
+        Some("Cursor Position in Title"),
+        Some("win.cursor_in_title"),
+    );
+    view_menu.append(Some("Accessibility Mode"), Some("win.accessible"));
 
-                    // Apply previous text without recording as a new undo entry
-                    *doc_state.is_programmatic.borrow_mut() = true;
-                    if let Some(buffer) = get_text_buffer_from_window(&window_clone) {
-                        buffer.set_text(&prev_text);
-                    }
-                    *doc_state.last_text.borrow_mut() = prev_text;
-                    *doc_state.is_programmatic.borrow_mut() = false;
-                }
-            }
-        }
-    });
-    app.add_action(&undo);
+    let margin_section = gio::Menu::new();
+    margin_section.append(Some("Right Margin"), Some("win.right_margin"));
+    margin_section.append(
+        Some("Right Margin Column…"),
+        Some("win.right_margin_column"),
+    );
 
-    // Redo
-    let redo = SimpleAction::new("redo", None);
-    let window_clone = window.clone();
-    redo.connect_activate(move |_, _| {
-        unsafe {
-            if let Some(doc_state_ptr) = window_clone.data::<DocumentState>("rpad-doc-state") {
-                let doc_state: &DocumentState = doc_state_ptr.as_ref();
+    let whitespace_menu = gio::Menu::new();
+    whitespace_menu.append(Some("Off"), Some("win.show_whitespace('none')"));
+    whitespace_menu.append(Some("All"), Some("win.show_whitespace('all')"));
+    whitespace_menu.append(Some("Leading"), Some("win.show_whitespace('leading')"));
+    whitespace_menu.append(Some("Trailing"), Some("win.show_whitespace('trailing')"));
+    view_menu.append_submenu(Some("Show Whitespace"), &whitespace_menu);
+
+    let line_numbers_menu = gio::Menu::new();
+    line_numbers_menu.append(Some("Off"), Some("win.line_numbers('none')"));
+    line_numbers_menu.append(Some("Absolute"), Some("win.line_numbers('absolute')"));
+    line_numbers_menu.append(Some("Relative"), Some("win.line_numbers('relative')"));
+    view_menu.append_submenu(Some("Line Numbers"), &line_numbers_menu);
+    margin_section.append(Some("Indent Guides"), Some("win.indent_guides"));
+    view_menu.append_section(None, &margin_section);
+
+    let spell_section = gio::Menu::new();
+    spell_section.append(Some("Spell Check"), Some("win.spell_check"));
+    spell_section.append_submenu(Some("Spell Check Language"), &build_spell_language_menu());
+    view_menu.append_section(None, &spell_section);
+    root.append_submenu(Some("View"), &view_menu);
 
-                let mut redo_stack = doc_state.redo_stack.borrow_mut();
-                if let Some(next_text) = redo_stack.pop() {
-                    let current_text = doc_state.last_text.borrow().clone();
+    // ----- Mode menu (your custom feature) -----
+    let mode_menu = build_mode_menu();
+    mode_menu.append(Some("Sudo Mode"), Some("win.sudo_mode"));
+    root.append_submenu(Some("Mode"), &mode_menu);
 
-                    // Push current text back to undo stack
-                    doc_state.undo_stack.borrow_mut().push(current_text.clone());
+    // ----- Tools menu -----
+    let tools_menu = gio::Menu::new();
+    tools_menu.append(Some("Show Changes…"), Some("win.show_changes"));
+    tools_menu.append(Some("Tasks…"), Some("win.tasks"));
+    tools_menu.append(Some("Statistics…"), Some("win.statistics"));
+    tools_menu.append(
+        Some("Normalize Line Endings…"),
+        Some("win.normalize_line_endings"),
+    );
+    root.append_submenu(Some("Tools"), &tools_menu);
 
-                    // Apply next text without recording as a new undo entry
-                    *doc_state.is_programmatic.borrow_mut() = true;
-                    if let Some(buffer) = get_text_buffer_from_window(&window_clone) {
-                        buffer.set_text(&next_text);
-                    }
-                    *doc_state.last_text.borrow_mut() = next_text;
-                    *doc_state.is_programmatic.borrow_mut() = false;
-                }
-            }
-        }
-    });
-    app.add_action(&redo);
+    // ----- Help menu -----
+    let help_menu = gio::Menu::new();
+    help_menu.append(Some("rpad Help"), Some("win.help"));
+    help_menu.append(Some("Keyboard Shortcuts"), Some("win.shortcuts"));
+    help_menu.append(Some("About rpad"), Some("win.about"));
+    root.append_submenu(Some("Help"), &help_menu);
 
-    // ----- Clipboard actions -----
-    // CUT
-    let cut = SimpleAction::new("cut", None);
-    {
-        let text_view = text_view.clone();
-        cut.connect_activate(move |_, _| {
-            text_view.emit_by_name::<()>("cut-clipboard", &[]);
-        });
-    }
-    app.add_action(&cut);
+    gtk::PopoverMenuBar::from_model(Some(&root))
+}
 
-    // COPY
-    let copy = SimpleAction::new("copy", None);
-    {
-        let text_view = text_view.clone();
-        copy.connect_activate(move |_, _| {
-            text_view.emit_by_name::<()>("copy-clipboard", &[]);
-        });
-    }
-    app.add_action(&copy);
+fn build_lines_menu() -> gtk::gio::Menu {
+    let menu = gtk::gio::Menu::new();
+    menu.append(Some("Join Lines"), Some("win.join_lines"));
+    menu.append(Some("Delete Line"), Some("win.delete_line"));
+    menu.append(Some("Reverse Lines"), Some("win.reverse_lines"));
+    menu.append(Some("Shuffle Lines"), Some("win.shuffle_lines"));
+    menu
+}
 
-    // PASTE
-    let paste = SimpleAction::new("paste", None);
-    {
-        let text_view = text_view.clone();
-        paste.connect_activate(move |_, _| {
-            text_view.emit_by_name::<()>("paste-clipboard", &[]);
-        });
-    }
-    app.add_action(&paste);
+fn build_transpose_menu() -> gtk::gio::Menu {
+    let menu = gtk::gio::Menu::new();
+    menu.append(Some("Characters"), Some("win.transpose_chars"));
+    menu.append(Some("Words"), Some("win.transpose_words"));
+    menu
+}
 
-    // DELETE selection
-    let delete = SimpleAction::new("delete", None);
-    {
-        let text_view = text_view.clone();
-        delete.connect_activate(move |_, _| {
-            let buffer = text_view.buffer();
-            buffer.delete_selection(true, true);
-        });
+// Formatting for Markup mode; the actions are disabled in Plain mode
+fn build_markdown_menu() -> gtk::gio::Menu {
+    let menu = gtk::gio::Menu::new();
+    menu.append(Some("Bold"), Some("win.bold"));
+    menu.append(Some("Italic"), Some("win.italic"));
+    menu.append(Some("Link…"), Some("win.insert_link"));
+    menu.append(Some("Table…"), Some("win.insert_table"));
+
+    let headings = gtk::gio::Menu::new();
+    headings.append(Some("Increase Heading Level"), Some("win.heading_increase"));
+    headings.append(Some("Decrease Heading Level"), Some("win.heading_decrease"));
+    for level in 1..=6 {
+        headings.append(
+            Some(&format!("Heading {level}")),
+            Some(&format!("win.heading_level({level})")),
+        );
     }
-    app.add_action(&delete);
-
-    // Keyboard shortcuts for these
-    app.set_accels_for_action("app.undo", &["<Primary>z"]);
-    app.set_accels_for_action("app.redo", &["<Primary>y"]);
-    app.set_accels_for_action("app.cut", &["<Primary>X"]);
-    app.set_accels_for_action("app.copy", &["<Primary>C"]);
-    app.set_accels_for_action("app.paste", &["<Primary>V"]);
-    app.set_accels_for_action("app.delete", &["Delete"]);
+    headings.append(Some("Normal Text"), Some("win.heading_level(0)"));
+    menu.append_section(None, &headings);
+    menu
+}
 
-    // File shortcuts
-    app.set_accels_for_action("app.new", &["<Primary>n"]);
-    app.set_accels_for_action("app.save", &["<Primary>s"]);
-    app.set_accels_for_action("app.open", &["<Primary>o"]);
-    app.set_accels_for_action("app.save_as", &["<Primary><Shift>s"]);
-    app.set_accels_for_action("app.quit", &["<Primary>q"]);
+fn build_convert_indentation_menu() -> gtk::gio::Menu {
+    let menu = gtk::gio::Menu::new();
+    menu.append(Some("Tabs to Spaces"), Some("win.tabs_to_spaces"));
+    menu.append(Some("Spaces to Tabs"), Some("win.spaces_to_tabs"));
+    menu
+}
 
-    // ----- Find / Replace / Go To -----
-    // Find…
-    let find = SimpleAction::new("find", None);
-    {
-        let window_clone = window.clone();
-        let text_view = text_view.clone();
-        find.connect_activate(move |_, _| {
-            open_find_dialog(&window_clone, &text_view);
-        });
-    }
-    app.add_action(&find);
+fn build_change_case_menu() -> gtk::gio::Menu {
+    let menu = gtk::gio::Menu::new();
+    menu.append(Some("UPPERCASE"), Some("win.upper_case"));
+    menu.append(Some("lowercase"), Some("win.lower_case"));
+    menu
+}
 
-    // Find Next
-    let find_next = SimpleAction::new("find_next", None);
-    {
-        let window_clone = window.clone();
-        let text_view = text_view.clone();
-        find_next.connect_activate(move |_, _| {
-            do_find_next(&window_clone, &text_view);
-        });
+// One entry per dictionary the spelling provider has installed
+fn build_spell_language_menu() -> gtk::gio::Menu {
+    let menu = gtk::gio::Menu::new();
+    for info in libspelling::Provider::default().list_languages() {
+        let item = gtk::gio::MenuItem::new(Some(&info.name()), None);
+        item.set_action_and_target_value(
+            Some("win.spell_language"),
+            Some(&info.code().to_variant()),
+        );
+        menu.append_item(&item);
     }
-    app.add_action(&find_next);
+    menu
+}
 
-    // Find Previous
-    let find_prev = SimpleAction::new("find_prev", None);
-    {
-        let window_clone = window.clone();
-        let text_view = text_view.clone();
-        find_prev.connect_activate(move |_, _| {
-            do_find_prev(&window_clone, &text_view);
-        });
-    }
-    app.add_action(&find_prev);
+// Extra items appended to the editor's right-click menu. `spelling` holds
+// the suggestions for the word under the pointer and goes first.
+fn build_context_menu(spelling: &gtk::gio::MenuModel) -> gtk::gio::Menu {
+    use gtk::gio;
 
-    // Replace…
-    let replace = SimpleAction::new("replace", None);
-    {
-        let window_clone = window.clone();
-        let text_view = text_view.clone();
-        replace.connect_activate(move |_, _| {
-            open_replace_dialog(&window_clone, &text_view);
-        });
-    }
-    app.add_action(&replace);
+    let menu = gio::Menu::new();
+    menu.append_section(None, spelling);
 
-    // Go To…
-    let goto = SimpleAction::new("goto", None);
-    {
-        let window_clone = window.clone();
-        let text_view = text_view.clone();
-        goto.connect_activate(move |_, _| {
-            open_goto_dialog(&window_clone, &text_view);
-        });
-    }
-    app.add_action(&goto);
+    let search_section = gio::Menu::new();
+    search_section.append(Some("Find…"), Some("win.find"));
+    search_section.append(Some("Replace…"), Some("win.replace"));
+    search_section.append(Some("Go To…"), Some("win.goto"));
+    menu.append_section(None, &search_section);
 
-    app.set_accels_for_action("app.find", &["<Primary>F"]);
-    app.set_accels_for_action("app.find_next", &["F3"]);
-    app.set_accels_for_action("app.find_prev", &["<Shift>F3"]);
-    app.set_accels_for_action("app.replace", &["<Primary>H"]);
-    app.set_accels_for_action("app.goto", &["<Primary>G"]);
+    let edit_section = gio::Menu::new();
+    edit_section.append_submenu(Some("Change Case"), &build_change_case_menu());
+    menu.append_section(None, &edit_section);
 
-    // Select All
-    let select_all = SimpleAction::new("select_all", None);
-    {
-        let text_view = text_view.clone();
-        select_all.connect_activate(move |_, _| {
-            let buffer = text_view.buffer();
-            let (start, end) = buffer.bounds();
-            buffer.select_range(&start, &end);
-        });
-    }
-    app.add_action(&select_all);
+    menu.append_section(Some("Mode"), &build_mode_menu());
 
-    // Time/Date (insert at cursor, like Notepad's F5)
-    let time_date = SimpleAction::new("time_date", None);
-    {
-        let text_view = text_view.clone();
-        time_date.connect_activate(move |_, _| {
-            let buffer = text_view.buffer();
+    menu
+}
 
-            // now_local -> Result<DateTime, BoolError>
-            if let Ok(now) = glib::DateTime::now_local() {
-                // format -> Result<GString, BoolError>
-                if let Ok(stamp) = now.format("%Y-%m-%d %H:%M") {
-                    // GString derefs to &str, so this is fine
-                    buffer.insert_at_cursor(&stamp);
-                } else {
-                    buffer.insert_at_cursor("0000-00-00 00:00");
-                }
-            } else {
-                buffer.insert_at_cursor("0000-00-00 00:00");
-            }
-        });
-    }
-    app.add_action(&time_date);
+fn build_mode_menu() -> gtk::gio::Menu {
+    let menu = gtk::gio::Menu::new();
+    menu.append(Some("Plain Text"), Some("win.mode('plain')"));
+    menu.append(Some("Markup"), Some("win.mode('markup')"));
+    menu
+}
 
-    app.set_accels_for_action("app.select_all", &["<Primary>A"]);
-    app.set_accels_for_action("app.time_date", &["F5"]);
+// Clicking a status bar label pops up `menu` to change that setting directly
+fn attach_status_menu(label: &gtk::Label, menu: &gtk::gio::Menu) {
+    let popover = gtk::PopoverMenu::from_model(Some(menu));
+    popover.set_parent(label);
 
-    // ----- View actions (stubs) -----
-    // Zoom In
-    let zoom_in = SimpleAction::new("zoom_in", None);
-    let window_clone = window.clone();
-    zoom_in.connect_activate(move |_, _| unsafe {
-        if let Some(doc_state_ptr) = window_clone.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
-            let current = *doc_state.zoom.borrow();
-            if current < 500 {
-                *doc_state.zoom.borrow_mut() = current + 10;
-                update_zoom_css(doc_state);
-            }
-        }
+    let popover_clone = popover.clone();
+    let gesture = gtk::GestureClick::new();
+    gesture.connect_released(move |_, _, _, _| {
+        popover_clone.popup();
     });
-    app.add_action(&zoom_in);
+    label.add_controller(gesture);
+    label.set_cursor_from_name(Some("pointer"));
 
-    // Zoom Out
-    let zoom_out = SimpleAction::new("zoom_out", None);
-    let window_clone = window.clone();
-    zoom_out.connect_activate(move |_, _| unsafe {
-        if let Some(doc_state_ptr) = window_clone.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
-            let current = *doc_state.zoom.borrow();
-            if current > 20 {
-                *doc_state.zoom.borrow_mut() = current - 10;
-                update_zoom_css(doc_state);
-            }
-        }
+    label.connect_destroy(move |_| {
+        popover.unparent();
     });
-    app.add_action(&zoom_out);
+}
 
-    // Zoom Reset
-    let zoom_reset = SimpleAction::new("zoom_reset", None);
-    let window_clone = window.clone();
-    zoom_reset.connect_activate(move |_, _| unsafe {
-        if let Some(doc_state_ptr) = window_clone.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
-            *doc_state.zoom.borrow_mut() = 100;
-            update_zoom_css(doc_state);
-        }
-    });
-    app.add_action(&zoom_reset);
+// Application-level menu: the entries that aren't tied to the document
+fn build_app_menu() -> gtk::gio::Menu {
+    use gtk::gio;
 
-    // Add shortcuts
-    app.set_accels_for_action("app.zoom_in", &["<Primary>plus", "<Primary>equal"]);
-    app.set_accels_for_action("app.zoom_out", &["<Primary>minus"]);
-    app.set_accels_for_action("app.zoom_reset", &["<Primary>0"]);
+    let menu = gio::Menu::new();
 
-    let status_bar = SimpleAction::new_stateful(
-        "status_bar",
-        None,
-        &true.to_variant(), // Default to true (visible)
-    );
-    let window_clone = window.clone();
-    status_bar.connect_change_state(move |action, state| unsafe {
-        if let Some(state) = state {
-            action.set_state(state); // Update action state
-            let visible = state.get::<bool>().unwrap_or(true);
+    let preferences_section = gio::Menu::new();
+    preferences_section.append(Some("Preferences"), Some("win.preferences"));
+    menu.append_section(None, &preferences_section);
 
-            if let Some(doc_state_ptr) = window_clone.data::<DocumentState>("rpad-doc-state") {
-                let doc_state: &DocumentState = doc_state_ptr.as_ref();
-                doc_state.status_box.set_visible(visible);
-            }
-        }
-    });
-    app.add_action(&status_bar);
+    let help_section = gio::Menu::new();
+    help_section.append(Some("Keyboard Shortcuts"), Some("win.shortcuts"));
+    help_section.append(Some("About rpad"), Some("win.about"));
+    menu.append_section(None, &help_section);
 
-    // ----- Mode actions -----
-    // ----- Mode actions -----
-    // Stateful "mode" action
-    let initial_mode_str = unsafe {
-        if let Some(doc_state_ptr) = window.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
-            match doc_state.mode() {
-                Mode::Plain => "plain",
-                Mode::Markup => "markup",
-            }
-        } else {
-            "plain"
-        }
-    };
+    let quit_section = gio::Menu::new();
+    quit_section.append(Some("Quit"), Some("app.quit"));
+    menu.append_section(None, &quit_section);
 
-    let mode_action = SimpleAction::new_stateful(
-        "mode",
-        Some(glib::VariantTy::STRING),
-        &initial_mode_str.to_variant(),
+    menu
+}
+
+// Write the bundled desktop entry to $XDG_DATA_HOME/applications so the
+// WM can match windows (by app id) to the right name and icon
+fn install_desktop_file() -> Result<PathBuf, String> {
+    const DESKTOP_ENTRY: &str = include_str!("../dev.rpad.app.desktop");
+
+    let exe_path = std::env::current_exe()
+        .map_err(|e| format!("Could not determine current executable: {}", e))?;
+    let contents = DESKTOP_ENTRY.replace(
+        "Exec=rpad %F",
+        &format!("Exec=\"{}\" %F", exe_path.display()),
     );
 
-    {
-        let window_clone = window.clone();
-        let text_view_clone = text_view.clone();
-        mode_action.connect_change_state(move |action, value| unsafe {
-            if let Some(value) = value {
-                let requested_mode_str = value.str().unwrap_or("plain");
-                let requested_mode = match requested_mode_str {
-                    "markup" => Mode::Markup,
-                    _ => Mode::Plain,
-                };
+    let dir = glib::user_data_dir().join("applications");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
 
-                if let Some(doc_state_ptr) = window_clone.data::<DocumentState>("rpad-doc-state") {
-                    let doc_state: &DocumentState = doc_state_ptr.as_ref();
+    let path = dir.join(format!("{}.desktop", APP_ID));
+    fs::write(&path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
 
-                    // If already in that mode, just ensure state is sync and return
-                    if doc_state.mode() == requested_mode {
-                        action.set_state(value);
-                        return;
-                    }
+// Register a new window's notebook and status bar, for
+// notebook_from_window and status_bar_from_window to find
+fn attach_window_widgets(
+    window: &gtk::ApplicationWindow,
+    notebook: &gtk::Notebook,
+    status: &StatusBar,
+) {
+    WINDOWS.with(|windows| {
+        windows
+            .borrow_mut()
+            .push((window.downgrade(), notebook.clone(), status.clone()))
+    });
+    window.connect_destroy(|closing| {
+        // Dropped after the registry is released, as in forget_tab
+        let closed: Vec<WindowEntry> = WINDOWS.with(|windows| {
+            let mut windows = windows.borrow_mut();
+            let (open, closed) = windows
+                .drain(..)
+                .partition(|(window, _, _)| window.upgrade().is_some_and(|w| w != *closing));
+            *windows = open;
+            closed
+        });
+        drop(closed);
+    });
+}
 
-                    // Check buffer
-                    let sv_buffer = text_view_clone
-                        .buffer()
-                        .downcast::<sv::Buffer>()
-                        .expect("Buffer is not sv::Buffer");
+fn notebook_from_window(window: &gtk::ApplicationWindow) -> Option<gtk::Notebook> {
+    WINDOWS.with(|windows| {
+        windows
+            .borrow()
+            .iter()
+            .find(|(entry, _, _)| entry.upgrade().as_ref() == Some(window))
+            .map(|(_, notebook, _)| notebook.clone())
+    })
+}
 
-                    if !buffer_is_empty(&sv_buffer) {
-                        // Show dialog
-                        let dialog = gtk::MessageDialog::builder()
-                            .transient_for(&window_clone)
-                            .modal(true)
-                            .message_type(gtk::MessageType::Info)
-                            .buttons(gtk::ButtonsType::Ok)
-                            .text("Cannot change mode while the document has content.")
-                            .secondary_text(
-                                "Create a new file or clear all text before switching between Plain and Markup.",
-                            )
-                            .build();
+fn status_bar_from_window(window: &gtk::ApplicationWindow) -> Option<StatusBar> {
+    WINDOWS.with(|windows| {
+        windows
+            .borrow()
+            .iter()
+            .find(|(entry, _, _)| entry.upgrade().as_ref() == Some(window))
+            .map(|(_, _, status)| status.clone())
+    })
+}
 
-                        dialog.connect_response(|d, _| d.close());
-                        dialog.show();
-                        return; // Abort state change
-                    }
+// The page of the tab in front
+fn current_page(window: &gtk::ApplicationWindow) -> Option<gtk::Widget> {
+    let notebook = notebook_from_window(window)?;
+    notebook.nth_page(notebook.current_page())
+}
 
-                    // Apply changes
-                    doc_state.set_mode(requested_mode);
+fn tab_pages(window: &gtk::ApplicationWindow) -> Vec<gtk::Widget> {
+    match notebook_from_window(window) {
+        Some(notebook) => (0..notebook.n_pages())
+            .filter_map(|index| notebook.nth_page(Some(index)))
+            .collect(),
+        None => Vec::new(),
+    }
+}
 
-                    // Update label
-                    let label = match requested_mode {
-                        Mode::Plain => "Plain Text",
-                        Mode::Markup => "Markdown",
-                    };
-                    doc_state.label_mode.set_text(label);
+// Bring `page` to the front; false if it's no longer one of the tabs
+fn select_tab(window: &gtk::ApplicationWindow, page: &gtk::Widget) -> bool {
+    let Some(notebook) = notebook_from_window(window) else {
+        return false;
+    };
+    match notebook.page_num(page) {
+        Some(index) => {
+            notebook.set_current_page(Some(index));
+            true
+        }
+        None => false,
+    }
+}
 
-                    // Apply language
-                    apply_language_for_mode(&sv_buffer, requested_mode);
+// Register a new tab's page with its DocumentState and widgets, for
+// doc_state_of and tab_widgets to find
+fn attach_tab(page: &gtk::Widget, doc_state: DocumentState, widgets: TabWidgets) {
+    TABS.with(|tabs| {
+        tabs.borrow_mut().push(TabEntry {
+            page: page.downgrade(),
+            doc_state: Rc::new(doc_state),
+            widgets,
+        })
+    });
+    page.connect_destroy(forget_tab);
+}
 
-                    // Update title
-                    let base_title = match doc_state.path() {
-                        Some(path) => format!("rpad - {}", path.display()),
-                        None => "rpad - Untitled".to_string(),
-                    };
-                    let suffix = match requested_mode {
-                        Mode::Plain => " [Plain]",
-                        Mode::Markup => " [Markdown]",
-                    };
-                    window_clone.set_title(Some(&format!("{}{}", base_title, suffix)));
+// Drop the entry of a page that is being destroyed, and of any already
+// gone. They are dropped after the registry is released, in case dropping
+// one reaches back into it.
+fn forget_tab(closing: &gtk::Widget) {
+    let closed: Vec<TabEntry> = TABS.with(|tabs| {
+        let mut tabs = tabs.borrow_mut();
+        let (open, closed) = tabs
+            .drain(..)
+            .partition(|tab| tab.page.upgrade().is_some_and(|page| page != *closing));
+        *tabs = open;
+        closed
+    });
+    drop(closed);
+}
 
-                    // Update action state
-                    action.set_state(value);
-                }
-            }
-        });
-    }
-    app.add_action(&mode_action);
+fn find_tab<T>(page: &gtk::Widget, get: impl FnOnce(&TabEntry) -> T) -> Option<T> {
+    TABS.with(|tabs| {
+        tabs.borrow()
+            .iter()
+            .find(|tab| tab.page.upgrade().as_ref() == Some(page))
+            .map(get)
+    })
+}
 
-    // Sudo Mode Toggle
-    let sudo_mode = SimpleAction::new_stateful("sudo_mode", None, &false.to_variant());
-    {
-        let window_clone = window.clone();
-        sudo_mode.connect_change_state(move |action, value| unsafe {
-            // "value" is the requested new state (Some(bool))
-            if let Some(requested_state_variant) = value {
-                let new_state = requested_state_variant.get::<bool>().unwrap_or(false);
+fn doc_state_of(page: &impl IsA<gtk::Widget>) -> Option<Rc<DocumentState>> {
+    find_tab(page.upcast_ref(), |tab| tab.doc_state.clone())
+}
 
-                if let Some(doc_state_ptr) = window_clone.data::<DocumentState>("rpad-doc-state") {
-                    let doc_state: &DocumentState = doc_state_ptr.as_ref();
+fn tab_widgets(page: &gtk::Widget) -> Option<TabWidgets> {
+    find_tab(page, |tab| tab.widgets.clone())
+}
 
-                    if new_state {
-                        // Enable
-                        if let Some(password) = prompt_for_password(&window_clone) {
-                            match validate_sudo_password(&password) {
-                                Ok(_) => {
-                                    *doc_state.sudo_password.borrow_mut() = Some(password);
-                                    *doc_state.sudo_expiry.borrow_mut() = Some(
-                                        std::time::Instant::now()
-                                            + std::time::Duration::from_secs(300),
-                                    );
-
-                                    // Success: apply state
-                                    action.set_state(&new_state.into());
-
-                                    eprintln!("Sudo activated. Setting state to true.");
-                                    set_sudo_state(&window_clone, true);
-                                }
-                                Err(err_msg) => {
-                                    eprintln!("Sudo validation returned error: {}", err_msg);
-                                    // Invalid password: do NOT set state.
-                                    let dialog = gtk::MessageDialog::builder()
-                                        .transient_for(&window_clone)
-                                        .modal(true)
-                                        .message_type(gtk::MessageType::Error)
-                                        .buttons(gtk::ButtonsType::Ok)
-                                        .text("Sudo Validation Failed")
-                                        .secondary_text(&err_msg)
-                                        .build();
-                                    dialog.connect_response(|d, _| d.close());
-                                    dialog.show();
-                                }
-                            }
-                        }
-                        // If cancelled, do nothing (state remains false)
-                    } else {
-                        // Disable (unchecked)
-                        *doc_state.sudo_password.borrow_mut() = None;
-                        *doc_state.sudo_expiry.borrow_mut() = None;
+// The current tab's DocumentState
+fn active_doc_state(window: &gtk::ApplicationWindow) -> Option<Rc<DocumentState>> {
+    doc_state_of(&current_page(window)?)
+}
 
-                        action.set_state(&new_state.into());
-                        set_sudo_state(&window_clone, false);
+fn get_text_view_from_window(window: &gtk::ApplicationWindow) -> Option<sv::View> {
+    tab_widgets(&current_page(window)?).map(|widgets| widgets.text_view)
+}
 
-                        let dialog = gtk::MessageDialog::builder()
-                            .transient_for(&window_clone)
-                            .modal(true)
-                            .message_type(gtk::MessageType::Info)
-                            .buttons(gtk::ButtonsType::Ok)
-                            .text("Sudo Mode Disabled")
-                            .build();
-                        dialog.connect_response(|d, _| d.close());
-                        dialog.show();
-                    }
+fn spell_adapter_of_page(page: &gtk::Widget) -> Option<libspelling::TextBufferAdapter> {
+    tab_widgets(page).map(|widgets| widgets.spell_adapter)
+}
+
+fn minimap_of_page(page: &gtk::Widget) -> Option<sv::Map> {
+    tab_widgets(page).map(|widgets| widgets.minimap)
+}
+
+fn get_spell_adapter_from_window(
+    window: &gtk::ApplicationWindow,
+) -> Option<libspelling::TextBufferAdapter> {
+    spell_adapter_of_page(&current_page(window)?)
+}
+
+fn get_text_buffer_from_window(window: &gtk::ApplicationWindow) -> Option<gtk::TextBuffer> {
+    get_text_view_from_window(window).map(|view| view.buffer())
+}
+
+fn get_source_buffer_from_window(window: &gtk::ApplicationWindow) -> Option<sv::Buffer> {
+    get_text_view_from_window(window).and_then(|view| view.buffer().downcast::<sv::Buffer>().ok())
+}
+
+fn source_buffer_of_page(page: &gtk::Widget) -> Option<sv::Buffer> {
+    tab_widgets(page).and_then(|widgets| widgets.text_view.buffer().downcast::<sv::Buffer>().ok())
+}
+
+fn save_buffer_to_path(
+    window: &gtk::ApplicationWindow,
+    path: &std::path::Path,
+) -> Result<(), String> {
+    let buffer = get_text_buffer_from_window(window)
+        .ok_or_else(|| "Could not find text buffer".to_string())?;
+    let (start, end) = buffer.bounds();
+    let text = buffer.text(&start, &end, true);
+
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        if !lock_document(doc_state, path) && !confirm_save_locked(window, path) {
+            return Err(format!("{} is locked by another program", path.display()));
+        }
+
+        // Check Sudo Mode
+        let mut use_sudo = false;
+        let mut sudo_pass = None;
+
+        if doc_state.sudo_active() {
+            let now = std::time::Instant::now();
+            let pass = doc_state
+                .document
+                .borrow()
+                .sudo_password(now)
+                .map(str::to_string);
+
+            if let Some(pass) = pass {
+                use_sudo = true;
+                sudo_pass = Some(pass);
+            } else {
+                // Expired: re-prompt
+                if let Some(new_pass) = prompt_for_password(window) {
+                    doc_state
+                        .document
+                        .borrow_mut()
+                        .start_sudo(new_pass.clone(), now);
+                    use_sudo = true;
+                    sudo_pass = Some(new_pass);
+                } else {
+                    return Err("Sudo re-authentication cancelled".to_string());
                 }
             }
-        });
-    }
-    app.add_action(&sudo_mode);
+        }
 
-    // ----- Help actions -----
-    let about = SimpleAction::new("about", None);
-    let window_clone = window.clone();
-    about.connect_activate(move |_, _| {
-        let dialog = gtk::AboutDialog::builder()
-            .transient_for(&window_clone)
-            .modal(true)
-            .program_name("Rust Pad (rpad)")
-            .version("0.1.0")
-            .authors(vec!["Rhonald John Rose".to_string()])
-            .website("https://github.com/rhonaldjr/rpad")
-            .logo_icon_name("text-editor") // Use a generic icon name
-            .build();
+        // A byte order mark the file came with goes back on
+        let mut contents = if *doc_state.bom.borrow() {
+            format!("{BOM}{text}")
+        } else {
+            text.to_string()
+        };
+        if let Some(eol) = *doc_state.eol.borrow() {
+            contents = text_ops::normalize_line_endings(&contents, eol);
+        }
+        let contents = encode_text(&contents, doc_state.encoding.borrow().as_deref())?;
 
-        dialog.present();
-    });
-    app.add_action(&about);
-}
+        if use_sudo {
+            if let Some(pass) = sudo_pass {
+                perform_sudo_save(path, &contents, &pass)?;
+            } else {
+                return Err("Sudo password missing logic error".to_string());
+            }
+        } else {
+            // Normal Save
+            if let Err(e) = fs::write(path, &contents) {
+                return Err(format!("Failed to write file: {}", e));
+            }
+        }
 
-fn save_as_with_dialog(window: &gtk::ApplicationWindow) {
-    use gtk::{FileChooserAction, FileFilter, ResponseType};
+        // Remember which files needed sudo, to offer it when they reopen
+        let saved_with_sudo = Session::load().file(path).is_some_and(|file| file.sudo);
+        if use_sudo != saved_with_sudo {
+            Session::update_file(path, |file| file.sudo = use_sudo);
+        }
 
-    let dialog = gtk::FileChooserDialog::new(
-        Some("Save File"),
-        Some(window),
-        FileChooserAction::Save,
-        &[
-            ("_Cancel", ResponseType::Cancel),
-            ("_Save", ResponseType::Accept),
-        ],
-    );
+        // Mark as not dirty only on success
+        doc_state
+            .update_document(|document| document.mark_saved(path.to_path_buf(), text.to_string()));
+        // A new file can be locked now that it exists
+        if !lock_document(doc_state, path) {
+            doc_state.lock.replace(None);
+        }
+        update_save_action(window, doc_state);
 
-    // Decide default name based on current mode
-    let mode = current_mode(window);
-    let default_name = match mode {
-        Mode::Plain => "Untitled.txt",
-        Mode::Markup => "Untitled.md",
-    };
-    dialog.set_current_name(default_name);
+        update_title(window);
+        flash_status_message(
+            &doc_state.status,
+            &format!("Saved {} at {}", path.display(), clock_time()),
+        );
 
-    // Optional: filters match mode, but allow both
-    let text_filter = FileFilter::new();
-    text_filter.set_name(Some("Text Files (*.txt)"));
-    text_filter.add_pattern("*.txt");
-    dialog.add_filter(&text_filter);
+        return Ok(());
+    }
 
-    let md_filter = FileFilter::new();
-    md_filter.set_name(Some("Markdown Files (*.md, *.markdown)"));
-    md_filter.add_pattern("*.md");
-    md_filter.add_pattern("*.markdown");
-    dialog.add_filter(&md_filter);
+    // Fallback if doc_state missing (shouldn't happen)
+    if let Err(e) = fs::write(path, text.as_str()) {
+        Err(format!("Failed to write file: {}", e))
+    } else {
+        Ok(())
+    }
+}
 
-    let all_filter = FileFilter::new();
-    all_filter.set_name(Some("All Files"));
-    all_filter.add_pattern("*");
-    dialog.add_filter(&all_filter);
+// Open `path`, putting the cursor on 1-based `line` if given. A file that is
+// already open, in this window or another, is offered instead: two copies
+// would each save over the other's edits.
+fn open_file(window: &gtk::ApplicationWindow, path: &Path, line: Option<u32>, format: FileFormat) {
+    match find_open_document(window, path) {
+        Some((owner, page)) => confirm_open_again(window, path, line, format, owner, page),
+        None => open_text_file(window, path, line, format),
+    }
+}
+
+// Opening a file from the UI: refuse binary content, and ask before loading
+// anything big enough to make the editor sluggish
+fn open_text_file(
+    window: &gtk::ApplicationWindow,
+    path: &Path,
+    line: Option<u32>,
+    format: FileFormat,
+) {
+    if !ensure_text_file(window, path, format.encoding.as_deref()) {
+        return;
+    }
+
+    let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    if size <= LARGE_FILE_WARN_BYTES {
+        load_file_into_window(window, path, line, format);
+        return;
+    }
+
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Warning)
+        .buttons(gtk::ButtonsType::None)
+        .text("This file is very large")
+        .secondary_text(format!(
+            "{} is {:.1} MB. Opening it may make rpad slow or unresponsive.",
+            path.display(),
+            size as f64 / (1024.0 * 1024.0)
+        ))
+        .build();
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Open Anyway", gtk::ResponseType::Accept);
 
     let window_clone = window.clone();
+    let path = path.to_path_buf();
     dialog.connect_response(move |dialog, response| {
-        if response == ResponseType::Accept {
-            if let Some(file) = dialog.file() {
-                if let Some(path) = file.path() {
-                    if let Err(err) = save_buffer_to_path(&window_clone, path.as_ref()) {
-                        eprintln!("Error saving file: {err}");
-                    }
-                }
-            }
+        if response == gtk::ResponseType::Accept {
+            load_file_into_window(&window_clone, &path, line, format.clone());
         }
-
         dialog.close();
     });
 
     dialog.show();
 }
 
-fn current_mode(window: &gtk::ApplicationWindow) -> Mode {
-    unsafe {
-        if let Some(doc_state_ptr) = window.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
-            doc_state.mode()
-        } else {
-            Mode::Plain
-        }
-    }
+// The tab holding `path` in any of the application's windows, comparing
+// canonical paths so that symlinks and relative paths still match
+fn find_open_document(
+    window: &gtk::ApplicationWindow,
+    path: &Path,
+) -> Option<(gtk::ApplicationWindow, gtk::Widget)> {
+    let app = window.application()?;
+    let canonical = |path: PathBuf| fs::canonicalize(&path).unwrap_or(path);
+    let path = canonical(path.to_path_buf());
+    app.windows()
+        .into_iter()
+        .filter_map(|window| window.downcast::<gtk::ApplicationWindow>().ok())
+        .find_map(|window| {
+            let page = tab_pages(&window).into_iter().find(|page| {
+                doc_state_of(page)
+                    .and_then(|doc_state| doc_state.path())
+                    .is_some_and(|open| canonical(open) == path)
+            })?;
+            Some((window, page))
+        })
 }
 
-// Sudo Helpers
-
-fn prompt_for_password(window: &gtk::ApplicationWindow) -> Option<String> {
-    use std::cell::RefCell;
-    use std::rc::Rc;
-
+// `path` is already open in `page`: switch to it (and to `line`), or open a
+// second copy anyway
+fn confirm_open_again(
+    window: &gtk::ApplicationWindow,
+    path: &Path,
+    line: Option<u32>,
+    format: FileFormat,
+    owner: gtk::ApplicationWindow,
+    page: gtk::Widget,
+) {
+    let name = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    let place = if &owner == window {
+        "another tab"
+    } else {
+        "another window"
+    };
     let dialog = gtk::MessageDialog::builder()
         .transient_for(window)
         .modal(true)
-        .message_type(gtk::MessageType::Question)
-        .buttons(gtk::ButtonsType::OkCancel)
-        .text("Enter Sudo Password")
+        .message_type(gtk::MessageType::Warning)
+        .buttons(gtk::ButtonsType::None)
+        .text(format!("“{name}” is already open"))
+        .secondary_text(format!(
+            "It is open in {place}. If both copies are edited, saving one overwrites the changes made in the other."
+        ))
         .build();
+    dialog.add_button("Open Another Copy", gtk::ResponseType::Reject);
+    dialog.add_button("Switch to It", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
 
-    let content_area = dialog.content_area();
-    let entry = gtk::PasswordEntry::new();
-    entry.set_hexpand(true);
-    entry.set_margin_start(10);
-    entry.set_margin_end(10);
+    let window_clone = window.clone();
+    let path = path.to_path_buf();
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        if response != gtk::ResponseType::Accept && response != gtk::ResponseType::Reject {
+            return;
+        }
+        // The tab may have been closed while the dialog was up
+        if response == gtk::ResponseType::Accept && select_tab(&owner, &page) {
+            owner.present();
+            if let (Some(line), Some(text_view)) = (line, get_text_view_from_window(&owner)) {
+                goto_line(&text_view, line as i32);
+            }
+        } else {
+            open_text_file(&window_clone, &path, line, format.clone());
+        }
+    });
+    dialog.show();
+}
+
+// Shows an explanatory dialog and returns false if `path` isn't readable text
+// (in `encoding`, when one is forced)
+fn ensure_text_file(window: &gtk::ApplicationWindow, path: &Path, encoding: Option<&str>) -> bool {
+    // Checked first: reading a FIFO would block until something writes to it
+    if let Some(kind) = special_file_kind(path) {
+        show_error_dialog(
+            window,
+            "Cannot open file",
+            &format!("{} is {}.", path.display(), kind),
+        );
+        return false;
+    }
+
+    // Text in a forced encoding such as UTF-16 can be full of NUL bytes
+    if encoding.is_some() {
+        return true;
+    }
+
+    match looks_binary(path) {
+        Ok(false) => true,
+        Ok(true) => {
+            show_error_dialog(
+                window,
+                "Cannot open binary file",
+                &format!(
+                    "{} appears to contain binary data and can't be edited as text.",
+                    path.display()
+                ),
+            );
+            false
+        }
+        // read_file_async offers to read these through sudo
+        Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => true,
+        Err(err) => {
+            show_error_dialog(
+                window,
+                "Cannot open file",
+                &format!("{}: {}", path.display(), err),
+            );
+            false
+        }
+    }
+}
+
+// `bytes` as text: UTF-8 unless `encoding` names another character set
+fn decode_text(bytes: Vec<u8>, encoding: Option<&str>) -> std::io::Result<String> {
+    use std::io::{Error, ErrorKind};
+
+    let bytes = match encoding {
+        Some(encoding) => glib::convert(&bytes, "UTF-8", encoding)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err.to_string()))?
+            .0
+            .to_vec(),
+        None => bytes,
+    };
+    String::from_utf8(bytes)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "stream did not contain valid UTF-8"))
+}
+
+// `text` as bytes in `encoding`, or UTF-8 without one
+fn encode_text(text: &str, encoding: Option<&str>) -> Result<Vec<u8>, String> {
+    match encoding {
+        Some(encoding) => glib::convert(text.as_bytes(), encoding, "UTF-8")
+            .map(|(bytes, _)| bytes.to_vec())
+            .map_err(|err| format!("Cannot save as {encoding}: {err}")),
+        None => Ok(text.as_bytes().to_vec()),
+    }
+}
+
+// What `path` is when it exists but isn't a regular file, e.g. "a directory"
+fn special_file_kind(path: &Path) -> Option<&'static str> {
+    let file_type = fs::metadata(path).ok()?.file_type();
+    if file_type.is_file() {
+        return None;
+    }
+    if file_type.is_dir() {
+        return Some("a directory");
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        if file_type.is_fifo() {
+            return Some("a named pipe");
+        }
+        if file_type.is_socket() {
+            return Some("a socket");
+        }
+        if file_type.is_block_device() || file_type.is_char_device() {
+            return Some("a device");
+        }
+    }
+    Some("not a regular file")
+}
+
+// Text files don't contain NUL bytes; checking the first chunk is enough
+fn looks_binary(path: &Path) -> std::io::Result<bool> {
+    use std::io::Read;
+
+    let mut chunk = Vec::with_capacity(BINARY_SNIFF_BYTES);
+    fs::File::open(path)?
+        .take(BINARY_SNIFF_BYTES as u64)
+        .read_to_end(&mut chunk)?;
+    Ok(chunk.contains(&0))
+}
+
+fn show_error_dialog(window: &gtk::ApplicationWindow, text: &str, secondary_text: &str) {
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Error)
+        .buttons(gtk::ButtonsType::Ok)
+        .text(text)
+        .secondary_text(secondary_text)
+        .build();
+    dialog.connect_response(|d, _| d.close());
+    dialog.show();
+}
+
+fn load_file_into_window(
+    window: &gtk::ApplicationWindow,
+    path: &Path,
+    line: Option<u32>,
+    format: FileFormat,
+) {
+    let window_clone = window.clone();
+    let path_buf = path.to_path_buf();
+    let encoding = format.encoding.clone();
+    read_file_async(window, path, encoding, move |contents, sudo_password| {
+        // The tab only comes once there's something to put in it, so a
+        // failed read doesn't leave an empty one behind
+        if tab_for_opening(&window_clone).is_some() {
+            set_file_format(&window_clone, &format);
+            let contents = match format.eol {
+                Some(eol) => text_ops::normalize_line_endings(&contents, eol),
+                None => contents,
+            };
+            let line_endings = text_ops::count_line_endings(&contents);
+            set_loaded_contents(&window_clone, &path_buf, contents);
+            if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+                if !lock_document(doc_state, &path_buf) {
+                    doc_state.lock.replace(None);
+                    flash_status_message(
+                        &doc_state.status,
+                        "Another program has locked this file; saving will ask first",
+                    );
+                }
+            }
+            // Saving it back will need sudo as well
+            if let Some(password) = sudo_password {
+                start_sudo_session(&window_clone, password);
+            } else if needs_sudo_again(&path_buf) {
+                offer_sudo_mode(&window_clone, &path_buf);
+            }
+            if line_endings.iter().filter(|&&count| count > 0).count() > 1 {
+                notify_mixed_line_endings(&window_clone, &path_buf, line_endings);
+            }
+            if let (Some(line), Some(text_view)) = (line, get_text_view_from_window(&window_clone))
+            {
+                goto_line(&text_view, line as i32);
+            }
+            refresh_status_counts(&window_clone);
+        }
+    });
+}
+
+// Line/column and word counts for the current tab, for loads that bypass
+// the buffer's change handler
+fn refresh_status_counts(window: &gtk::ApplicationWindow) {
+    let Some(buffer) = get_text_buffer_from_window(window) else {
+        return;
+    };
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        update_cursor(doc_state, &buffer);
+        update_counts(doc_state, &buffer);
+    }
+}
+
+// The tab in front reads and writes its file as `format` says
+fn set_file_format(window: &gtk::ApplicationWindow, format: &FileFormat) {
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        *doc_state.encoding.borrow_mut() = format.encoding.clone();
+        *doc_state.eol.borrow_mut() = format.eol;
+    }
+}
+
+// Open `path` read-only and append whatever gets written to it afterwards
+fn follow_file(window: &gtk::ApplicationWindow, path: &Path, format: FileFormat) {
+    if !ensure_text_file(window, path, format.encoding.as_deref()) {
+        return;
+    }
+    let Some(text_view) = get_text_view_from_window(window) else {
+        return;
+    };
+
+    // Read like the appends that follow, so the offset they start from is
+    // the file's length in bytes, whatever its encoding
+    let window = window.clone();
+    let path = path.to_path_buf();
+    let encoding = format.encoding.clone();
+    set_loading(&window, &text_view, true);
+    glib::MainContext::default().spawn_local(async move {
+        let read_path = path.clone();
+        let result =
+            gtk::gio::spawn_blocking(move || read_appended(&read_path, 0, encoding.as_deref()))
+                .await;
+        set_loading(&window, &text_view, false);
+
+        match result {
+            Ok(Ok((contents, offset))) => {
+                if let Some(page) = tab_for_opening(&window) {
+                    set_file_format(&window, &format);
+                    set_loaded_contents(&window, &path, contents);
+                    start_following(&window, &page, &path, offset);
+                }
+            }
+            Ok(Err(err)) => show_error_dialog(
+                &window,
+                "Cannot open file",
+                &format!("{}: {}", path.display(), err),
+            ),
+            Err(_) => show_error_dialog(
+                &window,
+                "Cannot open file",
+                &format!("{}: reading the file failed unexpectedly", path.display()),
+            ),
+        }
+    });
+}
+
+// Watch the file behind `page` and append bytes past `offset` as they
+// arrive. The view keeps scrolling to the end while it's at the bottom;
+// scrolling up stops that until the view is back at the end.
+fn start_following(window: &gtk::ApplicationWindow, page: &gtk::Widget, path: &Path, offset: u64) {
+    use gtk::gio;
+
+    let Some(text_view) = tab_widgets(page).map(|widgets| widgets.text_view) else {
+        return;
+    };
+    let monitor = match gio::File::for_path(path)
+        .monitor_file(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>)
+    {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            eprintln!("Cannot follow {}: {err}", path.display());
+            return;
+        }
+    };
+
+    for view in views_of_page(page) {
+        view.set_editable(false);
+    }
+
+    let at_bottom = Rc::new(std::cell::Cell::new(true));
+    if let Some(vadjustment) = text_view.vadjustment() {
+        let at_bottom = at_bottom.clone();
+        vadjustment.connect_value_changed(move |adjustment| {
+            at_bottom.set(adjustment.value() + adjustment.page_size() >= adjustment.upper() - 1.0);
+        });
+    }
+
+    let buffer = text_view.buffer();
+    let end_mark = buffer.create_mark(None, &buffer.end_iter(), false);
+    let offset = std::cell::Cell::new(offset);
+    let window = window.clone();
+    let page_weak = page.downgrade();
+    let path = path.to_path_buf();
+    monitor.connect_changed(move |_, _, _, event| {
+        if !matches!(
+            event,
+            gio::FileMonitorEvent::Changed | gio::FileMonitorEvent::Created
+        ) {
+            return;
+        }
+        let Some(page) = page_weak.upgrade() else {
+            return;
+        };
+        let Some(doc_state) = doc_state_of(&page) else {
+            return;
+        };
+
+        // A file that shrank was truncated or rotated: start over
+        let len = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if len < offset.get() {
+            offset.set(0);
+            *doc_state.is_programmatic.borrow_mut() = true;
+            buffer.set_text("");
+            *doc_state.is_programmatic.borrow_mut() = false;
+            doc_state.document.borrow_mut().set_text(String::new());
+        }
+
+        let encoding = doc_state.encoding.borrow().clone();
+        let Ok((text, read)) = read_appended(&path, offset.get(), encoding.as_deref()) else {
+            return;
+        };
+        if text.is_empty() {
+            return;
+        }
+        offset.set(offset.get() + read);
+
+        *doc_state.is_programmatic.borrow_mut() = true;
+        buffer.insert(&mut buffer.end_iter(), &text);
+        *doc_state.is_programmatic.borrow_mut() = false;
+        doc_state.document.borrow_mut().append_text(&text);
+        schedule_count_update(&window, &doc_state);
+
+        if at_bottom.get() {
+            text_view.scroll_to_mark(&end_mark, 0.0, true, 0.0, 1.0);
+        }
+    });
+
+    // The page's handler holds the monitor, so following stops when the
+    // tab closes
+    page.connect_destroy(move |_| {
+        monitor.cancel();
+    });
+}
+
+// Text written to `path` past byte `offset`, in `encoding` (UTF-8 without
+// one), plus how many bytes it used. A character cut off at the end is left
+// for the next read.
+fn read_appended(
+    path: &Path,
+    offset: u64,
+    encoding: Option<&str>,
+) -> std::io::Result<(String, u64)> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = fs::File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    let Some(encoding) = encoding else {
+        let complete = match std::str::from_utf8(&bytes) {
+            Ok(_) => bytes.len(),
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => bytes.len(),
+        };
+        let text = String::from_utf8_lossy(&bytes[..complete]).into_owned();
+        return Ok((text, complete as u64));
+    };
+
+    // No character set needs more than four bytes for one character, so a
+    // cut-off one is among the last three
+    let mut first_err = None;
+    for cut in 0..=bytes.len().min(3) {
+        let complete = bytes.len() - cut;
+        match decode_text(bytes[..complete].to_vec(), Some(encoding)) {
+            Ok(text) => return Ok((text, complete as u64)),
+            Err(err) => {
+                first_err.get_or_insert(err);
+            }
+        }
+    }
+    Err(first_err.unwrap_or_else(|| std::io::ErrorKind::InvalidData.into()))
+}
+
+// Opening a file reuses the current tab if it's an untouched, empty
+// document; otherwise the file gets a tab of its own. Called once the file
+// has been read, so that a read that fails adds no tab.
+fn tab_for_opening(window: &gtk::ApplicationWindow) -> Option<gtk::Widget> {
+    let is_empty =
+        get_text_buffer_from_window(window).is_some_and(|buffer| buffer.char_count() == 0);
+    let is_clean = active_doc_state(window).is_some_and(|doc_state| !doc_state.is_dirty());
+
+    if !(is_empty && is_clean) {
+        add_tab(window, &active_tab_config(window)?);
+    }
+    current_page(window)
+}
+
+// Paste a file's text at the cursor as a single undo step, leaving the
+// document's own path alone
+fn insert_file_at_cursor(window: &gtk::ApplicationWindow, path: &Path) {
+    if !ensure_text_file(window, path, None) {
+        return;
+    }
+
+    let window_clone = window.clone();
+    read_file_async(window, path, None, move |contents, _| {
+        if let Some(buffer) = get_text_buffer_from_window(&window_clone) {
+            buffer.begin_user_action();
+            buffer.insert_at_cursor(&contents);
+            buffer.end_user_action();
+        }
+    });
+}
+
+// Reads the file on a worker thread so big files don't freeze the UI,
+// decoding it from `encoding` (UTF-8 without one); `on_read` gets the
+// contents back on the main context, along with the sudo password when the
+// file could only be read through sudo
+fn read_file_async<F: FnOnce(String, Option<String>) + 'static>(
+    window: &gtk::ApplicationWindow,
+    path: &Path,
+    encoding: Option<String>,
+    on_read: F,
+) {
+    let Some(text_view) = get_text_view_from_window(window) else {
+        return;
+    };
+    let window = window.clone();
+    let path = path.to_path_buf();
+
+    set_loading(&window, &text_view, true);
+    glib::MainContext::default().spawn_local(async move {
+        let read_path = path.clone();
+        let read_encoding = encoding.clone();
+        let result = gtk::gio::spawn_blocking(move || {
+            decode_text(fs::read(read_path)?, read_encoding.as_deref())
+        })
+        .await;
+        set_loading(&window, &text_view, false);
+
+        let result = match result {
+            Ok(Ok(contents)) => Ok((contents, None)),
+            Ok(Err(err)) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+                read_file_with_sudo(&window, &text_view, &path, encoding, err).await
+            }
+            Ok(Err(err)) => Err(err.to_string()),
+            Err(_) => Err("reading the file failed unexpectedly".to_string()),
+        };
+        match result {
+            Ok((contents, sudo_password)) => on_read(contents, sudo_password),
+            Err(err) => show_error_dialog(
+                &window,
+                "Cannot open file",
+                &format!("{}: {}", path.display(), err),
+            ),
+        }
+    });
+}
+
+// A file we may not read can still be read through sudo, asking for the
+// password the way Sudo Mode does. Declining the prompt reports `err`.
+async fn read_file_with_sudo(
+    window: &gtk::ApplicationWindow,
+    text_view: &sv::View,
+    path: &Path,
+    encoding: Option<String>,
+    err: std::io::Error,
+) -> Result<(String, Option<String>), String> {
+    let Some(password) = prompt_for_password(window) else {
+        return Err(err.to_string());
+    };
+
+    let read_path = path.to_path_buf();
+    let read_password = password.clone();
+    set_loading(window, text_view, true);
+    let result = gtk::gio::spawn_blocking(move || {
+        perform_sudo_read(&read_path, &read_password, encoding.as_deref())
+    })
+    .await;
+    set_loading(window, text_view, false);
+
+    match result {
+        Ok(Ok(contents)) => Ok((contents, Some(password))),
+        Ok(Err(err)) => Err(err),
+        Err(_) => Err("reading the file through sudo failed unexpectedly".to_string()),
+    }
+}
+
+// Show the status bar spinner and lock the editor while a file is loading
+fn set_loading(window: &gtk::ApplicationWindow, text_view: &sv::View, loading: bool) {
+    text_view.set_sensitive(!loading);
+
+    if let Some(status) = status_bar_from_window(window) {
+        status.spinner.set_visible(loading);
+        status.spinner.set_spinning(loading);
+    }
+}
+
+fn set_loaded_contents(window: &gtk::ApplicationWindow, path: &Path, mut contents: String) {
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        let bom = contents.starts_with(BOM);
+        if bom {
+            contents.drain(..BOM.len_utf8());
+        }
+        *doc_state.bom.borrow_mut() = bom;
+        set_action_state(window, "bom", &bom.to_variant());
+
+        // Keep the outgoing file's zoom, then restore the incoming one's
+        remember_zoom(doc_state);
+        if let Some(zoom) = Session::load().file(path).and_then(|file| file.zoom) {
+            set_zoom(doc_state, zoom);
+        }
+
+        *doc_state.is_programmatic.borrow_mut() = true;
+
+        // Decide on highlighting before filling the buffer, so a huge
+        // file isn't highlighted just to have it switched off again
+        let too_large = contents.len() > doc_state.highlight_limit;
+        let long_lines = !too_large && text_ops::longest_line(&contents) > LONG_LINE_LIMIT;
+        *doc_state.highlight_suppressed.borrow_mut() = too_large || long_lines;
+        doc_state
+            .status
+            .highlight_button
+            .set_visible(too_large || long_lines);
+        doc_state
+            .status
+            .highlight_button
+            .set_tooltip_text(Some(if long_lines {
+                "Highlighting was turned off because the file has very long lines"
+            } else {
+                "Highlighting was turned off because the file is large"
+            }));
+
+        if let Some(buffer) = get_source_buffer_from_window(window) {
+            apply_highlighting(&buffer, doc_state);
+            buffer.set_text(&contents);
+            forget_positions(&buffer, doc_state);
+        }
+        set_indentation(window, doc_state, text_ops::detect_indentation(&contents));
+
+        // A fresh history, and no Sudo Mode, for the new file
+        doc_state
+            .update_document(|document| document.load(Some(path.to_path_buf()), contents.clone()));
+        update_undo_redo_actions(window, doc_state);
+        update_save_action(window, doc_state);
+
+        // Update UI state
+        set_sudo_state(window, false);
+
+        // Markdown files open in Markup mode. Going through the mode
+        // action keeps the menu, status label and title in step.
+        if doc_state.mode() == Mode::Plain && is_markdown_path(path) {
+            window.change_action_state("mode", &"markup".to_variant());
+        }
+        flash_status_message(
+            &doc_state.status,
+            &format!("Opened {} at {}", path.display(), clock_time()),
+        );
+
+        *doc_state.is_programmatic.borrow_mut() = false;
+    }
+}
+
+// Fill the current tab with `contents` as an unmodified document that has
+// no path yet, so Save asks where to put it
+fn set_untitled_contents(window: &gtk::ApplicationWindow, contents: &str) {
+    let Some(buffer) = get_text_buffer_from_window(window) else {
+        return;
+    };
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        *doc_state.is_programmatic.borrow_mut() = true;
+        buffer.set_text(contents);
+        buffer.place_cursor(&buffer.start_iter());
+        *doc_state.is_programmatic.borrow_mut() = false;
+
+        doc_state
+            .document
+            .borrow_mut()
+            .set_text(contents.to_string());
+        update_counts(doc_state, &buffer);
+        set_indentation(window, doc_state, text_ops::detect_indentation(contents));
+    }
+}
+
+// Split "name:120" into the name and line 120. Paths that exist as given
+// are left alone, so a file really called "notes:2" still opens.
+fn split_line_suffix(path: PathBuf) -> (PathBuf, Option<u32>) {
+    if path.exists() {
+        return (path, None);
+    }
+
+    let split = path.to_str().and_then(|text| {
+        let (file, line) = text.rsplit_once(':')?;
+        Some((PathBuf::from(file), line.parse::<u32>().ok()?))
+    });
+    match split {
+        Some((file, line)) => (file, Some(line)),
+        None => (path, None),
+    }
+}
+
+fn is_markdown_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"))
+}
+
+// Start a fresh untitled document in this window
+fn reset_to_new_document(window: &gtk::ApplicationWindow) {
+    if let Some(text_buffer) = get_text_buffer_from_window(window) {
+        text_buffer.set_text("");
+
+        if let Some(doc_state) = active_doc_state(window).as_deref() {
+            remember_zoom(doc_state);
+            // No path, no history, no lock and no Sudo Mode
+            doc_state.update_document(|document| document.load(None, String::new()));
+            doc_state.lock.replace(None);
+            set_indentation(window, doc_state, None);
+            *doc_state.bom.borrow_mut() = false;
+            set_action_state(window, "bom", &false.to_variant());
+            set_file_format(window, &FileFormat::default());
+
+            // Update UI state
+            set_sudo_state(window, false);
+            update_undo_redo_actions(window, doc_state);
+            update_save_action(window, doc_state);
+            *doc_state.is_programmatic.borrow_mut() = false;
+
+            // A fresh document gets highlighting back
+            *doc_state.highlight_suppressed.borrow_mut() = false;
+            doc_state.status.highlight_button.set_visible(false);
+            if let Some(buffer) = get_source_buffer_from_window(window) {
+                apply_highlighting(&buffer, doc_state);
+                forget_positions(&buffer, doc_state);
+            }
+        }
+    }
+
+    // The change handler skips edits that leave the text as it was (an
+    // already empty document), so refresh the status bar here
+    refresh_status_counts(window);
+}
+
+// Opens in a new tab unless the current one is empty, so nothing needs
+// discarding first
+fn open_with_dialog(window: &gtk::ApplicationWindow) {
+    let window_clone = window.clone();
+    choose_file(window, "Open File", move |path| {
+        open_file(&window_clone, &path, None, FileFormat::default());
+    });
+}
+
+fn insert_file_with_dialog(window: &gtk::ApplicationWindow) {
+    let window_clone = window.clone();
+    choose_file(window, "Insert File", move |path| {
+        insert_file_at_cursor(&window_clone, &path);
+    });
+}
+
+// File chooser for picking an existing file; `on_chosen` gets its path
+fn choose_file<F: Fn(PathBuf) + 'static>(
+    window: &gtk::ApplicationWindow,
+    title: &str,
+    on_chosen: F,
+) {
+    use gtk::{FileChooserAction, FileFilter, ResponseType};
+
+    let dialog = gtk::FileChooserDialog::new(
+        Some(title),
+        Some(window),
+        FileChooserAction::Open,
+        &[
+            ("_Cancel", ResponseType::Cancel),
+            ("_Open", ResponseType::Accept),
+        ],
+    );
+
+    let text_filter = FileFilter::new();
+    text_filter.set_name(Some("Text Files (*.txt, *.md, *.markdown)"));
+    text_filter.add_pattern("*.txt");
+    text_filter.add_pattern("*.md");
+    text_filter.add_pattern("*.markdown");
+    dialog.add_filter(&text_filter);
+
+    let all_filter = FileFilter::new();
+    all_filter.set_name(Some("All Files"));
+    all_filter.add_pattern("*");
+    dialog.add_filter(&all_filter);
+
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            if let Some(file) = dialog.file() {
+                if let Some(path) = file.path() {
+                    on_chosen(path);
+                }
+            }
+        }
+
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+// (action, title, accelerators)
+type Shortcut = (&'static str, &'static str, &'static [&'static str]);
+
+// Every accelerator rpad registers, grouped as shown in Help → Keyboard
+// Shortcuts. Add new shortcuts here rather than calling
+// set_accels_for_action directly.
+const SHORTCUT_GROUPS: &[(&str, &[Shortcut])] = &[
+    (
+        "File",
+        &[
+            ("win.new", "New", &["<Primary>n"]),
+            ("win.new_tab", "New Tab", &["<Primary>t"]),
+            ("win.open", "Open", &["<Primary>o"]),
+            ("win.save", "Save", &["<Primary>s"]),
+            ("win.save_as", "Save As", &["<Primary><Shift>s"]),
+            ("app.save_all", "Save All", &["<Primary><Alt>s"]),
+            ("win.close_tab", "Close Tab", &["<Primary>w"]),
+            ("app.quit", "Exit", &["<Primary>q"]),
+        ],
+    ),
+    (
+        "Edit",
+        &[
+            ("win.undo", "Undo", &["<Primary>z"]),
+            ("win.redo", "Redo", &["<Primary>y"]),
+            ("win.cut", "Cut", &["<Primary>X"]),
+            ("win.copy", "Copy", &["<Primary>C"]),
+            ("win.paste", "Paste", &["<Primary>V"]),
+            ("win.delete", "Delete", &["Delete"]),
+            ("win.select_all", "Select All", &["<Primary>A"]),
+            ("win.time_date", "Insert Time/Date", &["F5"]),
+            ("win.join_lines", "Join Lines", &["<Primary>j"]),
+            ("win.delete_line", "Delete Line", &["<Primary><Shift>k"]),
+            // Ctrl+T, as in Emacs, is already New Tab
+            (
+                "win.transpose_chars",
+                "Transpose Characters",
+                &["<Primary><Shift>t"],
+            ),
+            ("win.transpose_words", "Transpose Words", &["<Alt>t"]),
+        ],
+    ),
+    (
+        "Find",
+        &[
+            ("win.find", "Find", &["<Primary>F"]),
+            ("win.find_next", "Find Next", &["F3"]),
+            ("win.find_prev", "Find Previous", &["<Shift>F3"]),
+            ("win.replace", "Replace", &["<Primary>H"]),
+            ("win.goto", "Go To Line", &["<Primary>G"]),
+            ("win.last_edit", "Go to Last Edit", &["<Primary><Shift>q"]),
+            (
+                "win.matching_bracket",
+                "Go to Matching Bracket",
+                &["<Primary>m"],
+            ),
+            ("win.toggle_bookmark", "Toggle Bookmark", &["<Primary>F2"]),
+            ("win.next_bookmark", "Next Bookmark", &["F2"]),
+            ("win.previous_bookmark", "Previous Bookmark", &["<Shift>F2"]),
+        ],
+    ),
+    (
+        "Markdown",
+        &[
+            ("win.bold", "Bold", &["<Primary>b"]),
+            ("win.italic", "Italic", &["<Primary>i"]),
+            ("win.insert_link", "Link", &["<Primary>k"]),
+            (
+                "win.heading_increase",
+                "Increase Heading Level",
+                &["<Primary>bracketright"],
+            ),
+            (
+                "win.heading_decrease",
+                "Decrease Heading Level",
+                &["<Primary>bracketleft"],
+            ),
+            ("win.heading_level(1)", "Heading 1", &["<Primary>1"]),
+            ("win.heading_level(2)", "Heading 2", &["<Primary>2"]),
+            ("win.heading_level(3)", "Heading 3", &["<Primary>3"]),
+            ("win.heading_level(4)", "Heading 4", &["<Primary>4"]),
+            ("win.heading_level(5)", "Heading 5", &["<Primary>5"]),
+            ("win.heading_level(6)", "Heading 6", &["<Primary>6"]),
+        ],
+    ),
+    (
+        "View",
+        &[
+            (
+                "win.zoom_in",
+                "Zoom In",
+                &["<Primary>plus", "<Primary>equal"],
+            ),
+            ("win.zoom_out", "Zoom Out", &["<Primary>minus"]),
+            ("win.zoom_reset", "Restore Default Zoom", &["<Primary>0"]),
+        ],
+    ),
+    (
+        "Help",
+        &[
+            ("win.help", "rpad Help", &["F1"]),
+            (
+                "win.shortcuts",
+                "Keyboard Shortcuts",
+                &["<Primary>question"],
+            ),
+        ],
+    ),
+];
+
+// Actions that concern the whole application rather than one window
+fn register_app_actions(app: &gtk::Application) {
+    use gtk::gio::SimpleAction;
+
+    // Quit / Exit: close every window, asking about unsaved documents first
+    let quit = SimpleAction::new("quit", None);
+    let app_for_quit = app.clone();
+    quit.connect_activate(move |_, _| {
+        quit_application(&app_for_quit);
+    });
+    app.add_action(&quit);
+
+    // Save All: every unsaved tab in every window
+    let save_all = SimpleAction::new("save_all", None);
+    let app_clone = app.clone();
+    save_all.connect_activate(move |_, _| {
+        save_all_documents(&app_clone);
+    });
+    app.add_action(&save_all);
+}
+
+// Everything else acts on `window` and its current tab, so each window
+// gets its own set of these
+fn register_actions(app: &gtk::Application, window: &gtk::ApplicationWindow) {
+    use gtk::gio::SimpleAction;
+
+    // ----- File actions -----
+
+    // New (clear current document)
+    let new_doc = SimpleAction::new("new", None);
+    let window_clone = window.clone();
+    new_doc.connect_activate(move |_, _| {
+        let window_for_new = window_clone.clone();
+        confirm_discard(&window_clone, move |_| {
+            reset_to_new_document(&window_for_new)
+        });
+    });
+    window.add_action(&new_doc);
+
+    // New Tab
+    let new_tab = SimpleAction::new("new_tab", None);
+    let window_clone = window.clone();
+    new_tab.connect_activate(move |_, _| {
+        if let Some(config) = active_tab_config(&window_clone) {
+            add_tab(&window_clone, &config);
+        }
+    });
+    window.add_action(&new_tab);
+
+    // Close Tab (dirty-checked; the last one closes the window)
+    let close_tab_action = SimpleAction::new("close_tab", None);
+    let window_clone = window.clone();
+    close_tab_action.connect_activate(move |_, _| {
+        if let Some(page) = current_page(&window_clone) {
+            close_tab(&window_clone, &page);
+        }
+    });
+    window.add_action(&close_tab_action);
+
+    // New Window – another window in this process, set up like the current
+    // tab. Running rpad again is only the fallback.
+    let new_window = SimpleAction::new("new_window", None);
+    let window_clone = window.clone();
+    new_window.connect_activate(move |_, _| {
+        if let (Some(app), Some(config)) =
+            (window_clone.application(), active_tab_config(&window_clone))
+        {
+            build_ui(&app, config);
+            return;
+        }
+
+        // Try to get the current executable path
+        match std::env::current_exe() {
+            Ok(exe_path) => {
+                if let Err(err) = Command::new(exe_path).spawn() {
+                    eprintln!("Failed to open new window: {err}");
+                }
+            }
+            Err(err) => {
+                eprintln!("Could not determine current executable for New Window: {err}");
+            }
+        }
+    });
+    window.add_action(&new_window);
+
+    // Save
+    let save = SimpleAction::new("save", None);
+    let window_clone = window.clone();
+    save.connect_activate(move |_, _| {
+        if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+            if let Some(path) = doc_state.path() {
+                if let Err(err) = save_buffer_to_path(&window_clone, &path) {
+                    eprintln!("Error saving file: {err}");
+                }
+            } else {
+                // No path yet → behave like "Save As"
+                save_as_with_dialog(&window_clone, || {});
+            }
+        } else {
+            // No state stored? Fallback to "Save As"
+            save_as_with_dialog(&window_clone, || {});
+        }
+    });
+    window.add_action(&save);
+
+    // Save As…
+    let save_as = SimpleAction::new("save_as", None);
+    let window_clone = window.clone();
+    save_as.connect_activate(move |_, _| {
+        save_as_with_dialog(&window_clone, || {});
+    });
+    window.add_action(&save_as);
+
+    // Byte Order Mark: whether the current document is saved with one
+    let initial_bom = active_doc_state(window).is_some_and(|doc_state| *doc_state.bom.borrow());
+    let bom = SimpleAction::new_stateful("bom", None, &initial_bom.to_variant());
+    let window_clone = window.clone();
+    bom.connect_change_state(move |action, state| {
+        if let (Some(state), Some(doc_state)) = (state, active_doc_state(&window_clone)) {
+            let with_bom = state.get::<bool>().unwrap_or(false);
+            action.set_state(state);
+            if *doc_state.bom.borrow() != with_bom {
+                *doc_state.bom.borrow_mut() = with_bom;
+                // The file on disk no longer matches what a save would write
+                doc_state.set_dirty(true);
+                update_save_action(&window_clone, &doc_state);
+            }
+        }
+    });
+    window.add_action(&bom);
+
+    // File → Open Containing Folder; only enabled for a saved file
+    let open_containing_folder = SimpleAction::new("open_containing_folder", None);
+    let window_clone = window.clone();
+    open_containing_folder.connect_activate(move |_, _| {
+        let path = active_doc_state(&window_clone).and_then(|doc_state| doc_state.path());
+        let Some(folder) = path.as_deref().and_then(Path::parent) else {
+            return;
+        };
+        let uri = gtk::gio::File::for_path(folder).uri();
+        let context = WidgetExt::display(&window_clone).app_launch_context();
+        if let Err(err) = gtk::gio::AppInfo::launch_default_for_uri(&uri, Some(&context)) {
+            show_error_dialog(
+                &window_clone,
+                "Couldn't open the folder",
+                &format!("{}: {}", folder.display(), err),
+            );
+        }
+    });
+    window.add_action(&open_containing_folder);
+
+    // File → Copy File Path
+    let copy_path = SimpleAction::new("copy_path", None);
+    let window_clone = window.clone();
+    copy_path.connect_activate(move |_, _| {
+        if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+            match doc_state.path() {
+                Some(path) => {
+                    window_clone.clipboard().set_text(&path.to_string_lossy());
+                    flash_status_message(&doc_state.status, "Path copied");
+                }
+                None => flash_status_message(&doc_state.status, "No file"),
+            }
+        }
+    });
+    window.add_action(&copy_path);
+
+    // Open
+    let open = SimpleAction::new("open", None);
+    let window_clone = window.clone();
+    open.connect_activate(move |_, _| {
+        open_with_dialog(&window_clone);
+    });
+    window.add_action(&open);
+
+    // Print
+    let print = SimpleAction::new("print", None);
+    {
+        let window_clone = window.clone();
+        print.connect_activate(move |_, _| {
+            let Some(text_view) = get_text_view_from_window(&window_clone) else {
+                return;
+            };
+            let op = gtk::PrintOperation::new();
+            op.set_job_name("rpad-print-job");
+
+            let compositor = sv::PrintCompositor::from_view(&text_view);
+
+            let compositor_clone = compositor.clone();
+            op.connect_begin_print(move |op, context| {
+                let compositor = compositor_clone.clone();
+                while !compositor.paginate(context) {
+                    // spin loop or rely on internal iterations?
+                    // Documentation suggests paginate() does a chunk of work.
+                    // Usually we need to keep calling it until TRUE.
+                }
+                op.set_n_pages(compositor.n_pages());
+            });
+
+            let compositor_clone = compositor.clone();
+            op.connect_draw_page(move |_op, context, page_nr| {
+                compositor_clone.draw_page(context, page_nr);
+            });
+
+            let res = op.run(gtk::PrintOperationAction::PrintDialog, Some(&window_clone));
+            if let Err(e) = res {
+                eprintln!("Error printing: {}", e);
+            }
+        });
+    }
+    window.add_action(&print);
+
+    // ----- Edit actions (stubs) -----
+    // Undo
+    let undo = SimpleAction::new("undo", None);
+    let window_clone = window.clone();
+    undo.connect_activate(move |_, _| {
+        if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+            let prev_text = doc_state.update_document(Document::undo);
+            if let Some(prev_text) = prev_text {
+                // Apply previous text without recording as a new undo entry
+                *doc_state.is_programmatic.borrow_mut() = true;
+                if let Some(buffer) = get_text_buffer_from_window(&window_clone) {
+                    replace_changed_text(&buffer, &prev_text);
+                    remember_last_edit(&buffer, doc_state);
+                }
+                *doc_state.is_programmatic.borrow_mut() = false;
+            }
+            update_undo_redo_actions(&window_clone, doc_state);
+            update_save_action(&window_clone, doc_state);
+        }
+    });
+    window.add_action(&undo);
+
+    // Redo
+    let redo = SimpleAction::new("redo", None);
+    let window_clone = window.clone();
+    redo.connect_activate(move |_, _| {
+        if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+            let next_text = doc_state.update_document(Document::redo);
+            if let Some(next_text) = next_text {
+                // Apply next text without recording as a new undo entry
+                *doc_state.is_programmatic.borrow_mut() = true;
+                if let Some(buffer) = get_text_buffer_from_window(&window_clone) {
+                    replace_changed_text(&buffer, &next_text);
+                    remember_last_edit(&buffer, doc_state);
+                }
+                *doc_state.is_programmatic.borrow_mut() = false;
+            }
+            update_undo_redo_actions(&window_clone, doc_state);
+            update_save_action(&window_clone, doc_state);
+        }
+    });
+    window.add_action(&redo);
+
+    // ----- Clipboard actions -----
+    // CUT
+    let cut = SimpleAction::new("cut", None);
+    {
+        let window_clone = window.clone();
+        cut.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                text_view.emit_by_name::<()>("cut-clipboard", &[]);
+            }
+        });
+    }
+    window.add_action(&cut);
+
+    // COPY
+    let copy = SimpleAction::new("copy", None);
+    {
+        let window_clone = window.clone();
+        copy.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                text_view.emit_by_name::<()>("copy-clipboard", &[]);
+            }
+        });
+    }
+    window.add_action(&copy);
+
+    // PASTE
+    let paste = SimpleAction::new("paste", None);
+    {
+        let window_clone = window.clone();
+        paste.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                text_view.emit_by_name::<()>("paste-clipboard", &[]);
+            }
+        });
+    }
+    window.add_action(&paste);
+
+    // Paste Special: the clipboard's text, unchanged or as a Markdown quote
+    let paste_plain = SimpleAction::new("paste_plain", None);
+    let window_clone = window.clone();
+    paste_plain.connect_activate(move |_, _| {
+        paste_transformed(&window_clone, str::to_string);
+    });
+    window.add_action(&paste_plain);
+
+    let paste_quote = SimpleAction::new("paste_quote", None);
+    let window_clone = window.clone();
+    paste_quote.connect_activate(move |_, _| {
+        paste_transformed(&window_clone, text_ops::quote_lines);
+    });
+    window.add_action(&paste_quote);
+
+    // DELETE selection
+    let delete = SimpleAction::new("delete", None);
+    {
+        let window_clone = window.clone();
+        delete.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                let buffer = text_view.buffer();
+                buffer.delete_selection(true, true);
+            }
+        });
+    }
+    window.add_action(&delete);
+
+    // ----- Find / Replace / Go To -----
+    // Find…
+    let find = SimpleAction::new("find", None);
+    {
+        let window_clone = window.clone();
+        find.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                open_find_dialog(&window_clone, &text_view);
+            }
+        });
+    }
+    window.add_action(&find);
+
+    // Find Next
+    let find_next = SimpleAction::new("find_next", None);
+    {
+        let window_clone = window.clone();
+        find_next.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                do_find_next(&window_clone, &text_view);
+            }
+        });
+    }
+    window.add_action(&find_next);
+
+    // Find Previous
+    let find_prev = SimpleAction::new("find_prev", None);
+    {
+        let window_clone = window.clone();
+        find_prev.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                do_find_prev(&window_clone, &text_view);
+            }
+        });
+    }
+    window.add_action(&find_prev);
+
+    // Replace…
+    let replace = SimpleAction::new("replace", None);
+    {
+        let window_clone = window.clone();
+        replace.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                open_replace_dialog(&window_clone, &text_view);
+            }
+        });
+    }
+    window.add_action(&replace);
+
+    // Go To…
+    let goto = SimpleAction::new("goto", None);
+    {
+        let window_clone = window.clone();
+        goto.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                open_goto_dialog(&window_clone, &text_view);
+            }
+        });
+    }
+    window.add_action(&goto);
+
+    // Go to Last Edit
+    let last_edit = SimpleAction::new("last_edit", None);
+    let window_clone = window.clone();
+    last_edit.connect_activate(move |_, _| {
+        if let (Some(text_view), Some(doc_state)) = (
+            get_text_view_from_window(&window_clone),
+            active_doc_state(&window_clone),
+        ) {
+            if let Some(mark) = doc_state.last_edit.borrow().as_ref() {
+                let buffer = text_view.buffer();
+                buffer.place_cursor(&buffer.iter_at_mark(mark));
+                text_view.scroll_to_mark(mark, 0.1, false, 0.0, 0.0);
+            }
+        }
+    });
+    window.add_action(&last_edit);
+
+    // Go to Matching Bracket
+    let matching_bracket = SimpleAction::new("matching_bracket", None);
+    let window_clone = window.clone();
+    matching_bracket.connect_activate(move |_, _| {
+        if let Some(text_view) = get_text_view_from_window(&window_clone) {
+            let buffer = text_view.buffer();
+            let (start, end) = buffer.bounds();
+            let text = buffer.text(&start, &end, true);
+            let cursor = buffer.iter_at_mark(&buffer.get_insert()).offset() as usize;
+
+            if let Some(offset) = text_ops::matching_bracket(&text, cursor) {
+                let mut iter = buffer.iter_at_offset(offset as i32);
+                buffer.place_cursor(&iter);
+                text_view.scroll_to_iter(&mut iter, 0.1, false, 0.0, 0.0);
+            }
+        }
+    });
+    window.add_action(&matching_bracket);
+
+    // Tools → Show Changes
+    let show_changes = SimpleAction::new("show_changes", None);
+    let window_clone = window.clone();
+    show_changes.connect_activate(move |_, _| {
+        open_changes_dialog(&window_clone);
+    });
+    window.add_action(&show_changes);
+
+    // Tools → Tasks
+    let tasks = SimpleAction::new("tasks", None);
+    let window_clone = window.clone();
+    tasks.connect_activate(move |_, _| {
+        open_tasks_dialog(&window_clone);
+    });
+    window.add_action(&tasks);
+
+    // Tools → Statistics
+    let statistics = SimpleAction::new("statistics", None);
+    let window_clone = window.clone();
+    statistics.connect_activate(move |_, _| {
+        open_statistics_dialog(&window_clone);
+    });
+    window.add_action(&statistics);
+
+    // Tools → Normalize Line Endings
+    let normalize_line_endings = SimpleAction::new("normalize_line_endings", None);
+    let window_clone = window.clone();
+    normalize_line_endings.connect_activate(move |_, _| {
+        open_line_endings_dialog(&window_clone);
+    });
+    window.add_action(&normalize_line_endings);
+
+    // Bookmarks
+    let toggle_bookmark_action = SimpleAction::new("toggle_bookmark", None);
+    let window_clone = window.clone();
+    toggle_bookmark_action.connect_activate(move |_, _| {
+        if let (Some(buffer), Some(doc_state)) = (
+            get_source_buffer_from_window(&window_clone),
+            active_doc_state(&window_clone).as_deref(),
+        ) {
+            toggle_bookmark(&buffer, doc_state);
+        }
+    });
+    window.add_action(&toggle_bookmark_action);
+
+    for (name, forward) in [("next_bookmark", true), ("previous_bookmark", false)] {
+        let action = SimpleAction::new(name, None);
+        let window_clone = window.clone();
+        action.connect_activate(move |_, _| {
+            if let (Some(text_view), Some(doc_state)) = (
+                get_text_view_from_window(&window_clone),
+                active_doc_state(&window_clone).as_deref(),
+            ) {
+                goto_bookmark(&text_view, doc_state, forward);
+            }
+        });
+        window.add_action(&action);
+    }
+
+    // Select All
+    let select_all = SimpleAction::new("select_all", None);
+    {
+        let window_clone = window.clone();
+        select_all.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                let buffer = text_view.buffer();
+                let (start, end) = buffer.bounds();
+                buffer.select_range(&start, &end);
+            }
+        });
+    }
+    window.add_action(&select_all);
+
+    // Clear: empty the document as one undo step. Unlike New it stays the
+    // same file, so saving writes the empty text back (with sudo if on).
+    let clear = SimpleAction::new("clear", None);
+    {
+        let window_clone = window.clone();
+        clear.connect_activate(move |_, _| {
+            if let Some(buffer) = get_text_buffer_from_window(&window_clone) {
+                let (mut start, mut end) = buffer.bounds();
+                if start != end {
+                    replace_range(&buffer, &mut start, &mut end, "");
+                }
+            }
+        });
+    }
+    window.add_action(&clear);
+
+    // Time/Date (insert at cursor, like Notepad's F5)
+    let time_date = SimpleAction::new("time_date", None);
+    {
+        let window_clone = window.clone();
+        time_date.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                let buffer = text_view.buffer();
+
+                // now_local -> Result<DateTime, BoolError>
+                if let Ok(now) = glib::DateTime::now_local() {
+                    // format -> Result<GString, BoolError>
+                    if let Ok(stamp) = now.format("%Y-%m-%d %H:%M") {
+                        // GString derefs to &str, so this is fine
+                        buffer.insert_at_cursor(&stamp);
+                    } else {
+                        buffer.insert_at_cursor("0000-00-00 00:00");
+                    }
+                } else {
+                    buffer.insert_at_cursor("0000-00-00 00:00");
+                }
+            }
+        });
+    }
+    window.add_action(&time_date);
+
+    // Insert File…
+    let insert_file = SimpleAction::new("insert_file", None);
+    {
+        let window_clone = window.clone();
+        insert_file.connect_activate(move |_, _| {
+            insert_file_with_dialog(&window_clone);
+        });
+    }
+    window.add_action(&insert_file);
+
+    // Join Lines: the selected lines, or the current line with the next
+    let join_lines = SimpleAction::new("join_lines", None);
+    {
+        let window_clone = window.clone();
+        join_lines.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                let buffer = text_view.buffer();
+                let bounds = buffer.selection_bounds().or_else(|| {
+                    let line = buffer.iter_at_mark(&buffer.get_insert()).line();
+                    let start = buffer.iter_at_line(line)?;
+                    let mut end = buffer.iter_at_line(line + 1)?;
+                    end.forward_to_line_end();
+                    Some((start, end))
+                });
+
+                if let Some((mut start, mut end)) = bounds {
+                    let text = buffer.text(&start, &end, true);
+                    let joined = text_ops::join_lines(&text);
+                    if joined != text.as_str() {
+                        replace_range(&buffer, &mut start, &mut end, &joined);
+                    }
+                }
+            }
+        });
+    }
+    window.add_action(&join_lines);
+
+    // Delete Line: the cursor's line and its newline, keeping the column
+    let delete_line = SimpleAction::new("delete_line", None);
+    {
+        let window_clone = window.clone();
+        delete_line.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                let buffer = text_view.buffer();
+                let cursor = buffer.iter_at_mark(&buffer.get_insert());
+                let column = cursor.line_offset();
+
+                let mut start = cursor;
+                start.set_line_offset(0);
+                let mut end = start;
+                if !end.forward_line() {
+                    // Last line: take the newline before it instead
+                    start.backward_char();
+                }
+                if start == end {
+                    return;
+                }
+
+                buffer.begin_user_action();
+                buffer.delete(&mut start, &mut end);
+                buffer.end_user_action();
+
+                let mut target = buffer.iter_at_mark(&buffer.get_insert());
+                target.set_line_offset(0);
+                let mut line_end = target;
+                if !line_end.ends_line() {
+                    line_end.forward_to_line_end();
+                }
+                target.set_line_offset(column.min(line_end.line_offset()));
+                buffer.place_cursor(&target);
+            }
+        });
+    }
+    window.add_action(&delete_line);
+
+    // Transpose: swap the characters or words around the cursor, Emacs style
+    let transpose_chars_action = SimpleAction::new("transpose_chars", None);
+    {
+        let window_clone = window.clone();
+        transpose_chars_action.connect_activate(move |_, _| {
+            if let Some(buffer) = get_text_buffer_from_window(&window_clone) {
+                transpose_chars(&buffer);
+            }
+        });
+    }
+    window.add_action(&transpose_chars_action);
+
+    let transpose_words_action = SimpleAction::new("transpose_words", None);
+    {
+        let window_clone = window.clone();
+        transpose_words_action.connect_activate(move |_, _| {
+            if let Some(buffer) = get_text_buffer_from_window(&window_clone) {
+                transpose_words(&buffer);
+            }
+        });
+    }
+    window.add_action(&transpose_words_action);
+
+    // Markdown emphasis and links around the selection (Markup mode only)
+    for (name, emphasis) in [
+        ("bold", markdown::Emphasis::Bold),
+        ("italic", markdown::Emphasis::Italic),
+    ] {
+        let action = SimpleAction::new(name, None);
+        let window_clone = window.clone();
+        action.connect_activate(move |_, _| {
+            if let Some(buffer) = get_text_buffer_from_window(&window_clone) {
+                toggle_emphasis(&buffer, emphasis);
+            }
+        });
+        window.add_action(&action);
+    }
+
+    let insert_link = SimpleAction::new("insert_link", None);
+    let window_clone = window.clone();
+    insert_link.connect_activate(move |_, _| {
+        if let Some(text_view) = get_text_view_from_window(&window_clone) {
+            open_link_dialog(&window_clone, &text_view);
+        }
+    });
+    window.add_action(&insert_link);
+
+    let insert_table = SimpleAction::new("insert_table", None);
+    let window_clone = window.clone();
+    insert_table.connect_activate(move |_, _| {
+        if let Some(text_view) = get_text_view_from_window(&window_clone) {
+            open_table_dialog(&window_clone, &text_view);
+        }
+    });
+    window.add_action(&insert_table);
+
+    // Heading level of the current line: one more or fewer `#`, or a set
+    // level (0 for normal text)
+    let heading_level = SimpleAction::new("heading_level", Some(glib::VariantTy::INT32));
+    let window_clone = window.clone();
+    heading_level.connect_activate(move |_, parameter| {
+        let level = parameter.and_then(|p| p.get::<i32>()).unwrap_or(0);
+        if let Some(buffer) = get_text_buffer_from_window(&window_clone) {
+            change_heading_level(&buffer, |_| level.max(0) as usize);
+        }
+    });
+    window.add_action(&heading_level);
+
+    for (name, step) in [("heading_increase", 1), ("heading_decrease", -1)] {
+        let action = SimpleAction::new(name, None);
+        let window_clone = window.clone();
+        action.connect_activate(move |_, _| {
+            if let Some(buffer) = get_text_buffer_from_window(&window_clone) {
+                change_heading_level(&buffer, |current| current.saturating_add_signed(step));
+            }
+        });
+        window.add_action(&action);
+    }
+
+    // Reverse / Shuffle Lines: the selection, or the whole document
+    let reverse_lines = SimpleAction::new("reverse_lines", None);
+    {
+        let window_clone = window.clone();
+        reverse_lines.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                transform_selection_or_document(&text_view.buffer(), text_ops::reverse_lines);
+            }
+        });
+    }
+    window.add_action(&reverse_lines);
+
+    let shuffle_lines = SimpleAction::new("shuffle_lines", None);
+    {
+        let window_clone = window.clone();
+        shuffle_lines.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                transform_selection_or_document(&text_view.buffer(), |text| {
+                    text_ops::shuffle_lines(text, &mut rand::thread_rng())
+                });
+            }
+        });
+    }
+    window.add_action(&shuffle_lines);
+
+    // Convert Indentation: always the whole document, at the view's tab width
+    let tabs_to_spaces = SimpleAction::new("tabs_to_spaces", None);
+    {
+        let window_clone = window.clone();
+        tabs_to_spaces.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                let tab_width = text_view.tab_width();
+                transform_document(&text_view.buffer(), |text| {
+                    text_ops::tabs_to_spaces(text, tab_width)
+                });
+                if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+                    set_indentation(
+                        &window_clone,
+                        doc_state,
+                        Some(text_ops::Indent::Spaces(tab_width)),
+                    );
+                }
+            }
+        });
+    }
+    window.add_action(&tabs_to_spaces);
+
+    let spaces_to_tabs = SimpleAction::new("spaces_to_tabs", None);
+    {
+        let window_clone = window.clone();
+        spaces_to_tabs.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                let tab_width = text_view.tab_width();
+                transform_document(&text_view.buffer(), |text| {
+                    text_ops::spaces_to_tabs(text, tab_width)
+                });
+                if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+                    set_indentation(&window_clone, doc_state, Some(text_ops::Indent::Tabs));
+                }
+            }
+        });
+    }
+    window.add_action(&spaces_to_tabs);
+
+    // Change Case (selection only)
+    let upper_case = SimpleAction::new("upper_case", None);
+    {
+        let window_clone = window.clone();
+        upper_case.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                transform_selection(&text_view.buffer(), |text| text.to_uppercase());
+            }
+        });
+    }
+    window.add_action(&upper_case);
+
+    let lower_case = SimpleAction::new("lower_case", None);
+    {
+        let window_clone = window.clone();
+        lower_case.connect_activate(move |_, _| {
+            if let Some(text_view) = get_text_view_from_window(&window_clone) {
+                transform_selection(&text_view.buffer(), |text| text.to_lowercase());
+            }
+        });
+    }
+    window.add_action(&lower_case);
+
+    // ----- View actions (stubs) -----
+    // Zoom In
+    let zoom_in = SimpleAction::new("zoom_in", None);
+    let window_clone = window.clone();
+    zoom_in.connect_activate(move |_, _| {
+        if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+            let current = *doc_state.zoom.borrow();
+            set_zoom(doc_state, current + doc_state.zoom_step);
+        }
+    });
+    window.add_action(&zoom_in);
+
+    // Zoom Out
+    let zoom_out = SimpleAction::new("zoom_out", None);
+    let window_clone = window.clone();
+    zoom_out.connect_activate(move |_, _| {
+        if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+            let current = *doc_state.zoom.borrow();
+            set_zoom(doc_state, current.saturating_sub(doc_state.zoom_step));
+        }
+    });
+    window.add_action(&zoom_out);
+
+    // Zoom Reset
+    let zoom_reset = SimpleAction::new("zoom_reset", None);
+    let window_clone = window.clone();
+    zoom_reset.connect_activate(move |_, _| {
+        if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+            set_zoom(doc_state, ZOOM_DEFAULT);
+        }
+    });
+    window.add_action(&zoom_reset);
+
+    // Zoom to a specific percentage
+    let zoom_to = SimpleAction::new("zoom_to", None);
+    let window_clone = window.clone();
+    zoom_to.connect_activate(move |_, _| {
+        open_zoom_dialog(&window_clone);
+    });
+    window.add_action(&zoom_to);
+
+    let status_bar = SimpleAction::new_stateful(
+        "status_bar",
+        None,
+        &true.to_variant(), // Default to true (visible)
+    );
+    let window_clone = window.clone();
+    status_bar.connect_change_state(move |action, state| {
+        if let Some(state) = state {
+            action.set_state(state); // Update action state
+            let visible = state.get::<bool>().unwrap_or(true);
+
+            if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+                doc_state.status.status_box.set_visible(visible);
+            }
+        }
+    });
+    window.add_action(&status_bar);
+
+    // Minimap: shown or hidden in every tab at once
+    let minimap_visible = current_page(window)
+        .and_then(|page| minimap_of_page(&page))
+        .is_some_and(|map| map.is_visible());
+    let minimap = SimpleAction::new_stateful("minimap", None, &minimap_visible.to_variant());
+    let window_clone = window.clone();
+    minimap.connect_change_state(move |action, state| {
+        if let Some(state) = state {
+            action.set_state(state);
+            let visible = state.get::<bool>().unwrap_or(false);
+            for page in tab_pages(&window_clone) {
+                if let Some(map) = minimap_of_page(&page) {
+                    map.set_visible(visible);
+                }
+            }
+            Config::update(|c| c.minimap = Some(visible));
+        }
+    });
+    window.add_action(&minimap);
+
+    // Right margin guide, remembered in the config and applied to every tab
+    let initial_right_margin = active_doc_state(window)
+        .map(|doc_state| *doc_state.right_margin.borrow())
+        .unwrap_or(false);
+    let right_margin =
+        SimpleAction::new_stateful("right_margin", None, &initial_right_margin.to_variant());
+    let window_clone = window.clone();
+    right_margin.connect_change_state(move |action, state| {
+        if let Some(state) = state {
+            action.set_state(state);
+            let shown = state.get::<bool>().unwrap_or(false);
+            let column = active_doc_state(&window_clone)
+                .map(|doc_state| *doc_state.right_margin_column.borrow())
+                .unwrap_or(DEFAULT_RIGHT_MARGIN_COLUMN);
+            set_right_margin_everywhere(&window_clone, shown, column);
+            Config::update(|c| c.right_margin = Some(shown));
+        }
+    });
+    window.add_action(&right_margin);
+
+    // Indent guides, remembered in the config and applied to every tab
+    let initial_indent_guides =
+        active_doc_state(window).is_some_and(|doc_state| *doc_state.indent_guides.borrow());
+    let indent_guides =
+        SimpleAction::new_stateful("indent_guides", None, &initial_indent_guides.to_variant());
+    let window_clone = window.clone();
+    indent_guides.connect_change_state(move |action, state| {
+        if let Some(state) = state {
+            action.set_state(state);
+            let shown = state.get::<bool>().unwrap_or(false);
+            set_indent_guides_everywhere(&window_clone, shown);
+            Config::update(|c| c.indent_guides = Some(shown));
+        }
+    });
+    window.add_action(&indent_guides);
+
+    // Cursor blinking, a display-wide GTK setting remembered in the config
+    let initial_cursor_blink =
+        gtk::Settings::default().is_none_or(|settings| settings.is_gtk_cursor_blink());
+    let cursor_blink =
+        SimpleAction::new_stateful("cursor_blink", None, &initial_cursor_blink.to_variant());
+    cursor_blink.connect_change_state(move |action, state| {
+        if let Some(state) = state {
+            action.set_state(state);
+            let blink = state.get::<bool>().unwrap_or(true);
+            apply_cursor_blink(Some(blink));
+            Config::update(|c| c.cursor_blink = Some(blink));
+        }
+    });
+    window.add_action(&cursor_blink);
+
+    // Ln/Col after the window title, for window managers that show it
+    let initial_cursor_in_title =
+        active_doc_state(window).is_some_and(|doc_state| *doc_state.cursor_in_title.borrow());
+    let cursor_in_title = SimpleAction::new_stateful(
+        "cursor_in_title",
+        None,
+        &initial_cursor_in_title.to_variant(),
+    );
+    let window_clone = window.clone();
+    cursor_in_title.connect_change_state(move |action, state| {
+        if let Some(state) = state {
+            action.set_state(state);
+            let enabled = state.get::<bool>().unwrap_or(false);
+            for page in tab_pages(&window_clone) {
+                if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                    *doc_state.cursor_in_title.borrow_mut() = enabled;
+                }
+            }
+            update_title(&window_clone);
+            Config::update(|c| c.cursor_in_title = Some(enabled));
+        }
+    });
+    window.add_action(&cursor_in_title);
+
+    let right_margin_column = SimpleAction::new("right_margin_column", None);
+    let window_clone = window.clone();
+    right_margin_column.connect_activate(move |_, _| {
+        open_right_margin_dialog(&window_clone);
+    });
+    window.add_action(&right_margin_column);
+
+    // Split: a second view of the current tab's document
+    let split_view = SimpleAction::new_stateful("split_view", None, &false.to_variant());
+    let window_clone = window.clone();
+    split_view.connect_change_state(move |action, state| {
+        if let Some(state) = state {
+            action.set_state(state);
+            set_split(&window_clone, state.get::<bool>().unwrap_or(false));
+        }
+    });
+    window.add_action(&split_view);
+
+    // Spell Check toggle and dictionary, both remembered in the config and
+    // applied to every tab
+    let spell_adapter = get_spell_adapter_from_window(window);
+    let spell_check = SimpleAction::new_stateful(
+        "spell_check",
+        None,
+        &spell_adapter
+            .as_ref()
+            .is_some_and(|adapter| adapter.is_enabled())
+            .to_variant(),
+    );
+    {
+        let window_clone = window.clone();
+        spell_check.connect_change_state(move |action, state| {
+            if let Some(state) = state {
+                action.set_state(state);
+                let enabled = state.get::<bool>().unwrap_or(false);
+                for page in tab_pages(&window_clone) {
+                    if let Some(adapter) = spell_adapter_of_page(&page) {
+                        adapter.set_enabled(enabled);
+                    }
+                }
+                Config::update(|c| c.spell_check = Some(enabled));
+            }
+        });
+    }
+    window.add_action(&spell_check);
+
+    let spell_language = SimpleAction::new_stateful(
+        "spell_language",
+        Some(glib::VariantTy::STRING),
+        &spell_adapter
+            .as_ref()
+            .map(|adapter| adapter.language().to_string())
+            .unwrap_or_default()
+            .to_variant(),
+    );
+    {
+        let window_clone = window.clone();
+        spell_language.connect_change_state(move |action, value| {
+            if let Some(value) = value {
+                let code = value.str().unwrap_or_default().to_string();
+                action.set_state(value);
+                for page in tab_pages(&window_clone) {
+                    if let Some(adapter) = spell_adapter_of_page(&page) {
+                        adapter.set_language(&code);
+                    }
+                }
+                Config::update(|c| c.spell_language = Some(code));
+            }
+        });
+    }
+    window.add_action(&spell_language);
+
+    // Auto-pairing of brackets and quotes, remembered in the config and
+    // applied to every tab
+    let initial_auto_pair = active_doc_state(window)
+        .map(|doc_state| *doc_state.auto_pair.borrow())
+        .unwrap_or(true);
+    let auto_pair = SimpleAction::new_stateful("auto_pair", None, &initial_auto_pair.to_variant());
+    {
+        let window_clone = window.clone();
+        auto_pair.connect_change_state(move |action, state| {
+            if let Some(state) = state {
+                action.set_state(state);
+                let enabled = state.get::<bool>().unwrap_or(true);
+                for page in tab_pages(&window_clone) {
+                    if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                        *doc_state.auto_pair.borrow_mut() = enabled;
+                    }
+                }
+                Config::update(|c| c.auto_pair = Some(enabled));
+            }
+        });
+    }
+    window.add_action(&auto_pair);
+
+    // Smart Quotes, remembered in the config and applied to every tab
+    let initial_smart_quotes =
+        active_doc_state(window).is_some_and(|doc_state| *doc_state.smart_quotes.borrow());
+    let smart_quotes =
+        SimpleAction::new_stateful("smart_quotes", None, &initial_smart_quotes.to_variant());
+    let window_clone = window.clone();
+    smart_quotes.connect_change_state(move |action, state| {
+        if let Some(state) = state {
+            action.set_state(state);
+            let enabled = state.get::<bool>().unwrap_or(false);
+            for page in tab_pages(&window_clone) {
+                if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                    *doc_state.smart_quotes.borrow_mut() = enabled;
+                }
+            }
+            Config::update(|c| c.smart_quotes = Some(enabled));
+        }
+    });
+    window.add_action(&smart_quotes);
+
+    // Vim-style modal editing, switched for every tab at once
+    let initial_vim =
+        active_doc_state(window).is_some_and(|doc_state| doc_state.vim.borrow().is_some());
+    let vim_action = SimpleAction::new_stateful("vim", None, &initial_vim.to_variant());
+    {
+        let window_clone = window.clone();
+        vim_action.connect_change_state(move |action, state| {
+            if let Some(state) = state {
+                action.set_state(state);
+                let enabled = state.get::<bool>().unwrap_or(false);
+                for page in tab_pages(&window_clone) {
+                    if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                        *doc_state.vim.borrow_mut() = enabled.then(vim::Vim::default);
+                        update_vim_cursor(&page, doc_state);
+                    }
+                }
+                if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+                    update_vim_status(doc_state);
+                }
+            }
+        });
+    }
+    window.add_action(&vim_action);
+
+    // Accessibility mode, for every tab in the window
+    let initial_accessible =
+        active_doc_state(window).is_some_and(|doc_state| *doc_state.accessible.borrow());
+    let accessible =
+        SimpleAction::new_stateful("accessible", None, &initial_accessible.to_variant());
+    let window_clone = window.clone();
+    accessible.connect_change_state(move |action, state| {
+        if let Some(state) = state {
+            action.set_state(state);
+            let enabled = state.get::<bool>().unwrap_or(false);
+            for page in tab_pages(&window_clone) {
+                if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                    *doc_state.accessible.borrow_mut() = enabled;
+                    apply_accessible(&page, doc_state);
+                }
+            }
+        }
+    });
+    window.add_action(&accessible);
+
+    // Whitespace glyphs, remembered in the config and applied to every tab
+    let initial_whitespace = active_doc_state(window)
+        .map(|doc_state| doc_state.show_whitespace.borrow().clone())
+        .unwrap_or_else(|| "none".to_string());
+    let show_whitespace = SimpleAction::new_stateful(
+        "show_whitespace",
+        Some(glib::VariantTy::STRING),
+        &initial_whitespace.to_variant(),
+    );
+    {
+        let window_clone = window.clone();
+        show_whitespace.connect_change_state(move |action, value| {
+            if let Some(which) = value.and_then(|v| v.str()) {
+                action.set_state(&which.to_variant());
+                for page in tab_pages(&window_clone) {
+                    if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                        *doc_state.show_whitespace.borrow_mut() = which.to_string();
+                    }
+                    for text_view in views_of_page(&page) {
+                        apply_show_whitespace(&text_view, which);
+                    }
+                }
+                let which = which.to_string();
+                Config::update(|c| c.show_whitespace = Some(which));
+            }
+        });
+    }
+    window.add_action(&show_whitespace);
+
+    // Line numbers, remembered in the config and applied to every tab
+    let initial_line_numbers = active_doc_state(window)
+        .map(|doc_state| doc_state.line_numbers.borrow().clone())
+        .unwrap_or_else(|| "none".to_string());
+    let line_numbers = SimpleAction::new_stateful(
+        "line_numbers",
+        Some(glib::VariantTy::STRING),
+        &initial_line_numbers.to_variant(),
+    );
+    {
+        let window_clone = window.clone();
+        line_numbers.connect_change_state(move |action, value| {
+            if let Some(which) = value.and_then(|v| v.str()) {
+                action.set_state(&which.to_variant());
+                for page in tab_pages(&window_clone) {
+                    if let Some(doc_state) = doc_state_of(&page).as_deref() {
+                        *doc_state.line_numbers.borrow_mut() = which.to_string();
+                    }
+                    for text_view in views_of_page(&page) {
+                        apply_line_numbers(&text_view, which);
+                    }
+                }
+                let which = which.to_string();
+                Config::update(|c| c.line_numbers = Some(which));
+            }
+        });
+    }
+    window.add_action(&line_numbers);
+
+    // ----- Mode actions -----
+    // ----- Mode actions -----
+    // Stateful "mode" action
+    let initial_mode_str = if let Some(doc_state) = active_doc_state(window).as_deref() {
+        match doc_state.mode() {
+            Mode::Plain => "plain",
+            Mode::Markup => "markup",
+        }
+    } else {
+        "plain"
+    };
+
+    let mode_action = SimpleAction::new_stateful(
+        "mode",
+        Some(glib::VariantTy::STRING),
+        &initial_mode_str.to_variant(),
+    );
+
+    {
+        let window_clone = window.clone();
+        mode_action.connect_change_state(move |action, value| {
+            if let Some(value) = value {
+                let requested_mode_str = value.str().unwrap_or("plain");
+                let requested_mode = match requested_mode_str {
+                    "markup" => Mode::Markup,
+                    _ => Mode::Plain,
+                };
+
+                if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+                    // If already in that mode, just ensure state is sync and return
+                    if doc_state.mode() == requested_mode {
+                        action.set_state(value);
+                        return;
+                    }
+
+                    // Plain <-> Markup only changes highlighting, never the
+                    // text, so switching is safe whatever the buffer holds
+                    let Some(sv_buffer) = get_source_buffer_from_window(&window_clone) else {
+                        return;
+                    };
+
+                    // Apply changes
+                    doc_state.set_mode(requested_mode);
+                    update_markdown_actions(&window_clone, doc_state);
+
+                    // Update label
+                    let label = match requested_mode {
+                        Mode::Plain => "Plain Text",
+                        Mode::Markup => "Markdown",
+                    };
+                    doc_state.status.label_mode.set_text(label);
+
+                    // Apply language
+                    apply_highlighting(&sv_buffer, doc_state);
+
+                    update_title(&window_clone);
+
+                    // Update action state
+                    action.set_state(value);
+                }
+            }
+        });
+    }
+    window.add_action(&mode_action);
+
+    // Sudo Mode Toggle
+    let sudo_mode = SimpleAction::new_stateful("sudo_mode", None, &false.to_variant());
+    {
+        let window_clone = window.clone();
+        sudo_mode.connect_change_state(move |action, value| {
+            // "value" is the requested new state (Some(bool))
+            if let Some(requested_state_variant) = value {
+                let new_state = requested_state_variant.get::<bool>().unwrap_or(false);
+
+                if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+                    if new_state {
+                        // Enable
+                        // The prompt has already checked the password with sudo
+                        if let Some(password) = prompt_for_password(&window_clone) {
+                            doc_state
+                                .document
+                                .borrow_mut()
+                                .start_sudo(password, std::time::Instant::now());
+
+                            // Success: apply state
+                            action.set_state(&new_state.into());
+
+                            eprintln!("Sudo activated. Setting state to true.");
+                            set_sudo_state(&window_clone, true);
+                        }
+                        // If cancelled, do nothing (state remains false)
+                    } else {
+                        // Disable (unchecked)
+                        doc_state.document.borrow_mut().end_sudo();
+
+                        action.set_state(&new_state.into());
+                        set_sudo_state(&window_clone, false);
+
+                        let dialog = gtk::MessageDialog::builder()
+                            .transient_for(&window_clone)
+                            .modal(true)
+                            .message_type(gtk::MessageType::Info)
+                            .buttons(gtk::ButtonsType::Ok)
+                            .text("Sudo Mode Disabled")
+                            .build();
+                        dialog.connect_response(|d, _| d.close());
+                        dialog.show();
+                    }
+                }
+            }
+        });
+    }
+    window.add_action(&sudo_mode);
+
+    // Preferences: the config file itself, opened in a tab. Saving it
+    // applies the changes to every window, as any edit to it does.
+    let preferences = SimpleAction::new("preferences", None);
+    {
+        let window_clone = window.clone();
+        preferences.connect_activate(move |_, _| {
+            let path = Config::path();
+            if !path.exists() {
+                Config::update(|_| {});
+            }
+            open_file(&window_clone, &path, None, FileFormat::default());
+        });
+    }
+    window.add_action(&preferences);
+
+    // ----- Help actions -----
+    let about = SimpleAction::new("about", None);
+    let window_clone = window.clone();
+    about.connect_activate(move |_, _| {
+        let dialog = gtk::AboutDialog::builder()
+            .transient_for(&window_clone)
+            .modal(true)
+            .program_name("Rust Pad (rpad)")
+            .version(env!("CARGO_PKG_VERSION"))
+            .comments("A simple Rust notepad for plain text and Markdown, built with GTK4")
+            .copyright("© Rhonald John Rose")
+            .license_type(gtk::License::MitX11)
+            .authors(vec!["Rhonald John Rose".to_string()])
+            .website("https://github.com/rhonaldjr/rpad")
+            // rpad's own icon when build_ui found it installed
+            .logo_icon_name(
+                gtk::Window::default_icon_name()
+                    .as_deref()
+                    .unwrap_or(FALLBACK_ICON_NAME),
+            )
+            .build();
+
+        dialog.present();
+    });
+    window.add_action(&about);
+
+    // rpad Help: the built-in quick reference
+    let help = SimpleAction::new("help", None);
+    {
+        let window_clone = window.clone();
+        help.connect_activate(move |_, _| {
+            open_help_dialog(&window_clone);
+        });
+    }
+    window.add_action(&help);
+
+    // Keyboard Shortcuts
+    let shortcuts = SimpleAction::new("shortcuts", None);
+    {
+        let app_clone = app.clone();
+        let window_clone = window.clone();
+        shortcuts.connect_activate(move |_, _| {
+            show_shortcuts_window(&app_clone, &window_clone);
+        });
+    }
+    window.add_action(&shortcuts);
+
+    // Sync enabled-state with the (possibly just loaded) document
+    sync_ui_with_active_tab(window);
+    update_paste_actions(window, clipboard_has_text(&window.clipboard()));
+
+    // Keyboard shortcuts for all of the above
+    apply_keybindings(app, window, &Config::load().keybindings);
+}
+
+// The default accelerators from SHORTCUT_GROUPS, with the config's
+// [keybindings] laid over them. Actions are named as in the menus' "app."
+// and "win." targets; the prefix is optional. `window` is any window, to
+// look up the actions every window has.
+fn apply_keybindings(
+    app: &gtk::Application,
+    window: &gtk::ApplicationWindow,
+    keybindings: &BTreeMap<String, Accels>,
+) {
+    for (_, shortcuts) in SHORTCUT_GROUPS {
+        for (action, _, accels) in shortcuts.iter() {
+            app.set_accels_for_action(action, accels);
+        }
+    }
+
+    for (name, accels) in keybindings {
+        let action = name
+            .strip_prefix("app.")
+            .or_else(|| name.strip_prefix("win."))
+            .unwrap_or(name);
+        // Actions with a target, such as "heading_level(2)", are looked up
+        // by their name alone
+        let base = action.split_once('(').map_or(action, |(base, _)| base);
+        let prefix = if app.lookup_action(base).is_some() {
+            "app"
+        } else if window.lookup_action(base).is_some() {
+            "win"
+        } else {
+            eprintln!("Ignoring keybinding for unknown action \"{name}\"");
+            continue;
+        };
+
+        let accels: Vec<&str> = accels
+            .as_slice()
+            .iter()
+            .map(String::as_str)
+            .filter(|accel| {
+                let valid = gtk::accelerator_parse(*accel).is_some();
+                if !valid {
+                    eprintln!("Ignoring invalid accelerator \"{accel}\" for \"{name}\"");
+                }
+                valid
+            })
+            .collect();
+        app.set_accels_for_action(&format!("{prefix}.{action}"), &accels);
+    }
+}
+
+// Builds the shortcuts window from SHORTCUT_GROUPS, reading the accels back
+// from the application so it shows what is actually registered
+fn show_shortcuts_window(app: &gtk::Application, window: &gtk::ApplicationWindow) {
+    let mut groups_xml = String::new();
+    for (group_title, shortcuts) in SHORTCUT_GROUPS {
+        let mut shortcuts_xml = String::new();
+        for (action, title, _) in shortcuts.iter() {
+            let accels = app.accels_for_action(action);
+            if accels.is_empty() {
+                continue;
+            }
+            let accels: Vec<&str> = accels.iter().map(|a| a.as_str()).collect();
+            shortcuts_xml.push_str(&format!(
+                r#"<child><object class="GtkShortcutsShortcut">
+                    <property name="title">{}</property>
+                    <property name="accelerator">{}</property>
+                </object></child>"#,
+                glib::markup_escape_text(title),
+                glib::markup_escape_text(&accels.join(" ")),
+            ));
+        }
+
+        if !shortcuts_xml.is_empty() {
+            groups_xml.push_str(&format!(
+                r#"<child><object class="GtkShortcutsGroup">
+                    <property name="title">{}</property>
+                    {}
+                </object></child>"#,
+                glib::markup_escape_text(group_title),
+                shortcuts_xml,
+            ));
+        }
+    }
+
+    let ui = format!(
+        r#"<interface>
+            <object class="GtkShortcutsWindow" id="shortcuts_window">
+                <property name="modal">1</property>
+                <child><object class="GtkShortcutsSection">
+                    <property name="section-name">shortcuts</property>
+                    <property name="max-height">12</property>
+                    {}
+                </object></child>
+            </object>
+        </interface>"#,
+        groups_xml
+    );
+
+    let builder = gtk::Builder::from_string(&ui);
+    if let Some(shortcuts_window) = builder.object::<gtk::ShortcutsWindow>("shortcuts_window") {
+        shortcuts_window.set_transient_for(Some(window));
+        shortcuts_window.present();
+    } else {
+        eprintln!("Could not build the keyboard shortcuts window");
+    }
+}
+
+// Save As… dialog; `on_saved` runs after the document was written successfully
+fn save_as_with_dialog<F: Fn() + 'static>(window: &gtk::ApplicationWindow, on_saved: F) {
+    use gtk::{FileChooserAction, FileFilter, ResponseType};
+
+    let dialog = gtk::FileChooserDialog::new(
+        Some("Save File"),
+        Some(window),
+        FileChooserAction::Save,
+        &[
+            ("_Cancel", ResponseType::Cancel),
+            ("_Save", ResponseType::Accept),
+        ],
+    );
+
+    // Decide default name based on current mode
+    let mode = current_mode(window);
+    let default_name = match mode {
+        Mode::Plain => "Untitled.txt",
+        Mode::Markup => "Untitled.md",
+    };
+    dialog.set_current_name(default_name);
+
+    // Optional: filters match mode, but allow both
+    let text_filter = FileFilter::new();
+    text_filter.set_name(Some("Text Files (*.txt)"));
+    text_filter.add_pattern("*.txt");
+    dialog.add_filter(&text_filter);
+
+    let md_filter = FileFilter::new();
+    md_filter.set_name(Some("Markdown Files (*.md, *.markdown)"));
+    md_filter.add_pattern("*.md");
+    md_filter.add_pattern("*.markdown");
+    dialog.add_filter(&md_filter);
+
+    let all_filter = FileFilter::new();
+    all_filter.set_name(Some("All Files"));
+    all_filter.add_pattern("*");
+    dialog.add_filter(&all_filter);
+
+    // Preselect the filter matching the default name
+    if mode == Mode::Markup {
+        dialog.set_filter(&md_filter);
+    }
+
+    let window_clone = window.clone();
+    let on_saved = std::rc::Rc::new(on_saved);
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            if let Some(file) = dialog.file() {
+                if let Some(mut path) = file.path() {
+                    // Typed a bare name? Take the extension from the chosen filter
+                    let mut extension_added = false;
+                    if path.extension().is_none() {
+                        let selected_filter = dialog.filter();
+                        if selected_filter.as_ref() == Some(&md_filter) {
+                            path.set_extension("md");
+                            extension_added = true;
+                        } else if selected_filter.as_ref() == Some(&text_filter) {
+                            path.set_extension("txt");
+                            extension_added = true;
+                        }
+                    }
+
+                    let window_for_save = window_clone.clone();
+                    let on_saved = on_saved.clone();
+                    let save_path = path.clone();
+                    let save = move || match save_buffer_to_path(&window_for_save, &save_path) {
+                        Ok(()) => on_saved(),
+                        Err(err) => show_error_dialog(
+                            &window_for_save,
+                            "Cannot save file",
+                            &format!("{}: {}", save_path.display(), err),
+                        ),
+                    };
+
+                    // The chooser itself confirms replacing the name as typed, but
+                    // an added extension can point at a different existing file
+                    if extension_added && path.exists() {
+                        confirm_overwrite(&window_clone, &path, save);
+                    } else {
+                        save();
+                    }
+                }
+            }
+        }
+
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+fn confirm_overwrite<F: Fn() + 'static>(
+    window: &gtk::ApplicationWindow,
+    path: &Path,
+    on_confirm: F,
+) {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Warning)
+        .buttons(gtk::ButtonsType::None)
+        .text(format!(
+            "A file named “{}” already exists. Do you want to replace it?",
+            file_name
+        ))
+        .secondary_text("Replacing it will overwrite its contents.")
+        .build();
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Replace", gtk::ResponseType::Accept);
+
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Accept {
+            on_confirm();
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+// Take an advisory lock on `path` for the document, in place of any it held
+// on another path. False, leaving the old lock alone, when someone else
+// holds one: another program, or another copy of the file open in rpad. A
+// file that can't be locked (it doesn't exist yet, only root can read it,
+// or its filesystem has no locks) leaves the document without a lock.
+fn lock_document(doc_state: &DocumentState, path: &Path) -> bool {
+    let mut lock = doc_state.lock.borrow_mut();
+    if lock.as_ref().is_some_and(|(locked, _)| locked == path) {
+        return true;
+    }
+    let Ok(file) = fs::File::open(path) else {
+        *lock = None;
+        return true;
+    };
+    match file.try_lock() {
+        Ok(()) => {
+            *lock = Some((path.to_path_buf(), file));
+            true
+        }
+        Err(fs::TryLockError::WouldBlock) => false,
+        Err(fs::TryLockError::Error(_)) => {
+            *lock = None;
+            true
+        }
+    }
+}
+
+// Someone else holds a lock on `path`: ask before saving over changes they
+// may be making. Blocks until answered, like prompt_for_password, so every
+// save path gets the question.
+fn confirm_save_locked(window: &gtk::ApplicationWindow, path: &Path) -> bool {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Warning)
+        .buttons(gtk::ButtonsType::None)
+        .text(format!("“{file_name}” is locked by another program"))
+        .secondary_text(
+            "Another editor, or another copy open in rpad, may be changing it. Saving now can overwrite those changes.",
+        )
+        .build();
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Save Anyway", gtk::ResponseType::Accept);
+
+    let answer = Rc::new(RefCell::new(None));
+    let answer_clone = answer.clone();
+    dialog.connect_response(move |dialog, response| {
+        *answer_clone.borrow_mut() = Some(response == gtk::ResponseType::Accept);
+        dialog.close();
+    });
+    dialog.show();
+
+    let context = glib::MainContext::default();
+    while answer.borrow().is_none() {
+        context.iteration(true);
+    }
+    answer.take().unwrap_or(false)
+}
+
+fn current_mode(window: &gtk::ApplicationWindow) -> Mode {
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        doc_state.mode()
+    } else {
+        Mode::Plain
+    }
+}
+
+// Sudo Helpers
+
+// Ask for the sudo password and check it with sudo before handing it back;
+// None when cancelled. The check runs on a worker thread, with a spinner in
+// the dialog, since sudo can be slow (LDAP and the like); a wrong password
+// can be typed again right there.
+fn prompt_for_password(window: &gtk::ApplicationWindow) -> Option<String> {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // sudo asks through the askpass program itself; an empty password
+    // stands in so Sudo Mode still knows it is on
+    if uses_askpass() {
+        let checked = wait_for_worker(|| validate_sudo_password(""))
+            .unwrap_or_else(|| Err("Validation failed unexpectedly".to_string()));
+        return match checked {
+            Ok(()) => Some(String::new()),
+            Err(err) => {
+                show_error_dialog(window, "Sudo Validation Failed", &err);
+                None
+            }
+        };
+    }
+
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::OkCancel)
+        .text("Enter Sudo Password")
+        .build();
+
+    let content_area = dialog.content_area();
+    let entry = gtk::PasswordEntry::new();
+    entry.set_hexpand(true);
+    let spinner = gtk::Spinner::new();
+    let entry_row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    entry_row.set_margin_start(10);
+    entry_row.set_margin_end(10);
+    entry_row.append(&entry);
+    entry_row.append(&spinner);
+
+    let error_label = gtk::Label::new(None);
+    error_label.add_css_class("error");
+    error_label.set_wrap(true);
+    error_label.set_visible(false);
+
+    // Modern clone usage
+    let dialog_weak = dialog.downgrade();
+    entry.connect_activate(move |_| {
+        if let Some(dialog) = dialog_weak.upgrade() {
+            dialog.response(gtk::ResponseType::Ok);
+        }
+    });
+
+    content_area.append(&entry_row);
+    content_area.append(&error_label);
+    entry.grab_focus();
+    dialog.show();
+
+    // Some(Some(password)) once sudo accepted it, Some(None) when cancelled
+    let outcome: Rc<RefCell<Option<Option<String>>>> = Rc::new(RefCell::new(None));
+    let outcome_clone = outcome.clone();
+    dialog.connect_response(move |d, res| {
+        if res != gtk::ResponseType::Ok {
+            *outcome_clone.borrow_mut() = Some(None);
+            d.close();
+            return;
+        }
+        let password = entry.text().to_string();
+        if password.is_empty() || !entry.is_sensitive() {
+            return;
+        }
+
+        d.set_response_sensitive(gtk::ResponseType::Ok, false);
+        entry.set_sensitive(false);
+        error_label.set_visible(false);
+        spinner.start();
+
+        let (d, entry, spinner, error_label, outcome) = (
+            d.clone(),
+            entry.clone(),
+            spinner.clone(),
+            error_label.clone(),
+            outcome_clone.clone(),
+        );
+        glib::MainContext::default().spawn_local(async move {
+            let checked_password = password.clone();
+            let result =
+                gtk::gio::spawn_blocking(move || validate_sudo_password(&checked_password))
+                    .await
+                    .unwrap_or_else(|_| Err("Validation failed unexpectedly".to_string()));
+            spinner.stop();
+            // Cancelled while sudo was busy
+            if outcome.borrow().is_some() {
+                return;
+            }
+
+            match result {
+                Ok(()) => {
+                    *outcome.borrow_mut() = Some(Some(password));
+                    d.close();
+                }
+                Err(err) => {
+                    error_label.set_text(err.trim());
+                    error_label.set_visible(true);
+                    d.set_response_sensitive(gtk::ResponseType::Ok, true);
+                    entry.set_sensitive(true);
+                    entry.set_text("");
+                    entry.grab_focus();
+                }
+            }
+        });
+    });
+
+    // Block until answered using iteration loop
+    let context = glib::MainContext::default();
+    while outcome.borrow().is_none() {
+        context.iteration(true);
+    }
+    outcome.take().flatten()
+}
+
+// Run `work` on a worker thread, keeping the main loop (and so the UI) going
+// until it is done; None if it panicked
+fn wait_for_worker<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    let result = Rc::new(RefCell::new(None));
+    let result_clone = result.clone();
+    glib::MainContext::default().spawn_local(async move {
+        *result_clone.borrow_mut() = Some(gtk::gio::spawn_blocking(work).await.ok());
+    });
+
+    let context = glib::MainContext::default();
+    while result.borrow().is_none() {
+        context.iteration(true);
+    }
+    result.take().flatten()
+}
+
+// Whether sudo should ask for the password through $SUDO_ASKPASS (usually a
+// graphical prompt) rather than rpad's dialog, which also works where sudo
+// won't read a password from a pipe
+fn uses_askpass() -> bool {
+    std::env::var_os("SUDO_ASKPASS").is_some_and(|program| !program.is_empty())
+}
+
+// `sudo` taking the password from askpass, or else from stdin, where the
+// callers write it
+fn sudo_command() -> std::process::Command {
+    use std::process::{Command, Stdio};
+
+    let mut command = Command::new("sudo");
+    if uses_askpass() {
+        command.arg("-A").stdin(Stdio::null());
+    } else {
+        command.arg("-S").stdin(Stdio::piped());
+    }
+    command
+}
+
+fn validate_sudo_password(password: &str) -> Result<(), String> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    // Debug logging
+    eprintln!("Validating sudo password...");
+
+    // 1. Invalidate credentials first (sudo -k)
+    let _ = Command::new("sudo").arg("-k").status();
+
+    // 2. Validate with input (sudo -S -v)
+    // sudo -S -v reads password from stdin and validates/updates timestamp
+    let child = sudo_command()
+        .arg("-v")
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped()) // Capture stderr
+        .spawn();
+
+    match child {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = stdin.write_all(format!("{}\n", password).as_bytes());
+            }
+            match child.wait_with_output() {
+                Ok(output) => {
+                    if output.status.success() {
+                        eprintln!("Sudo validation successful.");
+                        Ok(())
+                    } else {
+                        let err_msg = String::from_utf8_lossy(&output.stderr).to_string();
+                        eprintln!("Sudo validation failed: {}", err_msg);
+                        Err(if err_msg.trim().is_empty() {
+                            "Validation failed (unknown error)".to_string()
+                        } else {
+                            err_msg
+                        })
+                    }
+                }
+                Err(e) => {
+                    let msg = format!("Failed to wait on sudo: {}", e);
+                    eprintln!("{}", msg);
+                    Err(msg)
+                }
+            }
+        }
+        Err(e) => {
+            let msg = format!("Failed to spawn sudo: {}", e);
+            eprintln!("{}", msg);
+            Err(msg)
+        }
+    }
+}
+
+fn perform_sudo_save(path: &Path, content: &[u8], password: &str) -> Result<(), String> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    // 1. Write to temp file
+    let temp_dir = std::env::temp_dir();
+    let temp_file = temp_dir.join("rpad_sudo_save.tmp");
+    if let Err(e) = fs::write(&temp_file, content) {
+        return Err(format!("Failed to write temp file: {}", e));
+    }
+
+    let status = sudo_command()
+        .arg("cp")
+        .arg(&temp_file)
+        .arg(path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped()) // Capture error if any
+        .spawn();
+
+    match status {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = stdin.write_all(format!("{}\n", password).as_bytes());
+            }
+            match child.wait() {
+                Ok(status) => {
+                    let _ = fs::remove_file(temp_file);
+                    if status.success() {
+                        Ok(())
+                    } else {
+                        Err("Sudo save failed".to_string())
+                    }
+                }
+                Err(e) => Err(format!("Failed to wait on sudo: {}", e)),
+            }
+        }
+        Err(e) => Err(format!("Failed to spawn sudo: {}", e)),
+    }
+}
+
+fn perform_sudo_read(
+    path: &Path,
+    password: &str,
+    encoding: Option<&str>,
+) -> Result<String, String> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let child = sudo_command()
+        .arg("cat")
+        .arg("--")
+        .arg(path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+
+    match child {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = stdin.write_all(format!("{}\n", password).as_bytes());
+            }
+            match child.wait_with_output() {
+                Ok(output) => {
+                    if !output.status.success() {
+                        return Err("Sudo read failed".to_string());
+                    }
+                    // The same check ensure_text_file makes for readable files
+                    if encoding.is_none() && output.stdout.contains(&0) {
+                        return Err("the file appears to contain binary data".to_string());
+                    }
+                    decode_text(output.stdout, encoding).map_err(|err| err.to_string())
+                }
+                Err(e) => Err(format!("Failed to wait on sudo: {}", e)),
+            }
+        }
+        Err(e) => Err(format!("Failed to spawn sudo: {}", e)),
+    }
+}
+
+// The file was last saved with sudo and still can't be written without it
+fn needs_sudo_again(path: &Path) -> bool {
+    Session::load().file(path).is_some_and(|file| file.sudo)
+        && fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .is_err_and(|err| err.kind() == std::io::ErrorKind::PermissionDenied)
+}
+
+// Ask before turning Sudo Mode on for a file that needed it last time,
+// rather than have the save fail later
+fn offer_sudo_mode(window: &gtk::ApplicationWindow, path: &Path) {
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::None)
+        .text("Turn on Sudo Mode?")
+        .secondary_text(format!(
+            "{} was saved with sudo last time. Saving it again will need sudo as well.",
+            path.display()
+        ))
+        .build();
+
+    dialog.add_button("Not Now", gtk::ResponseType::Reject);
+    dialog.add_button("Turn On", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let window_clone = window.clone();
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        if response == gtk::ResponseType::Accept {
+            window_clone.change_action_state("sudo_mode", &true.to_variant());
+        }
+    });
+    dialog.show();
+}
+
+// Turn Sudo Mode on for the current tab with a password that already worked
+fn start_sudo_session(window: &gtk::ApplicationWindow, password: String) {
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        doc_state
+            .document
+            .borrow_mut()
+            .start_sudo(password, std::time::Instant::now());
+    }
+    set_sudo_state(window, true);
+}
+
+// "SUDO 4:32" until the password is due again, then a reminder that the
+// next save asks for it
+fn update_sudo_label(doc_state: &DocumentState) {
+    let remaining = doc_state
+        .document
+        .borrow()
+        .sudo_remaining(std::time::Instant::now());
+
+    let label = &doc_state.status.label_sudo;
+    match remaining {
+        Some(remaining) => {
+            let seconds = remaining.as_secs_f64().ceil() as u64;
+            label.set_markup(&format!(
+                "<span weight='bold' foreground='red'>SUDO {}:{:02}</span>",
+                seconds / 60,
+                seconds % 60
+            ));
+            label.set_tooltip_text(Some("Time left before sudo asks for the password again"));
+        }
+        None => {
+            label.set_markup("<span weight='bold' foreground='red'>SUDO (re-authenticate)</span>");
+            label.set_tooltip_text(Some("The next save asks for the sudo password again"));
+        }
+    }
+}
+
+// Title for the tab in front: its file, [SUDO] while in Sudo Mode, its
+// mode, and the cursor position when that is turned on
+fn update_title(window: &gtk::ApplicationWindow) {
+    let Some(buffer) = get_text_buffer_from_window(window) else {
+        return;
+    };
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        let mut title = match doc_state.path() {
+            Some(path) => format!("rpad - {}", path.display()),
+            None => "rpad - Untitled".to_string(),
+        };
+        if doc_state.sudo_active() {
+            title.push_str(" [SUDO]");
+        }
+        title.push_str(match doc_state.mode() {
+            Mode::Plain => " [Plain]",
+            Mode::Markup => " [Markdown]",
+        });
+        if *doc_state.cursor_in_title.borrow() {
+            let iter = buffer.iter_at_mark(&buffer.get_insert());
+            title.push_str(&format!(
+                " — Ln {}, Col {}",
+                iter.line() + 1,
+                iter.line_offset() + 1
+            ));
+        }
+        window.set_title(Some(&title));
+    }
+}
+
+fn set_sudo_state(window: &gtk::ApplicationWindow, active: bool) {
+    use gtk::gio;
+
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        update_title(window);
+
+        // Update Status Label
+        doc_state.status.label_sudo.set_visible(active);
+
+        // Count down to the next password prompt, once a second
+        if let Some(source_id) = doc_state.status.sudo_timeout.borrow_mut().take() {
+            source_id.remove();
+        }
+        if active {
+            update_sudo_label(doc_state);
+            let window_weak = window.downgrade();
+            let source_id = glib::timeout_add_seconds_local(1, move || {
+                let Some(window) = window_weak.upgrade() else {
+                    return glib::ControlFlow::Break;
+                };
+                if let Some(doc_state) = active_doc_state(&window).as_deref() {
+                    update_sudo_label(doc_state);
+                }
+                glib::ControlFlow::Continue
+            });
+            *doc_state.status.sudo_timeout.borrow_mut() = Some(source_id);
+        }
+
+        // Update Action State
+        if let Some(action) = window.lookup_action("sudo_mode") {
+            if let Some(stateful_action) = action.downcast_ref::<gio::SimpleAction>() {
+                if stateful_action.state() != Some(active.to_variant()) {
+                    stateful_action.set_state(&active.into());
+                }
+            }
+        }
+    }
+}
+
+fn set_action_enabled(window: &gtk::ApplicationWindow, name: &str, enabled: bool) {
+    use gtk::gio;
+
+    if let Some(action) = window.lookup_action(name) {
+        if let Some(simple_action) = action.downcast_ref::<gio::SimpleAction>() {
+            simple_action.set_enabled(enabled);
+        }
+    }
+}
+
+// Reflect state that changed elsewhere (e.g. switching tabs) without
+// running the action's change handler
+fn set_action_state(window: &gtk::ApplicationWindow, name: &str, state: &glib::Variant) {
+    use gtk::gio;
+
+    if let Some(action) = window.lookup_action(name) {
+        if let Some(simple_action) = action.downcast_ref::<gio::SimpleAction>() {
+            simple_action.set_state(state);
+        }
+    }
+}
+
+// Grey out Undo/Redo when there is nothing on the respective stack
+fn update_undo_redo_actions(window: &gtk::ApplicationWindow, doc_state: &DocumentState) {
+    let (can_undo, can_redo) = {
+        let document = doc_state.document.borrow();
+        (document.can_undo(), document.can_redo())
+    };
+    set_action_enabled(window, "undo", can_undo);
+    set_action_enabled(window, "redo", can_redo);
+}
+
+// Save is pointless for a clean document that already lives on disk;
+// Save As stays enabled regardless
+fn update_save_action(window: &gtk::ApplicationWindow, doc_state: &DocumentState) {
+    set_action_enabled(
+        window,
+        "save",
+        doc_state.is_dirty() || doc_state.path().is_none(),
+    );
+    // Comparing with the saved file needs both a file and unsaved edits
+    set_action_enabled(
+        window,
+        "show_changes",
+        doc_state.is_dirty() && doc_state.path().is_some(),
+    );
+    set_action_enabled(window, "open_containing_folder", doc_state.path().is_some());
+}
+
+fn update_markdown_actions(window: &gtk::ApplicationWindow, doc_state: &DocumentState) {
+    for name in [
+        "bold",
+        "italic",
+        "insert_link",
+        "insert_table",
+        "heading_level",
+        "heading_increase",
+        "heading_decrease",
+    ] {
+        set_action_enabled(window, name, doc_state.mode() == Mode::Markup);
+    }
+}
+
+fn update_selection_actions(window: &gtk::ApplicationWindow, has_selection: bool) {
+    for name in ["cut", "copy", "delete"] {
+        set_action_enabled(window, name, has_selection);
+    }
+}
+
+fn update_paste_actions(window: &gtk::ApplicationWindow, has_text: bool) {
+    for name in ["paste", "paste_plain", "paste_quote"] {
+        set_action_enabled(window, name, has_text);
+    }
+}
+
+// Paste the clipboard's text through `transform`, replacing any selection.
+// Only the text is read, so whatever formatting the source had is dropped.
+fn paste_transformed(window: &gtk::ApplicationWindow, transform: fn(&str) -> String) {
+    let Some(text_view) = get_text_view_from_window(window) else {
+        return;
+    };
+    window
+        .clipboard()
+        .read_text_async(gtk::gio::Cancellable::NONE, move |result| {
+            let text = match result {
+                Ok(Some(text)) => transform(&text),
+                Ok(None) => return,
+                Err(err) => {
+                    eprintln!("Error reading clipboard: {err}");
+                    return;
+                }
+            };
+            let buffer = text_view.buffer();
+            let (mut start, mut end) = buffer.selection_bounds().unwrap_or_else(|| {
+                let cursor = buffer.iter_at_mark(&buffer.get_insert());
+                (cursor, cursor)
+            });
+            replace_range(&buffer, &mut start, &mut end, &text);
+            text_view.scroll_mark_onscreen(&buffer.get_insert());
+        });
+}
+
+fn clipboard_has_text(clipboard: &gtk::gdk::Clipboard) -> bool {
+    clipboard.formats().contains_type(glib::Type::STRING)
+}
+
+// Tabs with unsaved changes in every window, with the window holding each
+fn unsaved_documents(app: &gtk::Application) -> Vec<(gtk::ApplicationWindow, gtk::Widget)> {
+    app.windows()
+        .into_iter()
+        .filter_map(|window| window.downcast::<gtk::ApplicationWindow>().ok())
+        .flat_map(|window| {
+            tab_pages(&window)
+                .into_iter()
+                .filter(|page| doc_state_of(page).is_some_and(|doc_state| doc_state.is_dirty()))
+                .map(move |page| (window.clone(), page))
+        })
+        .collect()
+}
+
+// Closing a window asks about its own unsaved tabs one at a time, which is
+// fine for a single document; several get one dialog covering them all
+fn quit_application(app: &gtk::Application) {
+    let unsaved = unsaved_documents(app);
+    if unsaved.len() <= 1 {
+        close_all_windows(app);
+    } else {
+        confirm_save_all(app, unsaved, || {});
+    }
+}
+
+fn close_all_windows(app: &gtk::Application) {
+    for window in app.windows() {
+        window.close();
+    }
+}
+
+// Save every unsaved document. Those with a path are written straight away;
+// the untitled ones then get a Save As each, in turn, until one is cancelled.
+fn save_all_documents(app: &gtk::Application) {
+    let mut untitled = Vec::new();
+    for (window, page) in unsaved_documents(app) {
+        let path = doc_state_of(&page).and_then(|doc_state| doc_state.path());
+        match path {
+            Some(path) => {
+                select_tab(&window, &page);
+                if let Err(err) = save_buffer_to_path(&window, &path) {
+                    eprintln!("Error saving file: {err}");
+                }
+            }
+            None => untitled.push((window, page)),
+        }
+    }
+    save_untitled_in_turn(untitled);
+}
+
+fn save_untitled_in_turn(mut untitled: Vec<(gtk::ApplicationWindow, gtk::Widget)>) {
+    if untitled.is_empty() {
+        return;
+    }
+    let (window, page) = untitled.remove(0);
+    if !select_tab(&window, &page) {
+        save_untitled_in_turn(untitled);
+        return;
+    }
+
+    window.present();
+    let untitled = RefCell::new(Some(untitled));
+    save_as_with_dialog(&window, move || {
+        if let Some(untitled) = untitled.take() {
+            save_untitled_in_turn(untitled);
+        }
+    });
+}
+
+// Offer to save or discard all of `unsaved` at once, then close every
+// window. Untitled documents still get a Save As each, from their window's
+// own close prompt. `on_answered` runs whatever the answer.
+fn confirm_save_all<F: Fn() + 'static>(
+    app: &gtk::Application,
+    unsaved: Vec<(gtk::ApplicationWindow, gtk::Widget)>,
+    on_answered: F,
+) {
+    let names: Vec<String> = unsaved
+        .iter()
+        .map(|(_, page)| {
+            doc_state_of(page)
+                .and_then(|doc_state| doc_state.path())
+                .map_or_else(|| "Untitled".to_string(), |path| path.display().to_string())
+        })
+        .collect();
+
+    let mut builder = gtk::MessageDialog::builder()
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::None)
+        .text(format!(
+            "Save changes to {} documents before quitting?",
+            unsaved.len()
+        ))
+        .secondary_text(format!(
+            "If you don’t save, changes to these documents will be lost:\n\n{}",
+            names.join("\n")
+        ));
+    if let Some(window) = app.active_window() {
+        builder = builder.transient_for(&window);
+    }
+    let dialog = builder.build();
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Don't Save", gtk::ResponseType::Reject);
+    dialog.add_button("Save All", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let app = app.clone();
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        on_answered();
+        if !matches!(
+            response,
+            gtk::ResponseType::Accept | gtk::ResponseType::Reject
+        ) {
+            return;
+        }
+
+        for (window, page) in &unsaved {
+            let Some(doc_state) = doc_state_of(page) else {
+                continue;
+            };
+            if response == gtk::ResponseType::Reject {
+                // Marked clean so the windows close without asking again
+                doc_state.set_dirty(false);
+            } else if let Some(path) = doc_state.path() {
+                select_tab(window, page);
+                if let Err(err) = save_buffer_to_path(window, &path) {
+                    eprintln!("Error saving file: {err}");
+                }
+            }
+        }
+        close_all_windows(&app);
+    });
+
+    dialog.show();
+}
+
+// If the document has unsaved changes, offer to save or discard them first.
+// `on_proceed(discarded)` runs once it's safe to replace or close the
+// document; it doesn't run if the user cancels (or the save fails). After
+// Don't Save, `discarded` is true and the document is still unsaved: only
+// the caller knows when it has really been replaced or closed.
+fn confirm_discard<F: Fn(bool) + 'static>(window: &gtk::ApplicationWindow, on_proceed: F) {
+    let dirty = active_doc_state(window)
+        .map(|doc_state| doc_state.is_dirty())
+        .unwrap_or(false);
+    if !dirty {
+        on_proceed(false);
+        return;
+    }
+
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::None)
+        .text("Do you want to save changes to this document first?")
+        .secondary_text("If you don’t save, your changes will be lost.")
+        .build();
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Don't Save", gtk::ResponseType::Reject);
+    dialog.add_button("Save", gtk::ResponseType::Accept);
+
+    let window_clone = window.clone();
+    let on_proceed = std::rc::Rc::new(on_proceed);
+    dialog.connect_response(move |dialog, response| {
+        match response {
+            gtk::ResponseType::Accept => {
+                if let Some(doc_state) = active_doc_state(&window_clone).as_deref() {
+                    if let Some(path) = doc_state.path() {
+                        // Save to existing path
+                        match save_buffer_to_path(&window_clone, &path) {
+                            Ok(()) => on_proceed(false),
+                            Err(err) => show_error_dialog(
+                                &window_clone,
+                                "Cannot save file",
+                                &format!("{}: {}", path.display(), err),
+                            ),
+                        }
+                    } else {
+                        // No path yet → Save As, then proceed
+                        let on_proceed = on_proceed.clone();
+                        save_as_with_dialog(&window_clone, move || on_proceed(false));
+                    }
+                }
+            }
+            gtk::ResponseType::Reject => on_proceed(true),
+            _ => {
+                // Cancel → do nothing, keep the document as it is
+            }
+        }
+
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+// Language highlighting for the current mode, unless it was switched off
+// because the loaded file is too large or has very long lines. Bracket
+// matching goes with it, as it scans those lines on every cursor move.
+fn apply_highlighting(buffer: &sv::Buffer, doc_state: &DocumentState) {
+    let suppressed = *doc_state.highlight_suppressed.borrow();
+    if suppressed {
+        buffer.set_language(None::<&sv::Language>);
+    } else {
+        apply_language_for_mode(buffer, doc_state.mode());
+    }
+    buffer.set_highlight_matching_brackets(!suppressed);
+}
+
+fn apply_language_for_mode(buffer: &sv::Buffer, mode: Mode) {
+    let lm = sv::LanguageManager::default();
+
+    match mode {
+        Mode::Plain => {
+            buffer.set_language(None::<&sv::Language>);
+        }
+        Mode::Markup => {
+            if let Some(lang) = lm.language("markdown") {
+                buffer.set_language(Some(&lang));
+            } else {
+                buffer.set_language(None::<&sv::Language>);
+            }
+        }
+    }
+}
+
+// The pinned scheme if it exists, otherwise the light or dark default to
+// match the desktop
+fn apply_style_scheme(buffer: &sv::Buffer, doc_state: &DocumentState) {
+    let manager = sv::StyleSchemeManager::default();
+    let dark = prefers_dark_theme();
+    let default_id = if dark {
+        DARK_STYLE_SCHEME
+    } else {
+        LIGHT_STYLE_SCHEME
+    };
+    // Accessibility mode overrides a scheme pinned in the config
+    let high_contrast = if *doc_state.accessible.borrow() {
+        Some(if dark {
+            HIGH_CONTRAST_DARK_STYLE_SCHEME
+        } else {
+            HIGH_CONTRAST_LIGHT_STYLE_SCHEME
+        })
+    } else {
+        None
+    };
+    let style_scheme = doc_state.style_scheme.borrow();
+    let scheme = high_contrast
+        .or(style_scheme.as_deref())
+        .and_then(|id| manager.scheme(id))
+        .or_else(|| manager.scheme(default_id));
+    if let Some(scheme) = scheme {
+        buffer.set_style_scheme(Some(&scheme));
+    }
+}
+
+// GTK reports a dark preference either directly or through a "-dark"
+// theme variant
+fn prefers_dark_theme() -> bool {
+    gtk::Settings::default().is_some_and(|settings| {
+        settings.is_gtk_application_prefer_dark_theme()
+            || settings
+                .gtk_theme_name()
+                .is_some_and(|name| name.ends_with("-dark"))
+    })
+}
+
+fn refresh_style_schemes(window: &gtk::ApplicationWindow) {
+    for page in tab_pages(window) {
+        if let (Some(buffer), Some(doc_state)) =
+            (source_buffer_of_page(&page), doc_state_of(&page).as_deref())
+        {
+            apply_style_scheme(&buffer, doc_state);
+        }
+    }
+}
+
+// Draw glyphs for spaces, tabs and newlines in the `which` part of each
+// line ("all", "leading" or "trailing"); anything else draws none
+fn apply_show_whitespace(text_view: &sv::View, which: &str) {
+    let locations = match which {
+        "all" => sv::SpaceLocationFlags::ALL,
+        "leading" => sv::SpaceLocationFlags::LEADING,
+        "trailing" => sv::SpaceLocationFlags::TRAILING,
+        _ => sv::SpaceLocationFlags::NONE,
+    };
+
+    let drawer = text_view.space_drawer();
+    drawer.set_types_for_locations(sv::SpaceLocationFlags::ALL, sv::SpaceTypeFlags::NONE);
+    drawer.set_types_for_locations(locations, sv::SpaceTypeFlags::ALL);
+    drawer.set_enable_matrix(locations != sv::SpaceLocationFlags::NONE);
+}
+
+// Number lines in the gutter: "absolute" uses sourceview's own numbers,
+// "relative" the renderer added by add_relative_line_numbers
+fn apply_line_numbers(text_view: &sv::View, which: &str) {
+    text_view.set_show_line_numbers(which == "absolute");
+    if let Some(renderer) = relative_line_numbers_of(text_view) {
+        renderer.set_visible(which == "relative");
+    }
+}
+
+// Gutter numbering each line by its distance from the cursor line, which
+// shows its own number instead, like Vim's relativenumber. Sourceview only
+// draws absolute numbers, so this is a renderer of our own, hidden until
+// line numbers are set to "relative".
+fn add_relative_line_numbers(text_view: &sv::View) {
+    let renderer = sv::GutterRendererText::new();
+    renderer.set_xpad(4);
+    renderer.set_xalign(1.0);
+    renderer.set_visible(false);
+
+    renderer.connect_query_data(|renderer, lines, line| {
+        let Some(lines) = lines.downcast_ref::<sv::GutterLines>() else {
+            return;
+        };
+        let buffer = lines.buffer();
+        let cursor_line = buffer.iter_at_mark(&buffer.get_insert()).line() as u32;
+        let number = if line == cursor_line {
+            line + 1
+        } else {
+            line.abs_diff(cursor_line)
+        };
+        renderer.set_text(&number.to_string());
+    });
+
+    // Wide enough for the biggest number shown, the cursor line's
+    let buffer = text_view.buffer();
+    fit_line_numbers(&renderer, &buffer);
+    let renderer_weak = renderer.downgrade();
+    buffer.connect_changed(move |buffer| {
+        if let Some(renderer) = renderer_weak.upgrade() {
+            fit_line_numbers(&renderer, buffer);
+        }
+    });
+
+    // Named so relative_line_numbers_of can pick it out among the gutter's
+    // renderers
+    renderer.set_widget_name(RELATIVE_LINE_NUMBERS);
+    // Where sourceview puts its own line numbers
+    ViewExt::gutter(text_view, gtk::TextWindowType::Left).insert(&renderer, -30);
+}
+
+fn fit_line_numbers(renderer: &sv::GutterRendererText, buffer: &gtk::TextBuffer) {
+    let digits = buffer.line_count().to_string().len();
+    let (width, _) = GutterRendererTextExt::measure(renderer, &"9".repeat(digits));
+    let width = width + 2 * renderer.xpad();
+    if renderer.width_request() != width {
+        renderer.set_size_request(width, -1);
+    }
+}
+
+fn relative_line_numbers_of(text_view: &sv::View) -> Option<sv::GutterRendererText> {
+    let gutter = ViewExt::gutter(text_view, gtk::TextWindowType::Left);
+    named_child(gutter.upcast_ref(), RELATIVE_LINE_NUMBERS)?
+        .downcast()
+        .ok()
+}
+
+// The child of `parent` given `name` with set_widget_name
+fn named_child(parent: &gtk::Widget, name: &str) -> Option<gtk::Widget> {
+    let mut child = parent.first_child();
+    while let Some(widget) = child {
+        if widget.widget_name() == name {
+            return Some(widget);
+        }
+        child = widget.next_sibling();
+    }
+    None
+}
+
+fn apply_right_margin(text_view: &sv::View, doc_state: &DocumentState) {
+    text_view.set_show_right_margin(*doc_state.right_margin.borrow());
+    text_view.set_right_margin_position(*doc_state.right_margin_column.borrow());
+}
+
+// Store the guide settings in every tab and redraw their views
+fn set_right_margin_everywhere(window: &gtk::ApplicationWindow, shown: bool, column: u32) {
+    for page in tab_pages(window) {
+        if let Some(doc_state) = doc_state_of(&page).as_deref() {
+            *doc_state.right_margin.borrow_mut() = shown;
+            *doc_state.right_margin_column.borrow_mut() = column;
+            for text_view in views_of_page(&page) {
+                apply_right_margin(&text_view, doc_state);
+            }
+        }
+    }
+}
+
+// Turn cursor blinking on or off for every text view on the display;
+// None goes back to the desktop's own setting
+fn apply_cursor_blink(blink: Option<bool>) {
+    let Some(settings) = gtk::Settings::default() else {
+        return;
+    };
+    match blink {
+        Some(blink) => settings.set_gtk_cursor_blink(blink),
+        None => settings.reset_property("gtk-cursor-blink"),
+    }
+}
+
+// Bring every tab in line with a config file that was edited while rpad
+// runs. Only settings that changed are applied, so that, say, a tab zoomed
+// on its own keeps its zoom when an unrelated setting is edited.
+fn apply_config_change(window: &gtk::ApplicationWindow, old: &Config, new: &Config) {
+    let spell_check = new.spell_check.unwrap_or(false);
+    let auto_pair = new.auto_pair.unwrap_or(true);
+    let smart_quotes = new.smart_quotes.unwrap_or(false);
+    let cursor_in_title = new.cursor_in_title.unwrap_or(false);
+    let minimap = new.minimap.unwrap_or(false);
+    let show_whitespace = new.show_whitespace.as_deref().unwrap_or("none");
+    let line_numbers = new.line_numbers.as_deref().unwrap_or("none");
+
+    for page in tab_pages(window) {
+        let Some(doc_state) = doc_state_of(&page) else {
+            continue;
+        };
+
+        if new.zoom != old.zoom {
+            set_zoom(&doc_state, new.zoom.unwrap_or(ZOOM_DEFAULT));
+        }
+        if new.auto_pair != old.auto_pair {
+            *doc_state.auto_pair.borrow_mut() = auto_pair;
+        }
+        if new.smart_quotes != old.smart_quotes {
+            *doc_state.smart_quotes.borrow_mut() = smart_quotes;
+        }
+        if new.cursor_in_title != old.cursor_in_title {
+            *doc_state.cursor_in_title.borrow_mut() = cursor_in_title;
+        }
+        if new.style_scheme != old.style_scheme {
+            *doc_state.style_scheme.borrow_mut() = new.style_scheme.clone();
+        }
+        if new.show_whitespace != old.show_whitespace {
+            *doc_state.show_whitespace.borrow_mut() = show_whitespace.to_string();
+            for text_view in views_of_page(&page) {
+                apply_show_whitespace(&text_view, show_whitespace);
+            }
+        }
+        if new.line_numbers != old.line_numbers {
+            *doc_state.line_numbers.borrow_mut() = line_numbers.to_string();
+            for text_view in views_of_page(&page) {
+                apply_line_numbers(&text_view, line_numbers);
+            }
+        }
+        if new.match_case != old.match_case {
+            *doc_state.match_case.borrow_mut() = new.match_case.unwrap_or(false);
+        }
+        if new.preserve_case != old.preserve_case {
+            *doc_state.preserve_case.borrow_mut() = new.preserve_case.unwrap_or(false);
+        }
+
+        if let Some(adapter) = spell_adapter_of_page(&page) {
+            if new.spell_check != old.spell_check {
+                adapter.set_enabled(spell_check);
+            }
+            if let Some(code) = new.spell_language.as_deref() {
+                if new.spell_language != old.spell_language {
+                    adapter.set_language(code);
+                }
+            }
+        }
+        if new.minimap != old.minimap {
+            if let Some(map) = minimap_of_page(&page) {
+                map.set_visible(minimap);
+            }
+        }
+        if new.undo_limit != old.undo_limit {
+            doc_state
+                .document
+                .borrow_mut()
+                .set_undo_limit(new.undo_limit.unwrap_or(document::DEFAULT_UNDO_LIMIT));
+        }
+    }
+    if new.undo_limit != old.undo_limit {
+        if let Some(doc_state) = active_doc_state(window).as_deref() {
+            update_undo_redo_actions(window, doc_state);
+        }
+    }
+
+    if new.style_scheme != old.style_scheme {
+        refresh_style_schemes(window);
+    }
+    if new.right_margin != old.right_margin || new.right_margin_column != old.right_margin_column {
+        let shown = new.right_margin.unwrap_or(false);
+        let column = new
+            .right_margin_column
+            .unwrap_or(DEFAULT_RIGHT_MARGIN_COLUMN)
+            .clamp(1, MAX_RIGHT_MARGIN_COLUMN);
+        set_right_margin_everywhere(window, shown, column);
+        set_action_state(window, "right_margin", &shown.to_variant());
+    }
+
+    if new.cursor_blink != old.cursor_blink {
+        apply_cursor_blink(new.cursor_blink);
+        let blink = gtk::Settings::default().is_none_or(|settings| settings.is_gtk_cursor_blink());
+        set_action_state(window, "cursor_blink", &blink.to_variant());
+    }
+
+    if new.indent_guides != old.indent_guides {
+        let shown = new.indent_guides.unwrap_or(false);
+        set_indent_guides_everywhere(window, shown);
+        set_action_state(window, "indent_guides", &shown.to_variant());
+    }
+
+    set_action_state(window, "spell_check", &spell_check.to_variant());
+    if let Some(code) = &new.spell_language {
+        set_action_state(window, "spell_language", &code.to_variant());
+    }
+    set_action_state(window, "auto_pair", &auto_pair.to_variant());
+    set_action_state(window, "smart_quotes", &smart_quotes.to_variant());
+    if new.cursor_in_title != old.cursor_in_title {
+        update_title(window);
+    }
+    set_action_state(window, "cursor_in_title", &cursor_in_title.to_variant());
+    set_action_state(window, "minimap", &minimap.to_variant());
+    set_action_state(window, "show_whitespace", &show_whitespace.to_variant());
+    set_action_state(window, "line_numbers", &line_numbers.to_variant());
+
+    if new.keybindings != old.keybindings {
+        if let Some(app) = window.application() {
+            apply_keybindings(&app, window, &new.keybindings);
+        }
+    }
+}
+
+// GtkSourceView has no indent guides, so they are drawn on an area laid
+// over the scrolled editor and redrawn whenever the view scrolls or the
+// text changes. The area is kept on the view as "rpad-indent-guides".
+fn add_indent_guides(overlay: &gtk::Overlay, scrolled: &gtk::ScrolledWindow, text_view: &sv::View) {
+    let area = gtk::DrawingArea::new();
+    area.set_can_target(false);
+    area.set_visible(false);
+
+    let view_weak = text_view.downgrade();
+    area.set_draw_func(move |area, cr, _, _| {
+        if let Some(text_view) = view_weak.upgrade() {
+            draw_indent_guides(&text_view, area, cr);
+        }
+    });
+
+    let redraw = {
+        let area_weak = area.downgrade();
+        move || {
+            if let Some(area) = area_weak.upgrade() {
+                if area.is_visible() {
+                    area.queue_draw();
+                }
+            }
+        }
+    };
+    for adjustment in [scrolled.vadjustment(), scrolled.hadjustment()] {
+        let redraw = redraw.clone();
+        adjustment.connect_value_changed(move |_| redraw());
+    }
+    {
+        let redraw = redraw.clone();
+        text_view.buffer().connect_changed(move |_| redraw());
+    }
+    if let Ok(buffer) = text_view.buffer().downcast::<sv::Buffer>() {
+        buffer.connect_language_notify(move |_| redraw());
+    }
+
+    // Named so apply_indent_guides can find it beside the view
+    area.set_widget_name(INDENT_GUIDES);
+    overlay.add_overlay(&area);
+}
+
+fn apply_indent_guides(text_view: &sv::View, shown: bool) {
+    // The view sits in a ScrolledWindow inside the pane's Overlay
+    let overlay = text_view.parent().and_then(|scrolled| scrolled.parent());
+    if let Some(area) = overlay.and_then(|overlay| named_child(&overlay, INDENT_GUIDES)) {
+        area.set_visible(shown);
+    }
+}
+
+// Bring a tab's text size, current-line highlight and style scheme in
+// line with its accessibility mode
+fn apply_accessible(page: &gtk::Widget, doc_state: &DocumentState) {
+    update_zoom_css(doc_state);
+    for text_view in views_of_page(page) {
+        text_view.set_highlight_current_line(*doc_state.accessible.borrow());
+    }
+    if let Some(buffer) = source_buffer_of_page(page) {
+        apply_style_scheme(&buffer, doc_state);
+    }
+}
+
+// Store the setting in every tab and show or hide their guides
+fn set_indent_guides_everywhere(window: &gtk::ApplicationWindow, shown: bool) {
+    for page in tab_pages(window) {
+        if let Some(doc_state) = doc_state_of(&page).as_deref() {
+            *doc_state.indent_guides.borrow_mut() = shown;
+        }
+        for text_view in views_of_page(&page) {
+            apply_indent_guides(&text_view, shown);
+        }
+    }
+}
+
+// One line per indent level down the visible lines. Blank lines take the
+// indentation of the line below, so guides run through gaps in a block.
+fn draw_indent_guides(text_view: &sv::View, area: &gtk::DrawingArea, cr: &gtk::cairo::Context) {
+    let has_language = text_view
+        .buffer()
+        .downcast::<sv::Buffer>()
+        .is_ok_and(|buffer| buffer.language().is_some());
+    if !has_language {
+        return;
+    }
+
+    let tab_width = text_view.tab_width().max(1);
+    let indent_width = u32::try_from(text_view.indent_width())
+        .ok()
+        .filter(|&width| width > 0)
+        .unwrap_or(tab_width);
+    let (space_width, _) = text_view.create_pango_layout(Some(" ")).pixel_size();
+    let step = f64::from(space_width) * f64::from(indent_width);
+
+    let visible = text_view.visible_rect();
+    let Some(mut iter) = text_view.iter_at_location(visible.x(), visible.y()) else {
+        return;
+    };
+    iter.set_line_offset(0);
+
+    // (line start x, top, height, indent) in buffer coordinates
+    let mut lines = Vec::new();
+    loop {
+        let (y, height) = text_view.line_yrange(&iter);
+        let mut end = iter;
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        let indent = text_ops::indent_columns(&iter.slice(&end), tab_width);
+        lines.push((text_view.iter_location(&iter).x(), y, height, indent));
+
+        if y + height >= visible.y() + visible.height() || !iter.forward_line() {
+            break;
+        }
+    }
+
+    let mut below = 0;
+    cr.set_source_rgba(0.5, 0.5, 0.5, 0.35);
+    cr.set_line_width(1.0);
+    for (x, y, height, indent) in lines.into_iter().rev() {
+        let indent = indent.unwrap_or(below);
+        below = indent;
+
+        let (x, y) = text_view.buffer_to_window_coords(gtk::TextWindowType::Widget, x, y);
+        let Some(origin) =
+            text_view.compute_point(area, &gtk::graphene::Point::new(x as f32, y as f32))
+        else {
+            continue;
+        };
+        for level in 0..indent / indent_width {
+            let guide_x = (f64::from(origin.x()) + step * f64::from(level)).floor() + 0.5;
+            cr.move_to(guide_x, f64::from(origin.y()));
+            cr.line_to(guide_x, f64::from(origin.y()) + f64::from(height));
+        }
+    }
+    let _ = cr.stroke();
+}
+
+// Match new indentation to the current tab's text, so Tab and auto-indent
+// produce what the file already uses
+fn set_indentation(
+    window: &gtk::ApplicationWindow,
+    doc_state: &DocumentState,
+    indent: Option<text_ops::Indent>,
+) {
+    *doc_state.indent.borrow_mut() = indent;
+    if let Some(page) = current_page(window) {
+        for text_view in views_of_page(&page) {
+            apply_indentation(&text_view, indent);
+        }
+    }
+    update_indent_label(doc_state);
+}
+
+fn apply_indentation(text_view: &sv::View, indent: Option<text_ops::Indent>) {
+    let (spaces, tab_width) = match indent {
+        Some(text_ops::Indent::Spaces(width)) => (true, width),
+        Some(text_ops::Indent::Tabs) | None => (false, DEFAULT_TAB_WIDTH),
+    };
+    text_view.set_insert_spaces_instead_of_tabs(spaces);
+    text_view.set_tab_width(tab_width);
+}
+
+fn update_indent_label(doc_state: &DocumentState) {
+    let label = &doc_state.status.label_indent;
+    let indent = *doc_state.indent.borrow();
+    label.set_visible(indent.is_some());
+    match indent {
+        Some(text_ops::Indent::Tabs) => label.set_text("Tabs"),
+        Some(text_ops::Indent::Spaces(width)) => label.set_text(&format!("Spaces: {width}")),
+        None => {}
+    }
+}
+
+fn is_markdown_buffer(buffer: &sv::Buffer) -> bool {
+    buffer
+        .language()
+        .is_some_and(|language| language.id() == "markdown")
+}
+
+// Gutter with ▾/▸ markers beside markdown headings; clicking one folds or
+// unfolds the section under it. Only shown while the buffer is markdown.
+fn add_fold_gutter(text_view: &sv::View) {
+    let renderer = sv::GutterRendererText::new();
+    renderer.set_xpad(4);
+
+    renderer.connect_query_data(|renderer, lines, line| {
+        let Some(lines) = lines.downcast_ref::<sv::GutterLines>() else {
+            return;
+        };
+        let start = lines.iter_at_line(line);
+        let mut end = start;
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+
+        let marker = if markdown::heading_level(&start.slice(&end)).is_none() {
+            ""
+        } else if is_folded_at(&end) {
+            "▸"
+        } else {
+            "▾"
+        };
+        renderer.set_text(marker);
+    });
+
+    renderer.connect_query_activatable(|_, iter, _| {
+        let mut end = *iter;
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        let mut start = *iter;
+        start.set_line_offset(0);
+        markdown::heading_level(&start.slice(&end)).is_some()
+    });
+
+    renderer.connect_activate(|renderer, iter, _, _, _, _| {
+        toggle_fold(&iter.buffer(), iter.line());
+        renderer.queue_draw();
+    });
+
+    if let Ok(buffer) = text_view.buffer().downcast::<sv::Buffer>() {
+        renderer.set_visible(is_markdown_buffer(&buffer));
+        let renderer_weak = renderer.downgrade();
+        buffer.connect_language_notify(move |buffer| {
+            if let Some(renderer) = renderer_weak.upgrade() {
+                renderer.set_visible(is_markdown_buffer(buffer));
+            }
+        });
+    }
+
+    ViewExt::gutter(text_view, gtk::TextWindowType::Left).insert(&renderer, 0);
+}
+
+fn is_folded_at(iter: &gtk::TextIter) -> bool {
+    iter.buffer()
+        .tag_table()
+        .lookup(FOLDED_TAG)
+        .is_some_and(|tag| iter.has_tag(&tag))
+}
+
+// Hide the section under the heading on `line`, or show it again if it's
+// already hidden. The heading itself stays visible, and the fold takes its
+// line's newline so the next visible line follows straight after it.
+fn toggle_fold(buffer: &gtk::TextBuffer, line: i32) {
+    let Some(tag) = buffer.tag_table().lookup(FOLDED_TAG) else {
+        return;
+    };
+    let Some(mut fold_start) = buffer.iter_at_line(line) else {
+        return;
+    };
+    if !fold_start.ends_line() {
+        fold_start.forward_to_line_end();
+    }
+
+    // Unfold everything the tag hides from here on, including folds nested
+    // inside this one
+    if fold_start.has_tag(&tag) {
+        let mut fold_end = fold_start;
+        fold_end.forward_to_tag_toggle(Some(&tag));
+        buffer.remove_tag(&tag, &fold_start, &fold_end);
+        return;
+    }
+
+    let (start, end) = buffer.bounds();
+    let text = buffer.text(&start, &end, true);
+    let headings = markdown::headings(&text);
+    let Some(index) = headings
+        .iter()
+        .position(|heading| heading.line == line as usize)
+    else {
+        return;
+    };
+
+    let line_count = buffer.line_count() as usize;
+    let end_line = markdown::section_end(&headings, index, line_count);
+    let fold_end = match buffer.iter_at_line(end_line as i32) {
+        Some(mut next_heading) if end_line < line_count => {
+            next_heading.backward_char();
+            next_heading
+        }
+        _ => buffer.end_iter(),
+    };
+    if fold_end.offset() <= fold_start.offset() {
+        return;
+    }
+
+    buffer.apply_tag(&tag, &fold_start, &fold_end);
+
+    // Keep the cursor out of the hidden text
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    if cursor.in_range(&fold_start, &fold_end) {
+        buffer.place_cursor(&fold_start);
+    }
+}
+
+// Bookmark the cursor's line, or remove the bookmark(s) already on it
+fn toggle_bookmark(buffer: &sv::Buffer, doc_state: &DocumentState) {
+    let line = buffer.iter_at_mark(&buffer.get_insert()).line();
+    let mut bookmarks = doc_state.bookmarks.borrow_mut();
+
+    let count = bookmarks.len();
+    bookmarks.retain(|mark| {
+        let on_line = buffer.iter_at_mark(mark).line() == line;
+        if on_line {
+            buffer.delete_mark(mark);
+        }
+        !on_line
+    });
+
+    if bookmarks.len() == count {
+        if let Some(line_start) = buffer.iter_at_line(line) {
+            bookmarks.push(buffer.create_source_mark(None, BOOKMARK_CATEGORY, &line_start));
+        }
+    }
+}
+
+// Move to the next (or previous) bookmarked line, wrapping around the ends
+fn goto_bookmark(text_view: &sv::View, doc_state: &DocumentState, forward: bool) {
+    let buffer = text_view.buffer();
+    let mut lines: Vec<i32> = doc_state
+        .bookmarks
+        .borrow()
+        .iter()
+        .map(|mark| buffer.iter_at_mark(mark).line())
+        .collect();
+    lines.sort_unstable();
+    lines.dedup();
+
+    let current = buffer.iter_at_mark(&buffer.get_insert()).line();
+    let target = if forward {
+        lines.iter().find(|&&line| line > current).or(lines.first())
+    } else {
+        lines
+            .iter()
+            .rev()
+            .find(|&&line| line < current)
+            .or(lines.last())
+    };
+
+    if let Some(mut iter) = target.and_then(|&line| buffer.iter_at_line(line)) {
+        buffer.place_cursor(&iter);
+        text_view.scroll_to_iter(&mut iter, 0.1, false, 0.0, 0.0);
+    }
+}
+
+// Turn the buffer's text into `text` by replacing only the part that
+// differs, so that bookmarks outside it stay on their lines. The cursor
+// ends up at the start of the change.
+fn replace_changed_text(buffer: &gtk::TextBuffer, text: &str) {
+    let (start, end) = buffer.bounds();
+    let old = buffer.text(&start, &end, true);
+    let (from, old_to, new_to) = text_ops::changed_span(&old, text);
+
+    let mut start = buffer.iter_at_offset(from as i32);
+    let mut end = buffer.iter_at_offset(old_to as i32);
+    buffer.delete(&mut start, &mut end);
+    let inserted: String = text.chars().skip(from).take(new_to - from).collect();
+    buffer.insert(&mut start, &inserted);
+    buffer.place_cursor(&buffer.iter_at_offset(from as i32));
+}
+
+// The cursor sits where the text just changed, whether by typing, pasting
+// or deleting
+fn remember_last_edit(buffer: &gtk::TextBuffer, doc_state: &DocumentState) {
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    let mut last_edit = doc_state.last_edit.borrow_mut();
+    match last_edit.as_ref() {
+        Some(mark) => buffer.move_mark(mark, &cursor),
+        None => *last_edit = Some(buffer.create_mark(None, &cursor, true)),
+    }
+}
+
+// Drop the bookmarks and last edit position when the buffer gets a whole
+// new document
+fn forget_positions(buffer: &sv::Buffer, doc_state: &DocumentState) {
+    for mark in doc_state.bookmarks.borrow_mut().drain(..) {
+        buffer.delete_mark(&mark);
+    }
+    if let Some(mark) = doc_state.last_edit.borrow_mut().take() {
+        buffer.delete_mark(&mark);
+    }
+}
+
+fn search_in_buffer(
+    buffer: &sv::Buffer,
+    text_view: &sv::View,
+    pattern: &str,
+    forward: bool,
+    match_case: bool,
+) -> Option<(gtk::TextIter, gtk::TextIter)> {
+    let (start, end) = buffer.bounds();
+    let from = buffer.iter_at_mark(&buffer.get_insert()).offset() as usize;
+    let direction = if forward {
+        search::Direction::Forward
+    } else {
+        search::Direction::Backward
+    };
+    let (start, end) = search::find_match(
+        &buffer.text(&start, &end, true),
+        pattern,
+        from,
+        direction,
+        match_case,
+    )?;
+
+    let mut match_start = buffer.iter_at_offset(start as i32);
+    let match_end = buffer.iter_at_offset(end as i32);
+    buffer.select_range(&match_start, &match_end);
+    text_view.scroll_to_iter(&mut match_start, 0.1, false, 0.0, 0.0);
+    Some((match_start, match_end))
+}
+
+fn search_flags(match_case: bool) -> gtk::TextSearchFlags {
+    let mut flags = gtk::TextSearchFlags::TEXT_ONLY;
+    if !match_case {
+        flags |= gtk::TextSearchFlags::CASE_INSENSITIVE;
+    }
+    flags
+}
+
+// Replace every match in `range` (the whole buffer when None) as one user
+// action, returning how many there were. With `preserve_case` each
+// replacement is cased like its match. A selected range stays selected,
+// grown or shrunk to fit the replacements.
+fn replace_all(
+    buffer: &gtk::TextBuffer,
+    pattern: &str,
+    replacement: &str,
+    match_case: bool,
+    preserve_case: bool,
+    range: Option<(gtk::TextIter, gtk::TextIter)>,
+) -> usize {
+    if pattern.is_empty() {
+        return 0;
+    }
+
+    let (start, end) = range.unwrap_or_else(|| buffer.bounds());
+    // Marks, since every replacement invalidates iters. The end mark has
+    // right gravity so a replacement right at the end stays inside.
+    let start_mark = buffer.create_mark(None, &start, true);
+    let end_mark = buffer.create_mark(None, &end, false);
+
+    let flags = search_flags(match_case);
+    let mut count = 0;
+    let mut from = start;
+    buffer.begin_user_action();
+    while let Some((mut match_start, mut match_end)) =
+        from.forward_search(pattern, flags, Some(&buffer.iter_at_mark(&end_mark)))
+    {
+        let replacement = if preserve_case {
+            text_ops::preserve_case(&match_start.slice(&match_end), replacement)
+        } else {
+            replacement.to_string()
+        };
+        buffer.delete(&mut match_start, &mut match_end);
+        buffer.insert(&mut match_start, &replacement);
+        from = match_start;
+        count += 1;
+    }
+    buffer.end_user_action();
+
+    if range.is_some() {
+        buffer.select_range(
+            &buffer.iter_at_mark(&start_mark),
+            &buffer.iter_at_mark(&end_mark),
+        );
+    }
+    buffer.delete_mark(&start_mark);
+    buffer.delete_mark(&end_mark);
+    count
+}
+
+// Non-overlapping matches of `pattern` in the whole buffer
+fn count_matches(buffer: &gtk::TextBuffer, pattern: &str, match_case: bool) -> usize {
+    if pattern.is_empty() {
+        return 0;
+    }
+
+    let flags = search_flags(match_case);
+    let mut count = 0;
+    let mut from = buffer.start_iter();
+    while let Some((_, match_end)) = from.forward_search(pattern, flags, None) {
+        count += 1;
+        from = match_end;
+    }
+    count
+}
+
+fn do_find_next(window: &gtk::ApplicationWindow, text_view: &sv::View) {
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        let pattern = doc_state.find_text.borrow().clone();
+        if pattern.is_empty() {
+            return;
+        }
+        let match_case = *doc_state.match_case.borrow();
+        let Some(buffer) = searchable_buffer(window, text_view) else {
+            return;
+        };
+        let _ = search_in_buffer(&buffer, text_view, &pattern, true, match_case);
+    }
+}
+
+fn do_find_prev(window: &gtk::ApplicationWindow, text_view: &sv::View) {
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        let pattern = doc_state.find_text.borrow().clone();
+        if pattern.is_empty() {
+            return;
+        }
+        let match_case = *doc_state.match_case.borrow();
+        let Some(buffer) = searchable_buffer(window, text_view) else {
+            return;
+        };
+        let _ = search_in_buffer(&buffer, text_view, &pattern, false, match_case);
+    }
+}
+
+// Escape works like the dialog's Cancel (or Close) button
+fn close_on_escape(dialog: &gtk::Dialog) {
+    let shortcuts = gtk::ShortcutController::new();
+    shortcuts.set_propagation_phase(gtk::PropagationPhase::Capture);
+    shortcuts.add_shortcut(gtk::Shortcut::new(
+        gtk::ShortcutTrigger::parse_string("Escape"),
+        Some(gtk::CallbackAction::new(|widget, _| {
+            if let Some(dialog) = widget.downcast_ref::<gtk::Dialog>() {
+                dialog.response(gtk::ResponseType::Cancel);
+            }
+            glib::Propagation::Stop
+        })),
+    ));
+    dialog.add_controller(shortcuts);
+}
+
+// An entry with a dropdown of earlier terms, newest first
+fn history_combo(history: &[String]) -> (gtk::ComboBoxText, gtk::Entry) {
+    let combo = gtk::ComboBoxText::with_entry();
+    combo.set_hexpand(true);
+    for term in history {
+        combo.append_text(term);
+    }
+    // with_entry always makes one; should it not, bring our own
+    let entry = combo
+        .child()
+        .and_downcast::<gtk::Entry>()
+        .unwrap_or_else(|| {
+            let entry = gtk::Entry::new();
+            combo.set_child(Some(&entry));
+            entry
+        });
+    (combo, entry)
+}
+
+// The view's buffer for searching. Every view rpad makes has a source
+// buffer; if one ever doesn't, say so rather than panic in a callback.
+fn searchable_buffer(window: &gtk::ApplicationWindow, text_view: &sv::View) -> Option<sv::Buffer> {
+    let buffer = text_view.buffer().downcast::<sv::Buffer>().ok();
+    if buffer.is_none() {
+        show_error_dialog(
+            window,
+            "Search failed",
+            "This document's text can't be searched.",
+        );
+    }
+    buffer
+}
+
+// The current tab's (find, replace) histories
+fn search_histories(window: &gtk::ApplicationWindow) -> (Vec<String>, Vec<String>) {
+    active_doc_state(window)
+        .map(|doc_state| {
+            (
+                doc_state.find_history.borrow().clone(),
+                doc_state.replace_history.borrow().clone(),
+            )
+        })
+        .unwrap_or_default()
+}
+
+// Add terms to every tab's history and to the session data
+fn remember_search_terms(window: &gtk::ApplicationWindow, find: &str, replace: Option<&str>) {
+    for page in tab_pages(window) {
+        if let Some(doc_state) = doc_state_of(&page).as_deref() {
+            config::remember_search(&mut doc_state.find_history.borrow_mut(), find);
+            if let Some(replace) = replace {
+                config::remember_search(&mut doc_state.replace_history.borrow_mut(), replace);
+            }
+        }
+    }
+    Session::update(|session| {
+        config::remember_search(&mut session.find_history, find);
+        if let Some(replace) = replace {
+            config::remember_search(&mut session.replace_history, replace);
+        }
+    });
+}
+
+// Search options from the Find and Replace dialogs apply to every tab and
+// are saved in the config, so they're still set after a restart. Replace
+// passes its "Preserve case" too; Find leaves it as it was.
+fn remember_search_options(
+    window: &gtk::ApplicationWindow,
+    match_case: bool,
+    preserve_case: Option<bool>,
+) {
+    let mut changed = false;
+    for page in tab_pages(window) {
+        if let Some(doc_state) = doc_state_of(&page).as_deref() {
+            changed |= doc_state.match_case.replace(match_case) != match_case;
+            if let Some(preserve_case) = preserve_case {
+                changed |= doc_state.preserve_case.replace(preserve_case) != preserve_case;
+            }
+        }
+    }
+    if changed {
+        Config::update(|c| {
+            c.match_case = Some(match_case);
+            if preserve_case.is_some() {
+                c.preserve_case = preserve_case;
+            }
+        });
+    }
+}
+
+fn open_find_dialog(window: &gtk::ApplicationWindow, text_view: &sv::View) {
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title("Find")
+        .build();
+    close_on_escape(&dialog);
+
+    dialog.add_button("Count", FIND_COUNT_RESPONSE);
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Find Next", gtk::ResponseType::Accept);
+
+    let content = dialog.content_area();
+    content.set_spacing(6);
+    content.set_margin_top(6);
+    content.set_margin_bottom(6);
+    content.set_margin_start(6);
+    content.set_margin_end(6);
+
+    let (find_history, _) = search_histories(window);
+    let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some("Find what:"));
+    let (combo, entry) = history_combo(&find_history);
+    hbox.append(&label);
+    hbox.append(&combo);
+
+    let match_case_cb = gtk::CheckButton::with_label("Match case");
+
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        entry.set_text(&doc_state.find_text.borrow());
+        match_case_cb.set_active(*doc_state.match_case.borrow());
+    }
+
+    // Filled in by Count
+    let count_label = gtk::Label::new(None);
+    count_label.set_xalign(0.0);
+    count_label.add_css_class("dim-label");
+
+    content.append(&hbox);
+    content.append(&match_case_cb);
+    content.append(&count_label);
+
+    let win_clone = window.clone();
+    let text_view_clone = text_view.clone();
+    let entry_clone = entry.clone();
+    let match_case_cb_clone = match_case_cb.clone();
+
+    dialog.connect_response(move |dialog, response| {
+        // Count leaves the dialog open and the cursor where it is
+        if response == FIND_COUNT_RESPONSE {
+            let count = count_matches(
+                &text_view_clone.buffer(),
+                &entry_clone.text(),
+                match_case_cb_clone.is_active(),
+            );
+            count_label.set_text(&match count {
+                1 => "1 match".to_string(),
+                n => format!("{n} matches"),
+            });
+            return;
+        }
+
+        if response == gtk::ResponseType::Accept {
+            let text = entry_clone.text().to_string();
+            let match_case = match_case_cb_clone.is_active();
+            remember_search_terms(&win_clone, &text, None);
+            remember_search_options(&win_clone, match_case, None);
+
+            if let Some(doc_state) = active_doc_state(&win_clone).as_deref() {
+                *doc_state.find_text.borrow_mut() = text.clone();
+            }
+
+            let Some(buffer) = searchable_buffer(&win_clone, &text_view_clone) else {
+                return;
+            };
+            let _ = search_in_buffer(&buffer, &text_view_clone, &text, true, match_case);
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+fn open_replace_dialog(window: &gtk::ApplicationWindow, text_view: &sv::View) {
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title("Replace")
+        .build();
+    close_on_escape(&dialog);
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Replace All", REPLACE_ALL_RESPONSE);
+    dialog.add_button("Replace", gtk::ResponseType::Accept);
+
+    let content = dialog.content_area();
+    content.set_spacing(6);
+    content.set_margin_top(6);
+    content.set_margin_bottom(6);
+    content.set_margin_start(6);
+    content.set_margin_end(6);
+
+    let (find_history, replace_history) = search_histories(window);
+
+    // Find row
+    let find_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    let find_label = gtk::Label::new(Some("Find what:"));
+    let (find_combo, find_entry) = history_combo(&find_history);
+    find_box.append(&find_label);
+    find_box.append(&find_combo);
+
+    // Replace row
+    let replace_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    let replace_label = gtk::Label::new(Some("Replace with:"));
+    let (replace_combo, replace_entry) = history_combo(&replace_history);
+    replace_box.append(&replace_label);
+    replace_box.append(&replace_combo);
+
+    let match_case_cb = gtk::CheckButton::with_label("Match case");
+
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        find_entry.set_text(&doc_state.find_text.borrow());
+        match_case_cb.set_active(*doc_state.match_case.borrow());
+    }
+
+    // Case each replacement like the text it replaces
+    let preserve_case_cb = gtk::CheckButton::with_label("Preserve case");
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        preserve_case_cb.set_active(*doc_state.preserve_case.borrow());
+    }
+
+    // Replace All within the selection; only offered when there is one
+    let in_selection_cb = gtk::CheckButton::with_label("In selection");
+    let has_selection = text_view.buffer().has_selection();
+    in_selection_cb.set_sensitive(has_selection);
+    in_selection_cb.set_active(has_selection);
+
+    content.append(&find_box);
+    content.append(&replace_box);
+    content.append(&match_case_cb);
+    content.append(&preserve_case_cb);
+    content.append(&in_selection_cb);
+
+    let win_clone = window.clone();
+    let text_view_clone = text_view.clone();
+    let find_entry_clone = find_entry.clone();
+    let replace_entry_clone = replace_entry.clone();
+    let match_case_cb_clone = match_case_cb.clone();
+
+    dialog.connect_response(move |dialog, response| {
+        if response == REPLACE_ALL_RESPONSE {
+            let find_text = find_entry_clone.text().to_string();
+            let replace_text = replace_entry_clone.text().to_string();
+            let match_case = match_case_cb_clone.is_active();
+            remember_search_terms(&win_clone, &find_text, Some(&replace_text));
+            remember_search_options(&win_clone, match_case, Some(preserve_case_cb.is_active()));
+
+            let buffer = text_view_clone.buffer();
+            let range = if in_selection_cb.is_active() {
+                buffer.selection_bounds()
+            } else {
+                None
+            };
+            replace_all(
+                &buffer,
+                &find_text,
+                &replace_text,
+                match_case,
+                preserve_case_cb.is_active(),
+                range,
+            );
+        }
+
+        if response == gtk::ResponseType::Accept {
+            let find_text = find_entry_clone.text().to_string();
+            let replace_text = replace_entry_clone.text().to_string();
+            let match_case = match_case_cb_clone.is_active();
+            remember_search_terms(&win_clone, &find_text, Some(&replace_text));
+            remember_search_options(&win_clone, match_case, Some(preserve_case_cb.is_active()));
+
+            if let Some(doc_state) = active_doc_state(&win_clone).as_deref() {
+                *doc_state.find_text.borrow_mut() = find_text.clone();
+            }
+
+            let Some(buffer) = searchable_buffer(&win_clone, &text_view_clone) else {
+                return;
+            };
+
+            if let Some((mut start, mut end)) =
+                search_in_buffer(&buffer, &text_view_clone, &find_text, true, match_case)
+            {
+                let replacement = if preserve_case_cb.is_active() {
+                    text_ops::preserve_case(&start.slice(&end), &replace_text)
+                } else {
+                    replace_text
+                };
+                buffer.begin_user_action();
+                buffer.delete(&mut start, &mut end);
+                buffer.insert(&mut start, &replacement);
+                buffer.end_user_action();
+            }
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+fn open_goto_dialog(window: &gtk::ApplicationWindow, text_view: &sv::View) {
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title("Go To Line")
+        .build();
+    close_on_escape(&dialog);
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Go To", gtk::ResponseType::Accept);
+
+    let content = dialog.content_area();
+    content.set_spacing(6);
+    content.set_margin_top(6);
+    content.set_margin_bottom(6);
+    content.set_margin_start(6);
+    content.set_margin_end(6);
+
+    let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some("Line number:"));
+    let entry = gtk::Entry::new();
+    entry.set_hexpand(true);
+    hbox.append(&label);
+    hbox.append(&entry);
+    content.append(&hbox);
+
+    let text_view_clone = text_view.clone();
+    let entry_clone = entry.clone();
+
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Accept {
+            if let Ok(line_num) = entry_clone.text().parse::<i32>() {
+                goto_line(&text_view_clone, line_num);
+            }
+        }
+
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+// Handle a key for Vim-style editing; true when it was used up. Insert
+// mode only watches for Escape, and keys with Ctrl or Alt (apart from
+// Ctrl+R for redo) are left to the window's shortcuts.
+fn vim_key(
+    page: &gtk::Widget,
+    text_view: &sv::View,
+    doc_state: &DocumentState,
+    key: gtk::gdk::Key,
+    modifiers: gtk::gdk::ModifierType,
+) -> bool {
+    use gtk::gdk::{Key, ModifierType};
+
+    let command = {
+        let mut vim_state = doc_state.vim.borrow_mut();
+        let Some(vim) = vim_state.as_mut() else {
+            return false;
+        };
+
+        if key == Key::Escape {
+            let was_insert = vim.mode == vim::VimMode::Insert;
+            vim.escape();
+            drop(vim_state);
+
+            // Like Vim, leaving Insert mode steps back onto the last character
+            let buffer = text_view.buffer();
+            let mut cursor = buffer.iter_at_mark(&buffer.get_insert());
+            if was_insert && !cursor.starts_line() {
+                cursor.backward_char();
+            }
+            buffer.place_cursor(&cursor);
+
+            update_vim_cursor(page, doc_state);
+            update_vim_status(doc_state);
+            return true;
+        }
+
+        if vim.mode == vim::VimMode::Insert {
+            return false;
+        }
+
+        if modifiers.intersects(ModifierType::CONTROL_MASK | ModifierType::ALT_MASK) {
+            let is_redo =
+                modifiers.contains(ModifierType::CONTROL_MASK) && (key == Key::r || key == Key::R);
+            if !is_redo {
+                return false;
+            }
+            Some(vim::Command::Redo)
+        } else {
+            let c = match key {
+                Key::Return | Key::KP_Enter => vim::ENTER,
+                Key::BackSpace => vim::BACKSPACE,
+                _ => match key.to_unicode() {
+                    Some(c) if !c.is_control() => c,
+                    // Tab would type a tab; arrows and the like still move
+                    Some('\t') => return true,
+                    _ => return false,
+                },
+            };
+            vim.key(c)
+        }
+    };
+
+    if let Some(command) = command {
+        run_vim_command(page, text_view, doc_state, command);
+    }
+    update_vim_cursor(page, doc_state);
+    update_vim_status(doc_state);
+    true
+}
+
+fn run_vim_command(
+    page: &gtk::Widget,
+    text_view: &sv::View,
+    doc_state: &DocumentState,
+    command: vim::Command,
+) {
+    use vim::{Command, InsertAt};
+
+    let buffer = text_view.buffer();
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    let window = page.root().and_downcast::<gtk::ApplicationWindow>();
+    let activate = |name: &str| {
+        if let Some(window) = &window {
+            gtk::gio::prelude::ActionGroupExt::activate_action(window, name, None);
+        }
+    };
+
+    match command {
+        Command::Move(motion) => {
+            let target = vim_motion_target(&cursor, motion);
+            let visual = doc_state
+                .vim
+                .borrow()
+                .as_ref()
+                .is_some_and(|vim| vim.mode == vim::VimMode::Visual);
+            if visual {
+                buffer.move_mark(&buffer.get_insert(), &target);
+            } else {
+                buffer.place_cursor(&target);
+            }
+            text_view.scroll_mark_onscreen(&buffer.get_insert());
+        }
+        Command::Insert(at) => {
+            let mut iter = cursor;
+            match at {
+                InsertAt::Cursor => {}
+                InsertAt::AfterCursor => {
+                    if !iter.ends_line() {
+                        iter.forward_char();
+                    }
+                }
+                InsertAt::LineStart => {
+                    iter.set_line_offset(0);
+                    while !iter.ends_line() && matches!(iter.char(), ' ' | '\t') {
+                        iter.forward_char();
+                    }
+                }
+                InsertAt::LineEnd => {
+                    if !iter.ends_line() {
+                        iter.forward_to_line_end();
+                    }
+                }
+                InsertAt::LineBelow => {
+                    if !iter.ends_line() {
+                        iter.forward_to_line_end();
+                    }
+                    buffer.insert(&mut iter, "\n");
+                }
+                InsertAt::LineAbove => {
+                    iter.set_line_offset(0);
+                    buffer.insert(&mut iter, "\n");
+                    iter.backward_char();
+                }
+            }
+            buffer.place_cursor(&iter);
+        }
+        Command::Visual => {}
+        Command::Normal => buffer.place_cursor(&cursor),
+        Command::DeleteChar => {
+            if !cursor.ends_line() {
+                let (mut start, mut end) = (cursor, cursor);
+                end.forward_char();
+                set_vim_register(doc_state, buffer.text(&start, &end, true).into(), false);
+                buffer.delete(&mut start, &mut end);
+            }
+        }
+        Command::DeleteLine | Command::YankLine => {
+            let mut start = cursor;
+            start.set_line_offset(0);
+            let mut end = start;
+            let has_newline = end.forward_line();
+
+            let mut text = buffer.text(&start, &end, true).to_string();
+            if !has_newline {
+                text.push('\n');
+            }
+            set_vim_register(doc_state, text, true);
+
+            if command == Command::DeleteLine {
+                // The last line has no newline of its own; take the one before
+                if !has_newline && start.line() > 0 {
+                    start.backward_char();
+                }
+                buffer.delete(&mut start, &mut end);
+                let mut line_start = buffer.iter_at_mark(&buffer.get_insert());
+                line_start.set_line_offset(0);
+                buffer.place_cursor(&line_start);
+            }
+        }
+        Command::DeleteSelection | Command::YankSelection => {
+            if let Some((mut start, mut end)) = buffer.selection_bounds() {
+                set_vim_register(doc_state, buffer.text(&start, &end, true).into(), false);
+                if command == Command::DeleteSelection {
+                    buffer.delete(&mut start, &mut end);
+                } else {
+                    buffer.place_cursor(&start);
+                }
+            }
+        }
+        Command::Paste { after } => {
+            let Some((text, linewise)) = doc_state
+                .vim
+                .borrow()
+                .as_ref()
+                .map(|vim| (vim.register.clone(), vim.linewise))
+            else {
+                return;
+            };
+            if text.is_empty() {
+                return;
+            }
+
+            let mut at = cursor;
+            if linewise {
+                at.set_line_offset(0);
+                if after && !at.forward_line() {
+                    // Pasting below the last line: it needs a newline first
+                    let line = at.line() + 1;
+                    buffer.insert(&mut at, &format!("\n{}", text.trim_end_matches('\n')));
+                    if let Some(line_start) = buffer.iter_at_line(line) {
+                        buffer.place_cursor(&line_start);
+                    }
+                    return;
+                }
+                let line = at.line();
+                buffer.insert(&mut at, &text);
+                if let Some(line_start) = buffer.iter_at_line(line) {
+                    buffer.place_cursor(&line_start);
+                }
+            } else {
+                if after && !at.ends_line() {
+                    at.forward_char();
+                }
+                buffer.insert(&mut at, &text);
+                at.backward_char();
+                buffer.place_cursor(&at);
+            }
+        }
+        Command::Undo => activate("undo"),
+        Command::Redo => activate("redo"),
+        Command::Search(pattern) => {
+            *doc_state.find_text.borrow_mut() = pattern;
+            activate("find_next");
+            collapse_selection_to_start(&buffer);
+        }
+        Command::SearchNext { forward } => {
+            activate(if forward { "find_next" } else { "find_prev" });
+            collapse_selection_to_start(&buffer);
+        }
+        Command::Ex(line) => match line.trim() {
+            "w" => activate("save"),
+            "q" => activate("close_tab"),
+            "q!" => {
+                doc_state.set_dirty(false);
+                activate("close_tab");
+            }
+            "wq" | "x" => {
+                activate("save");
+                // Saving a new document asks for a name first; close later
+                if !doc_state.is_dirty() {
+                    activate("close_tab");
+                }
+            }
+            other => match other.parse::<i32>() {
+                Ok(line_num) => goto_line(text_view, line_num),
+                Err(_) => {
+                    if let Some(vim) = doc_state.vim.borrow_mut().as_mut() {
+                        vim.message = Some(format!("Not an editor command: {other}"));
+                    }
+                }
+            },
+        },
+    }
+}
 
-    // Modern clone usage
-    let dialog_weak = dialog.downgrade();
-    entry.connect_activate(move |_| {
-        if let Some(dialog) = dialog_weak.upgrade() {
-            dialog.response(gtk::ResponseType::Ok);
+// Where a Vim motion takes the cursor from `from`
+fn vim_motion_target(from: &gtk::TextIter, motion: vim::Motion) -> gtk::TextIter {
+    use vim::Motion;
+
+    let buffer = from.buffer();
+    let mut iter = *from;
+    match motion {
+        Motion::Left => {
+            if !iter.starts_line() {
+                iter.backward_char();
+            }
         }
-    });
+        Motion::Right => {
+            if !iter.ends_line() {
+                iter.forward_char();
+            }
+        }
+        Motion::Up | Motion::Down => {
+            let line = iter.line() + if motion == Motion::Up { -1 } else { 1 };
+            if let Some(mut target) = buffer.iter_at_line(line) {
+                // Keep the column where the line is long enough
+                let column = iter.line_offset();
+                if !target.ends_line() {
+                    let mut line_end = target;
+                    line_end.forward_to_line_end();
+                    target.set_line_offset(column.min(line_end.line_offset()));
+                }
+                iter = target;
+            }
+        }
+        Motion::LineStart => iter.set_line_offset(0),
+        Motion::LineEnd => {
+            if !iter.ends_line() {
+                iter.forward_to_line_end();
+            }
+        }
+        Motion::WordForward => {
+            iter.forward_word_end();
+            while !iter.is_end() && !iter.starts_word() {
+                iter.forward_char();
+            }
+        }
+        Motion::WordBackward => {
+            iter.backward_word_start();
+        }
+        Motion::DocumentStart => iter = buffer.start_iter(),
+        Motion::DocumentEnd => {
+            iter = buffer.end_iter();
+            iter.set_line_offset(0);
+        }
+    }
+    iter
+}
 
-    content_area.append(&entry);
-    entry.grab_focus();
-    dialog.show();
+fn set_vim_register(doc_state: &DocumentState, text: String, linewise: bool) {
+    if let Some(vim) = doc_state.vim.borrow_mut().as_mut() {
+        vim.register = text;
+        vim.linewise = linewise;
+    }
+}
 
-    // Block until response using iteration loop
-    let response = Rc::new(RefCell::new(None));
-    let response_clone = response.clone();
-    dialog.connect_response(move |d, res| {
-        *response_clone.borrow_mut() = Some(res);
-        d.close();
-    });
+// Search selects the match; Vim just puts the cursor on it
+fn collapse_selection_to_start(buffer: &gtk::TextBuffer) {
+    if let Some((start, _)) = buffer.selection_bounds() {
+        buffer.place_cursor(&start);
+    }
+}
 
-    let context = glib::MainContext::default();
-    while response.borrow().is_none() {
-        context.iteration(true);
+// A block cursor outside Insert mode, as in Vim
+fn update_vim_cursor(page: &gtk::Widget, doc_state: &DocumentState) {
+    let block = doc_state
+        .vim
+        .borrow()
+        .as_ref()
+        .is_some_and(|vim| vim.mode != vim::VimMode::Insert);
+    for text_view in views_of_page(page) {
+        text_view.set_overwrite(block);
     }
+}
 
-    if *response.borrow() == Some(gtk::ResponseType::Ok) {
-        let text = entry.text();
-        if !text.is_empty() {
-            return Some(text.to_string());
-        }
+fn update_vim_status(doc_state: &DocumentState) {
+    let vim = doc_state.vim.borrow();
+    let label = &doc_state.status.label_vim;
+    label.set_visible(vim.is_some());
+    if let Some(vim) = vim.as_ref() {
+        label.set_text(&vim.status());
     }
-    None
 }
 
-fn validate_sudo_password(password: &str) -> Result<(), String> {
-    use std::io::Write;
-    use std::process::{Command, Stdio};
+// Put the cursor at the start of 1-based `line_num`, clamped to the
+// document, and scroll it into view. Scrolling to the insert mark rather
+// than an iter also works right after loading, before the lines have been
+// laid out.
+fn goto_line(text_view: &sv::View, line_num: i32) {
+    let buffer = text_view.buffer();
+    let max_lines = buffer.line_count();
+    if max_lines == 0 {
+        return;
+    }
 
-    // Debug logging
-    eprintln!("Validating sudo password...");
+    let line = (line_num - 1).clamp(0, max_lines - 1);
+    if let Some(iter) = buffer.iter_at_line(line) {
+        buffer.place_cursor(&iter);
+        text_view.scroll_to_mark(&buffer.get_insert(), 0.1, false, 0.0, 0.0);
+    }
+}
 
-    // 1. Invalidate credentials first (sudo -k)
-    let _ = Command::new("sudo").arg("-k").status();
+// Move the cursor to the first non-blank character of its line, or to
+// column 0 if it is already there. With `extend` the selection's other end
+// stays put.
+fn smart_home(text_view: &sv::View, extend: bool) {
+    let buffer = text_view.buffer();
+    let insert = buffer.get_insert();
+    let cursor = buffer.iter_at_mark(&insert);
 
-    // 2. Validate with input (sudo -S -v)
-    // sudo -S -v reads password from stdin and validates/updates timestamp
-    let child = Command::new("sudo")
-        .arg("-S")
-        .arg("-v")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped()) // Capture stderr
-        .spawn();
+    let mut line_start = cursor;
+    line_start.set_line_offset(0);
+    let mut first_non_blank = line_start;
+    while !first_non_blank.ends_line() && matches!(first_non_blank.char(), ' ' | '\t') {
+        first_non_blank.forward_char();
+    }
 
-    match child {
-        Ok(mut child) => {
-            if let Some(mut stdin) = child.stdin.take() {
-                let _ = stdin.write_all(format!("{}\n", password).as_bytes());
-            }
-            match child.wait_with_output() {
-                Ok(output) => {
-                    if output.status.success() {
-                        eprintln!("Sudo validation successful.");
-                        Ok(())
-                    } else {
-                        let err_msg = String::from_utf8_lossy(&output.stderr).to_string();
-                        eprintln!("Sudo validation failed: {}", err_msg);
-                        Err(if err_msg.trim().is_empty() {
-                            "Validation failed (unknown error)".to_string()
-                        } else {
-                            err_msg
-                        })
-                    }
-                }
-                Err(e) => {
-                    let msg = format!("Failed to wait on sudo: {}", e);
-                    eprintln!("{}", msg);
-                    Err(msg)
-                }
-            }
-        }
-        Err(e) => {
-            let msg = format!("Failed to spawn sudo: {}", e);
-            eprintln!("{}", msg);
-            Err(msg)
-        }
+    let target = if cursor == first_non_blank {
+        line_start
+    } else {
+        first_non_blank
+    };
+
+    if extend {
+        buffer.move_mark(&insert, &target);
+    } else {
+        buffer.place_cursor(&target);
     }
+    text_view.scroll_mark_onscreen(&insert);
 }
 
-fn perform_sudo_save(path: &Path, content: &str, password: &str) -> Result<(), String> {
-    use std::io::Write;
-    use std::process::{Command, Stdio};
+// Called before `text` is inserted at `location`. Returns true when it has
+// handled the insertion itself and the default one must not happen:
+// - typing the closer auto-pairing just added moves past it;
+// - typing an opener inserts the pair in one go (so one undo step) with the
+//   cursor in between. That only happens before whitespace, a closer or the
+//   end, and quotes aren't paired straight after a word character.
+fn auto_pair_insert(
+    buffer: &gtk::TextBuffer,
+    doc_state: &DocumentState,
+    location: &mut gtk::TextIter,
+    text: &str,
+) -> bool {
+    let mut chars = text.chars();
+    let (Some(typed), None) = (chars.next(), chars.next()) else {
+        return false;
+    };
 
-    // 1. Write to temp file
-    let temp_dir = std::env::temp_dir();
-    let temp_file = temp_dir.join("rpad_sudo_save.tmp");
-    if let Err(e) = fs::write(&temp_file, content) {
-        return Err(format!("Failed to write temp file: {}", e));
+    let at_closer = doc_state
+        .auto_pair_mark
+        .borrow()
+        .as_ref()
+        .is_some_and(|mark| buffer.iter_at_mark(mark) == *location && location.char() == typed);
+    if at_closer {
+        forget_auto_pair_mark(buffer, doc_state);
+        let mut past = *location;
+        past.forward_char();
+        buffer.place_cursor(&past);
+        return true;
     }
 
-    let status = Command::new("sudo")
-        .arg("-S")
-        .arg("cp")
-        .arg(&temp_file)
-        .arg(path)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped()) // Capture error if any
-        .spawn();
+    let Some(&(open, close)) = AUTO_PAIRS.iter().find(|(open, _)| *open == typed) else {
+        return false;
+    };
 
-    match status {
-        Ok(mut child) => {
-            if let Some(mut stdin) = child.stdin.take() {
-                let _ = stdin.write_all(format!("{}\n", password).as_bytes());
-            }
-            match child.wait() {
-                Ok(status) => {
-                    let _ = fs::remove_file(temp_file);
-                    if status.success() {
-                        Ok(())
-                    } else {
-                        Err("Sudo save failed".to_string())
-                    }
-                }
-                Err(e) => Err(format!("Failed to wait on sudo: {}", e)),
-            }
+    let next = location.char();
+    let next_allows_pair =
+        next == '\0' || next.is_whitespace() || AUTO_PAIRS.iter().any(|&(_, c)| c == next);
+    if !next_allows_pair {
+        return false;
+    }
+    if open == close {
+        let mut prev = *location;
+        if prev.backward_char() && prev.char().is_alphanumeric() {
+            return false;
         }
-        Err(e) => Err(format!("Failed to spawn sudo: {}", e)),
     }
+
+    let offset = location.offset();
+    buffer.insert(location, &format!("{open}{close}"));
+    let inside = buffer.iter_at_offset(offset + 1);
+    buffer.place_cursor(&inside);
+    forget_auto_pair_mark(buffer, doc_state);
+    *doc_state.auto_pair_mark.borrow_mut() = Some(buffer.create_mark(None, &inside, false));
+    true
 }
 
-fn set_sudo_state(window: &gtk::ApplicationWindow, active: bool) {
-    use gtk::gio;
+// Called before a newline is inserted at `location`. Returns true when it
+// has handled the Enter itself: after a list item or quote line the new
+// line starts with the same prefix (numbered items counting up), and an
+// item left empty loses its prefix rather than getting a new line.
+fn continue_markdown_list(buffer: &gtk::TextBuffer, location: &mut gtk::TextIter) -> bool {
+    let mut line_start = *location;
+    line_start.set_line_offset(0);
+    let mut line_end = *location;
+    if !line_end.ends_line() {
+        line_end.forward_to_line_end();
+    }
 
-    unsafe {
-        if let Some(doc_state_ptr) = window.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
+    match markdown::list_enter(&line_start.slice(location)) {
+        Some(markdown::ListEnter::Continue(prefix)) => {
+            buffer.insert(location, &format!("\n{prefix}"));
+            true
+        }
+        Some(markdown::ListEnter::End) if location.slice(&line_end).trim().is_empty() => {
+            buffer.delete(&mut line_start, location);
+            true
+        }
+        _ => false,
+    }
+}
 
-            // Update Title
-            let base_title = match doc_state.path() {
-                Some(path) => format!("rpad - {}", path.display()),
-                None => "rpad - Untitled".to_string(),
-            };
+// Called before `text` is inserted at `location`, like auto_pair_insert.
+// In prose (no language, or Markdown outside a `code span`) a typed quote,
+// dash or dot becomes its typographic form. The characters it absorbs are
+// removed without an undo step of their own, so one Undo goes back to
+// before the key that triggered it.
+fn smart_quotes_insert(
+    buffer: &sv::Buffer,
+    doc_state: &DocumentState,
+    location: &mut gtk::TextIter,
+    text: &str,
+) -> bool {
+    let mut chars = text.chars();
+    let (Some(typed), None) = (chars.next(), chars.next()) else {
+        return false;
+    };
+    if buffer
+        .language()
+        .is_some_and(|language| language.id() != "markdown")
+    {
+        return false;
+    }
 
-            let suffix = if active { " [SUDO]" } else { "" };
-            let mode_suffix = match doc_state.mode() {
-                Mode::Plain => " [Plain]",
-                Mode::Markup => " [Markdown]",
-            };
-            window.set_title(Some(&format!("{}{}{}", base_title, suffix, mode_suffix)));
+    let mut line_start = *location;
+    line_start.set_line_offset(0);
+    let before = line_start.slice(location);
+    if before.matches('`').count() % 2 == 1 {
+        return false;
+    }
+    let Some((replaced, substitute)) = text_ops::smart_substitution(&before, typed) else {
+        return false;
+    };
 
-            // Update Status Label
-            doc_state.label_sudo.set_visible(active);
+    if replaced > 0 {
+        let mut start = *location;
+        start.backward_chars(replaced as i32);
+        *doc_state.is_programmatic.borrow_mut() = true;
+        buffer.delete(&mut start, location);
+        *doc_state.is_programmatic.borrow_mut() = false;
+    }
+    buffer.insert(location, &substitute.to_string());
+    true
+}
 
-            // Update Action State
-            if let Some(app) = window.application() {
-                if let Some(action) = app.lookup_action("sudo_mode") {
-                    if let Some(stateful_action) = action.downcast_ref::<gio::SimpleAction>() {
-                        if stateful_action.state() != Some(active.to_variant()) {
-                            stateful_action.set_state(&active.into());
-                        }
-                    }
-                }
-            }
-        }
+fn forget_auto_pair_mark(buffer: &gtk::TextBuffer, doc_state: &DocumentState) {
+    if let Some(mark) = doc_state.auto_pair_mark.borrow_mut().take() {
+        buffer.delete_mark(&mark);
     }
 }
 
-fn save_as_with_dialog_and_then_close(window: &gtk::ApplicationWindow) {
-    use gtk::{FileChooserAction, FileFilter, ResponseType};
+// Replace start..end with `text` as one user action
+fn replace_range(
+    buffer: &gtk::TextBuffer,
+    start: &mut gtk::TextIter,
+    end: &mut gtk::TextIter,
+    text: &str,
+) {
+    buffer.begin_user_action();
+    buffer.delete(start, end);
+    buffer.insert(start, text);
+    buffer.end_user_action();
+}
 
-    let dialog = gtk::FileChooserDialog::new(
-        Some("Save File"),
-        Some(window),
-        FileChooserAction::Save,
-        &[
-            ("_Cancel", ResponseType::Cancel),
-            ("_Save", ResponseType::Accept),
-        ],
+// Wrap the selection in bold or italic markers, or unwrap it if it has
+// them already, leaving the text inside selected. Without a selection the
+// cursor ends up between a fresh pair of markers.
+fn toggle_emphasis(buffer: &gtk::TextBuffer, emphasis: markdown::Emphasis) {
+    let (mut start, mut end) = buffer.selection_bounds().unwrap_or_else(|| {
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        (cursor, cursor)
+    });
+    let selected = start.slice(&end).to_string();
+    let toggled = markdown::toggle_emphasis(&selected, emphasis);
+    let offset = start.offset();
+    replace_range(buffer, &mut start, &mut end, &toggled);
+
+    let mut inner_start = offset;
+    let mut inner_end = offset + toggled.chars().count() as i32;
+    if toggled.len() > selected.len() {
+        let marker = emphasis.marker().len() as i32;
+        inner_start += marker;
+        inner_end -= marker;
+    }
+    buffer.select_range(
+        &buffer.iter_at_offset(inner_start),
+        &buffer.iter_at_offset(inner_end),
     );
+}
 
-    let mode = current_mode(window);
-    let default_name = match mode {
-        Mode::Plain => "Untitled.txt",
-        Mode::Markup => "Untitled.md",
+// Rewrite the cursor line's heading markers for the level `level(current)`
+// returns, keeping the cursor on the same character of the heading text
+fn change_heading_level(buffer: &gtk::TextBuffer, level: impl Fn(usize) -> usize) {
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    let Some(mut start) = buffer.iter_at_line(cursor.line()) else {
+        return;
     };
-    dialog.set_current_name(default_name);
+    let mut end = start;
+    if !end.ends_line() {
+        end.forward_to_line_end();
+    }
 
-    let text_filter = FileFilter::new();
-    text_filter.set_name(Some("Text Files (*.txt)"));
-    text_filter.add_pattern("*.txt");
-    dialog.add_filter(&text_filter);
+    let line = start.slice(&end).to_string();
+    let heading = markdown::set_heading_level(&line, level(markdown::heading_prefix_level(&line)));
+    if heading == line {
+        return;
+    }
+    let from_end = end.offset() - cursor.offset();
+    let line_start = start.offset();
+    replace_range(buffer, &mut start, &mut end, &heading);
 
-    let md_filter = FileFilter::new();
-    md_filter.set_name(Some("Markdown Files (*.md, *.markdown)"));
-    md_filter.add_pattern("*.md");
-    md_filter.add_pattern("*.markdown");
-    dialog.add_filter(&md_filter);
+    let line_end = line_start + heading.chars().count() as i32;
+    buffer.place_cursor(&buffer.iter_at_offset((line_end - from_end).max(line_start)));
+}
 
-    let all_filter = FileFilter::new();
-    all_filter.set_name(Some("All Files"));
-    all_filter.add_pattern("*");
-    dialog.add_filter(&all_filter);
+// Turn the selection into a Markdown link to a URL asked for here; with no
+// selection the link text is left empty with the cursor in it
+fn open_link_dialog(window: &gtk::ApplicationWindow, text_view: &sv::View) {
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title("Insert Link")
+        .build();
+    close_on_escape(&dialog);
 
-    let window_clone = window.clone();
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Insert", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let content = dialog.content_area();
+    content.set_spacing(6);
+    content.set_margin_top(6);
+    content.set_margin_bottom(6);
+    content.set_margin_start(6);
+    content.set_margin_end(6);
+
+    let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some("URL:"));
+    let entry = gtk::Entry::new();
+    entry.set_hexpand(true);
+    entry.set_activates_default(true);
+    hbox.append(&label);
+    hbox.append(&entry);
+    content.append(&hbox);
+
+    let text_view_clone = text_view.clone();
     dialog.connect_response(move |dialog, response| {
-        if response == ResponseType::Accept {
-            if let Some(file) = dialog.file() {
-                if let Some(path) = file.path() {
-                    match save_buffer_to_path(&window_clone, path.as_ref()) {
-                        Ok(()) => {
-                            window_clone.close();
-                        }
-                        Err(err) => {
-                            eprintln!("Error saving file: {err}");
-                        }
-                    }
-                }
+        let url = entry.text();
+        if response == gtk::ResponseType::Accept && !url.is_empty() {
+            let buffer = text_view_clone.buffer();
+            let (mut start, mut end) = buffer.selection_bounds().unwrap_or_else(|| {
+                let cursor = buffer.iter_at_mark(&buffer.get_insert());
+                (cursor, cursor)
+            });
+            let text = start.slice(&end).to_string();
+            let offset = start.offset();
+            replace_range(&buffer, &mut start, &mut end, &format!("[{text}]({url})"));
+            if text.is_empty() {
+                buffer.place_cursor(&buffer.iter_at_offset(offset + 1));
             }
         }
-
         dialog.close();
     });
 
     dialog.show();
 }
 
-fn apply_language_for_mode(buffer: &sv::Buffer, mode: Mode) {
-    let lm = sv::LanguageManager::default();
+// Ask for a size and insert an empty Markdown table on its own lines at
+// the cursor
+fn open_table_dialog(window: &gtk::ApplicationWindow, text_view: &sv::View) {
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title("Insert Table")
+        .build();
+    close_on_escape(&dialog);
 
-    match mode {
-        Mode::Plain => {
-            buffer.set_language(None::<&sv::Language>);
-        }
-        Mode::Markup => {
-            if let Some(lang) = lm.language("markdown") {
-                buffer.set_language(Some(&lang));
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Insert", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let content = dialog.content_area();
+    content.set_spacing(6);
+    content.set_margin_top(6);
+    content.set_margin_bottom(6);
+    content.set_margin_start(6);
+    content.set_margin_end(6);
+
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(6);
+    grid.set_column_spacing(6);
+    let rows = gtk::SpinButton::with_range(1.0, MAX_TABLE_SIZE as f64, 1.0);
+    rows.set_value(2.0);
+    let columns = gtk::SpinButton::with_range(1.0, MAX_TABLE_SIZE as f64, 1.0);
+    columns.set_value(3.0);
+    for (row, (text, spin)) in [("Rows:", &rows), ("Columns:", &columns)]
+        .into_iter()
+        .enumerate()
+    {
+        let label = gtk::Label::new(Some(text));
+        label.set_xalign(0.0);
+        grid.attach(&label, 0, row as i32, 1, 1);
+        grid.attach(spin, 1, row as i32, 1, 1);
+    }
+    content.append(&grid);
+
+    let text_view_clone = text_view.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Accept {
+            let table = markdown::table(
+                rows.value_as_int() as usize,
+                columns.value_as_int() as usize,
+            );
+            let buffer = text_view_clone.buffer();
+            let (mut start, mut end) = buffer.selection_bounds().unwrap_or_else(|| {
+                let cursor = buffer.iter_at_mark(&buffer.get_insert());
+                (cursor, cursor)
+            });
+            let table = if start.starts_line() {
+                table
             } else {
-                buffer.set_language(None::<&sv::Language>);
-            }
+                format!("\n{table}")
+            };
+            replace_range(&buffer, &mut start, &mut end, &table);
+            text_view_clone.scroll_mark_onscreen(&buffer.get_insert());
         }
-    }
-}
+        dialog.close();
+    });
 
-fn search_in_buffer(
-    buffer: &sv::Buffer,
-    text_view: &sv::View,
-    pattern: &str,
-    forward: bool,
-    match_case: bool,
-) -> Option<(gtk::TextIter, gtk::TextIter)> {
-    if pattern.is_empty() {
-        return None;
+    dialog.show();
+}
+
+// Swap the characters either side of the cursor and step past them. At the
+// end of a line the two characters before the cursor are swapped instead;
+// at the start of one there is nothing to swap.
+fn transpose_chars(buffer: &gtk::TextBuffer) {
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    if cursor.starts_line() {
+        return;
     }
 
-    let mut flags = gtk::TextSearchFlags::TEXT_ONLY;
-    if !match_case {
-        flags |= gtk::TextSearchFlags::CASE_INSENSITIVE;
+    let (mut start, mut end) = (cursor, cursor);
+    if cursor.ends_line() {
+        if cursor.line_offset() < 2 {
+            return;
+        }
+        start.backward_chars(2);
+    } else {
+        start.backward_char();
+        end.forward_char();
     }
 
-    let insert_mark = buffer.get_insert();
-    let iter = buffer.iter_at_mark(&insert_mark);
+    let swapped: String = buffer.text(&start, &end, true).chars().rev().collect();
+    replace_range(buffer, &mut start, &mut end, &swapped);
+    buffer.place_cursor(&start);
+}
 
-    let result = if forward {
-        iter.forward_search(pattern, flags, None).or_else(|| {
-            let start = buffer.start_iter();
-            start.forward_search(pattern, flags, None)
-        })
-    } else {
-        iter.backward_search(pattern, flags, None).or_else(|| {
-            let end = buffer.end_iter();
-            end.backward_search(pattern, flags, None)
-        })
-    };
+// Swap the word at or before the cursor with the word after it, leaving
+// the cursor after both. A no-op without a word on each side.
+fn transpose_words(buffer: &gtk::TextBuffer) {
+    let mut second_end = buffer.iter_at_mark(&buffer.get_insert());
+    // In the middle of a word, that word is the first of the two
+    if second_end.inside_word() && !second_end.starts_word() {
+        second_end.forward_word_end();
+    }
+    if !second_end.forward_word_end() {
+        return;
+    }
+    let mut second_start = second_end;
+    second_start.backward_word_start();
 
-    if let Some((mut match_start, match_end)) = result {
-        buffer.select_range(&match_start, &match_end);
-        text_view.scroll_to_iter(&mut match_start, 0.1, false, 0.0, 0.0);
-        Some((match_start, match_end))
-    } else {
-        None
+    let mut first_start = second_start;
+    if !first_start.backward_word_start() {
+        return;
     }
+    let mut first_end = first_start;
+    first_end.forward_word_end();
+
+    let first = buffer.text(&first_start, &first_end, true);
+    let between = buffer.text(&first_end, &second_start, true);
+    let second = buffer.text(&second_start, &second_end, true);
+    let swapped = format!("{second}{between}{first}");
+
+    replace_range(buffer, &mut first_start, &mut second_end, &swapped);
+    buffer.place_cursor(&first_start);
 }
 
-fn do_find_next(window: &gtk::ApplicationWindow, text_view: &sv::View) {
-    unsafe {
-        if let Some(doc_state_ptr) = window.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
-            let pattern = doc_state.find_text.borrow().clone();
-            if pattern.is_empty() {
-                return;
-            }
-            let match_case = *doc_state.match_case.borrow();
-            let buffer = text_view
-                .buffer()
-                .downcast::<sv::Buffer>()
-                .expect("Buffer is not sv::Buffer");
-            let _ = search_in_buffer(&buffer, text_view, &pattern, true, match_case);
+// Replace the selection with f(selection) as one user action, keeping it selected
+fn transform_selection(buffer: &gtk::TextBuffer, f: impl Fn(&str) -> String) {
+    if let Some((mut start, mut end)) = buffer.selection_bounds() {
+        let text = buffer.text(&start, &end, true);
+        let replaced = f(&text);
+        if replaced == text.as_str() {
+            return;
         }
+
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        let start_offset = start.offset();
+        buffer.insert(&mut start, &replaced);
+        let new_start = buffer.iter_at_offset(start_offset);
+        buffer.select_range(&new_start, &start);
+        buffer.end_user_action();
     }
 }
 
-fn do_find_prev(window: &gtk::ApplicationWindow, text_view: &sv::View) {
-    unsafe {
-        if let Some(doc_state_ptr) = window.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
-            let pattern = doc_state.find_text.borrow().clone();
-            if pattern.is_empty() {
-                return;
-            }
-            let match_case = *doc_state.match_case.borrow();
-            let buffer = text_view
-                .buffer()
-                .downcast::<sv::Buffer>()
-                .expect("Buffer is not sv::Buffer");
-            let _ = search_in_buffer(&buffer, text_view, &pattern, false, match_case);
-        }
+fn set_zoom(doc_state: &DocumentState, value: u32) {
+    *doc_state.zoom.borrow_mut() = value.clamp(ZOOM_MIN, ZOOM_MAX);
+    update_zoom_css(doc_state);
+}
+
+// Zoom is remembered both as the default and for the file being edited
+fn remember_zoom(doc_state: &DocumentState) {
+    let zoom = *doc_state.zoom.borrow();
+    Config::update(|config| config.zoom = Some(zoom));
+    if let Some(path) = doc_state.path() {
+        Session::update_file(&path, |file| file.zoom = Some(zoom));
     }
 }
 
-fn open_find_dialog(window: &gtk::ApplicationWindow, text_view: &sv::View) {
+fn open_zoom_dialog(window: &gtk::ApplicationWindow) {
     let dialog = gtk::Dialog::builder()
         .transient_for(window)
         .modal(true)
-        .title("Find")
+        .title("Zoom")
         .build();
+    close_on_escape(&dialog);
 
     dialog.add_button("Cancel", gtk::ResponseType::Cancel);
-    dialog.add_button("Find Next", gtk::ResponseType::Accept);
+    dialog.add_button("Zoom", gtk::ResponseType::Accept);
 
     let content = dialog.content_area();
     content.set_spacing(6);
@@ -1718,151 +7689,423 @@ fn open_find_dialog(window: &gtk::ApplicationWindow, text_view: &sv::View) {
     content.set_margin_end(6);
 
     let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 6);
-    let label = gtk::Label::new(Some("Find what:"));
+    let label = gtk::Label::new(Some(&format!("Zoom ({}–{}%):", ZOOM_MIN, ZOOM_MAX)));
     let entry = gtk::Entry::new();
     entry.set_hexpand(true);
     hbox.append(&label);
     hbox.append(&entry);
+    content.append(&hbox);
 
-    let match_case_cb = gtk::CheckButton::with_label("Match case");
-
-    unsafe {
-        if let Some(doc_state_ptr) = window.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
-            entry.set_text(&doc_state.find_text.borrow());
-            match_case_cb.set_active(*doc_state.match_case.borrow());
-        }
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        entry.set_text(&doc_state.zoom.borrow().to_string());
     }
 
-    content.append(&hbox);
-    content.append(&match_case_cb);
-
     let win_clone = window.clone();
-    let text_view_clone = text_view.clone();
     let entry_clone = entry.clone();
-    let match_case_cb_clone = match_case_cb.clone();
 
     dialog.connect_response(move |dialog, response| {
         if response == gtk::ResponseType::Accept {
-            let text = entry_clone.text().to_string();
-            let match_case = match_case_cb_clone.is_active();
-
-            unsafe {
-                if let Some(doc_state_ptr) = win_clone.data::<DocumentState>("rpad-doc-state") {
-                    let doc_state: &DocumentState = doc_state_ptr.as_ref();
-                    *doc_state.find_text.borrow_mut() = text.clone();
-                    *doc_state.match_case.borrow_mut() = match_case;
+            let text = entry_clone.text();
+            if let Ok(percent) = text.trim().trim_end_matches('%').parse::<u32>() {
+                if let Some(doc_state) = active_doc_state(&win_clone).as_deref() {
+                    set_zoom(doc_state, percent);
                 }
             }
-
-            let buffer = text_view_clone
-                .buffer()
-                .downcast::<sv::Buffer>()
-                .expect("Buffer is not sv::Buffer");
-            let _ = search_in_buffer(&buffer, &text_view_clone, &text, true, match_case);
         }
+
         dialog.close();
     });
 
     dialog.show();
 }
 
-fn open_replace_dialog(window: &gtk::ApplicationWindow, text_view: &sv::View) {
+// Read-only unified diff of the saved file against the buffer
+fn open_changes_dialog(window: &gtk::ApplicationWindow) {
+    let path = active_doc_state(window).and_then(|doc_state| doc_state.path());
+    let Some(path) = path else {
+        return;
+    };
+    let Some(buffer) = get_text_buffer_from_window(window) else {
+        return;
+    };
+
+    let saved = match fs::read_to_string(&path) {
+        Ok(saved) => saved,
+        Err(err) => {
+            show_error_dialog(
+                window,
+                "Cannot compare with the saved file",
+                &format!("{}: {}", path.display(), err),
+            );
+            return;
+        }
+    };
+    // The buffer never holds the byte order mark
+    let saved = saved.strip_prefix(BOM).unwrap_or(&saved);
+    let (start, end) = buffer.bounds();
+    let current = buffer.text(&start, &end, true);
+
+    let name = path.display().to_string();
+    let diff = text_ops::unified_diff(saved, &current, &name, &format!("{name} (unsaved)"));
+
     let dialog = gtk::Dialog::builder()
         .transient_for(window)
         .modal(true)
-        .title("Replace")
+        .title("Changes")
+        .default_width(700)
+        .default_height(500)
         .build();
+    close_on_escape(&dialog);
+    dialog.add_button("Close", gtk::ResponseType::Close);
 
-    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
-    dialog.add_button("Replace", gtk::ResponseType::Accept);
+    // A diff buffer gets +/- lines coloured by the diff language
+    let diff_buffer = sv::Buffer::new(None);
+    if let Some(language) = sv::LanguageManager::default().language("diff") {
+        diff_buffer.set_language(Some(&language));
+    }
+    if let Some(editor_buffer) = buffer.downcast_ref::<sv::Buffer>() {
+        diff_buffer.set_style_scheme(editor_buffer.style_scheme().as_ref());
+    }
+    diff_buffer.set_text(if diff.is_empty() {
+        "No changes since the file was saved."
+    } else {
+        &diff
+    });
 
-    let content = dialog.content_area();
-    content.set_spacing(6);
-    content.set_margin_top(6);
-    content.set_margin_bottom(6);
-    content.set_margin_start(6);
-    content.set_margin_end(6);
+    let diff_view = sv::View::with_buffer(&diff_buffer);
+    diff_view.set_editable(false);
+    diff_view.set_monospace(true);
+    diff_view.set_left_margin(8);
+    diff_view.set_top_margin(8);
 
-    // Find row
-    let find_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
-    let find_label = gtk::Label::new(Some("Find what:"));
-    let find_entry = gtk::Entry::new();
-    find_entry.set_hexpand(true);
-    find_box.append(&find_label);
-    find_box.append(&find_entry);
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&diff_view)
+        .hexpand(true)
+        .vexpand(true)
+        .build();
+    dialog.content_area().append(&scrolled);
 
-    // Replace row
-    let replace_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
-    let replace_label = gtk::Label::new(Some("Replace with:"));
-    let replace_entry = gtk::Entry::new();
-    replace_entry.set_hexpand(true);
-    replace_box.append(&replace_label);
-    replace_box.append(&replace_entry);
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
 
-    let match_case_cb = gtk::CheckButton::with_label("Match case");
+// The quick reference in assets/help.md, read-only and highlighted as
+// Markdown the way Markup mode shows it. Like Tasks, it can stay open
+// beside the document.
+fn open_help_dialog(window: &gtk::ApplicationWindow) {
+    const HELP_TEXT: &str = include_str!("../assets/help.md");
 
-    unsafe {
-        if let Some(doc_state_ptr) = window.data::<DocumentState>("rpad-doc-state") {
-            let doc_state: &DocumentState = doc_state_ptr.as_ref();
-            find_entry.set_text(&doc_state.find_text.borrow());
-            match_case_cb.set_active(*doc_state.match_case.borrow());
-        }
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .title("rpad Help")
+        .default_width(600)
+        .default_height(600)
+        .build();
+    close_on_escape(&dialog);
+    dialog.add_button("Close", gtk::ResponseType::Close);
+
+    let help_buffer = sv::Buffer::new(None);
+    apply_language_for_mode(&help_buffer, Mode::Markup);
+    if let Some(editor_buffer) = get_text_buffer_from_window(window)
+        .as_ref()
+        .and_then(|buffer| buffer.downcast_ref::<sv::Buffer>())
+    {
+        help_buffer.set_style_scheme(editor_buffer.style_scheme().as_ref());
     }
+    help_buffer.set_text(HELP_TEXT);
 
-    content.append(&find_box);
-    content.append(&replace_box);
-    content.append(&match_case_cb);
+    let help_view = sv::View::with_buffer(&help_buffer);
+    help_view.set_editable(false);
+    help_view.set_wrap_mode(gtk::WrapMode::WordChar);
+    help_view.set_left_margin(12);
+    help_view.set_right_margin(12);
+    help_view.set_top_margin(12);
+    help_view.set_bottom_margin(12);
 
-    let win_clone = window.clone();
-    let text_view_clone = text_view.clone();
-    let find_entry_clone = find_entry.clone();
-    let replace_entry_clone = replace_entry.clone();
-    let match_case_cb_clone = match_case_cb.clone();
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&help_view)
+        .hexpand(true)
+        .vexpand(true)
+        .build();
+    dialog.content_area().append(&scrolled);
+
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
+
+// List the lines holding task markers (TODO, FIXME, …); activating one
+// moves the cursor there. The dialog stays open for jumping around.
+fn open_tasks_dialog(window: &gtk::ApplicationWindow) {
+    let Some(text_view) = get_text_view_from_window(window) else {
+        return;
+    };
+    let buffer = text_view.buffer();
+    let (start, end) = buffer.bounds();
+    let text = buffer.text(&start, &end, true);
+
+    let markers = Config::load().task_markers.unwrap_or_else(|| {
+        DEFAULT_TASK_MARKERS
+            .iter()
+            .map(|marker| marker.to_string())
+            .collect()
+    });
+    let tasks = text_ops::tasks(&text, &markers);
+
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .title(format!("Tasks ({})", tasks.len()))
+        .default_width(500)
+        .default_height(400)
+        .build();
+    close_on_escape(&dialog);
+    dialog.add_button("Close", gtk::ResponseType::Close);
+
+    let list = gtk::ListBox::new();
+    list.set_selection_mode(gtk::SelectionMode::Browse);
+    for task in &tasks {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+        row.set_margin_start(8);
+        row.set_margin_end(8);
+        row.set_margin_top(4);
+        row.set_margin_bottom(4);
+
+        let line_label = gtk::Label::new(Some(&(task.line + 1).to_string()));
+        line_label.set_width_chars(5);
+        line_label.set_xalign(1.0);
+        line_label.add_css_class("dim-label");
+        let text_label = gtk::Label::new(Some(&task.text));
+        text_label.set_xalign(0.0);
+        text_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+
+        row.append(&line_label);
+        row.append(&text_label);
+        list.append(&row);
+    }
+    if tasks.is_empty() {
+        list.set_placeholder(Some(&gtk::Label::new(Some(&format!(
+            "No {} in this document.",
+            markers.join(", ")
+        )))));
+    }
+
+    let text_view_weak = text_view.downgrade();
+    list.connect_row_activated(move |_, row| {
+        let Some(text_view) = text_view_weak.upgrade() else {
+            return;
+        };
+        if let Some(task) = usize::try_from(row.index()).ok().and_then(|i| tasks.get(i)) {
+            goto_line(&text_view, task.line as i32 + 1);
+            text_view.grab_focus();
+        }
+    });
+
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&list)
+        .hexpand(true)
+        .vexpand(true)
+        .build();
+    dialog.content_area().append(&scrolled);
+
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
+
+// Shown once when a file with more than one kind of line ending opens
+fn notify_mixed_line_endings(window: &gtk::ApplicationWindow, path: &Path, counts: [usize; 3]) {
+    let kinds: Vec<String> = text_ops::LineEnding::ALL
+        .iter()
+        .zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .map(|(ending, count)| format!("{} {}", count, ending.name()))
+        .collect();
+
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Info)
+        .buttons(gtk::ButtonsType::None)
+        .text("Mixed line endings")
+        .secondary_text(format!(
+            "{} has line endings of more than one kind ({}). Tools → Normalize Line Endings converts them all to one.",
+            path.display(),
+            kinds.join(", ")
+        ))
+        .build();
+
+    dialog.add_button("Leave As Is", gtk::ResponseType::Reject);
+    dialog.add_button("Normalize…", gtk::ResponseType::Accept);
 
+    let window_clone = window.clone();
     dialog.connect_response(move |dialog, response| {
+        dialog.close();
         if response == gtk::ResponseType::Accept {
-            let find_text = find_entry_clone.text().to_string();
-            let replace_text = replace_entry_clone.text().to_string();
-            let match_case = match_case_cb_clone.is_active();
+            open_line_endings_dialog(&window_clone);
+        }
+    });
+    dialog.show();
+}
 
-            unsafe {
-                if let Some(doc_state_ptr) = win_clone.data::<DocumentState>("rpad-doc-state") {
-                    let doc_state: &DocumentState = doc_state_ptr.as_ref();
-                    *doc_state.find_text.borrow_mut() = find_text.clone();
-                    *doc_state.match_case.borrow_mut() = match_case;
-                }
-            }
+// Convert every line ending in the document to the chosen kind, as one
+// undo step. The kind most lines already use is preselected.
+fn open_line_endings_dialog(window: &gtk::ApplicationWindow) {
+    let Some(buffer) = get_text_buffer_from_window(window) else {
+        return;
+    };
+    let (start, end) = buffer.bounds();
+    let dominant = text_ops::dominant_line_ending(&buffer.text(&start, &end, true))
+        .unwrap_or(text_ops::LineEnding::Lf);
 
-            let buffer = text_view_clone
-                .buffer()
-                .downcast::<sv::Buffer>()
-                .expect("Buffer is not sv::Buffer");
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title("Normalize Line Endings")
+        .build();
+    close_on_escape(&dialog);
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Convert", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    content.set_margin_start(12);
+    content.set_margin_end(12);
+    content.set_margin_top(12);
+    content.set_margin_bottom(12);
+    let label = gtk::Label::new(Some("Convert every line ending to:"));
+    label.set_xalign(0.0);
+    content.append(&label);
+
+    let mut choices: Vec<(text_ops::LineEnding, gtk::CheckButton)> = Vec::new();
+    for ending in text_ops::LineEnding::ALL {
+        let description = match ending {
+            text_ops::LineEnding::Lf => "LF (Linux, macOS)",
+            text_ops::LineEnding::CrLf => "CRLF (Windows)",
+            text_ops::LineEnding::Cr => "CR (classic Mac OS)",
+        };
+        let button = gtk::CheckButton::with_label(description);
+        if let Some((_, first)) = choices.first() {
+            button.set_group(Some(first));
+        }
+        button.set_active(ending == dominant);
+        content.append(&button);
+        choices.push((ending, button));
+    }
+    dialog.content_area().append(&content);
 
-            if let Some((mut start, mut end)) =
-                search_in_buffer(&buffer, &text_view_clone, &find_text, true, match_case)
-            {
-                buffer.begin_user_action();
-                buffer.delete(&mut start, &mut end);
-                buffer.insert(&mut start, &replace_text);
-                buffer.end_user_action();
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Accept {
+            if let Some((ending, _)) = choices.iter().find(|(_, button)| button.is_active()) {
+                transform_document(&buffer, |text| {
+                    text_ops::normalize_line_endings(text, *ending)
+                });
             }
         }
         dialog.close();
     });
+    dialog.show();
+}
+
+// Lines, words, characters and reading time of the current document, with
+// buttons to copy the summary or save it to a file
+fn open_statistics_dialog(window: &gtk::ApplicationWindow) {
+    let Some(buffer) = get_text_buffer_from_window(window) else {
+        return;
+    };
+    let path = active_doc_state(window).and_then(|doc_state| doc_state.path());
+    let name = path
+        .as_deref()
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().into_owned());
+
+    let (start, end) = buffer.bounds();
+    let stats = text_ops::compute_stats(&buffer.text(&start, &end, true));
+    let minutes = stats.reading_minutes();
+    let summary = format!(
+        "Document: {}\nLines: {}\nWords: {}\nCharacters: {}\nReading time: {} minute{}\n",
+        name.as_deref().unwrap_or("Untitled"),
+        stats.lines,
+        stats.words,
+        stats.chars,
+        minutes,
+        if minutes == 1 { "" } else { "s" }
+    );
+
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title("Statistics")
+        .build();
+    close_on_escape(&dialog);
+    dialog.add_button("Copy", STATS_COPY_RESPONSE);
+    dialog.add_button("Save…", STATS_SAVE_RESPONSE);
+    dialog.add_button("Close", gtk::ResponseType::Close);
+
+    let label = gtk::Label::new(Some(summary.trim_end()));
+    label.set_selectable(true);
+    label.set_xalign(0.0);
+    label.set_margin_start(16);
+    label.set_margin_end(16);
+    label.set_margin_top(12);
+    label.set_margin_bottom(12);
+    dialog.content_area().append(&label);
 
+    let window_clone = window.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == STATS_COPY_RESPONSE {
+            window_clone.clipboard().set_text(&summary);
+        } else if response == STATS_SAVE_RESPONSE {
+            let stem = name
+                .as_deref()
+                .map(|name| name.rsplit_once('.').map_or(name, |(stem, _)| stem))
+                .unwrap_or("Untitled");
+            save_statistics(&window_clone, &format!("{stem}-stats.txt"), summary.clone());
+        }
+        dialog.close();
+    });
     dialog.show();
 }
 
-fn open_goto_dialog(window: &gtk::ApplicationWindow, text_view: &sv::View) {
+fn save_statistics(window: &gtk::ApplicationWindow, default_name: &str, summary: String) {
+    use gtk::{FileChooserAction, ResponseType};
+
+    let dialog = gtk::FileChooserDialog::new(
+        Some("Save Statistics"),
+        Some(window),
+        FileChooserAction::Save,
+        &[
+            ("_Cancel", ResponseType::Cancel),
+            ("_Save", ResponseType::Accept),
+        ],
+    );
+    dialog.set_current_name(default_name);
+
+    let window_clone = window.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                if let Err(err) = fs::write(&path, &summary) {
+                    show_error_dialog(
+                        &window_clone,
+                        "Cannot save statistics",
+                        &format!("{}: {}", path.display(), err),
+                    );
+                }
+            }
+        }
+        dialog.close();
+    });
+    dialog.show();
+}
+
+fn open_right_margin_dialog(window: &gtk::ApplicationWindow) {
     let dialog = gtk::Dialog::builder()
         .transient_for(window)
         .modal(true)
-        .title("Go To Line")
+        .title("Right Margin")
         .build();
+    close_on_escape(&dialog);
 
     dialog.add_button("Cancel", gtk::ResponseType::Cancel);
-    dialog.add_button("Go To", gtk::ResponseType::Accept);
+    dialog.add_button("Set", gtk::ResponseType::Accept);
 
     let content = dialog.content_area();
     content.set_spacing(6);
@@ -1872,39 +8115,31 @@ fn open_goto_dialog(window: &gtk::ApplicationWindow, text_view: &sv::View) {
     content.set_margin_end(6);
 
     let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 6);
-    let label = gtk::Label::new(Some("Line number:"));
+    let label = gtk::Label::new(Some(&format!("Column (1–{}):", MAX_RIGHT_MARGIN_COLUMN)));
     let entry = gtk::Entry::new();
     entry.set_hexpand(true);
     hbox.append(&label);
     hbox.append(&entry);
     content.append(&hbox);
 
-    let text_view_clone = text_view.clone();
+    if let Some(doc_state) = active_doc_state(window).as_deref() {
+        entry.set_text(&doc_state.right_margin_column.borrow().to_string());
+    }
+
+    let win_clone = window.clone();
     let entry_clone = entry.clone();
 
     dialog.connect_response(move |dialog, response| {
         if response == gtk::ResponseType::Accept {
-            if let Ok(line_num) = entry_clone.text().parse::<i32>() {
-                let buffer = text_view_clone.buffer().upcast::<gtk::TextBuffer>();
-                let mut line = line_num - 1;
-                let max_lines = buffer.line_count();
-
-                if max_lines > 0 {
-                    if line < 0 {
-                        line = 0;
-                    }
-                    if line >= max_lines {
-                        line = max_lines - 1;
-                    }
-
-                    let mut iter = buffer.start_iter();
-                    if line > 0 {
-                        iter.forward_lines(line);
-                    }
-
-                    buffer.place_cursor(&iter);
-                    text_view_clone.scroll_to_iter(&mut iter, 0.1, false, 0.0, 0.0);
-                }
+            if let Ok(column) = entry_clone.text().trim().parse::<u32>() {
+                // Picking a column implies wanting to see the guide
+                let column = column.clamp(1, MAX_RIGHT_MARGIN_COLUMN);
+                set_right_margin_everywhere(&win_clone, true, column);
+                set_action_state(&win_clone, "right_margin", &true.to_variant());
+                Config::update(|c| {
+                    c.right_margin = Some(true);
+                    c.right_margin_column = Some(column);
+                });
             }
         }
 
@@ -1914,22 +8149,103 @@ fn open_goto_dialog(window: &gtk::ApplicationWindow, text_view: &sv::View) {
     dialog.show();
 }
 
+// Apply f to the selection, or to the whole document when nothing is selected
+fn transform_selection_or_document(buffer: &gtk::TextBuffer, f: impl Fn(&str) -> String) {
+    if buffer.has_selection() {
+        transform_selection(buffer, f);
+        return;
+    }
+
+    let (mut start, mut end) = buffer.bounds();
+    let text = buffer.text(&start, &end, true);
+    let replaced = f(&text);
+    if replaced != text.as_str() {
+        replace_range(buffer, &mut start, &mut end, &replaced);
+    }
+}
+
+// Replace the whole text with f(text) as one user action, keeping the
+// cursor on its line
+fn transform_document(buffer: &gtk::TextBuffer, f: impl Fn(&str) -> String) {
+    let (mut start, mut end) = buffer.bounds();
+    let text = buffer.text(&start, &end, true);
+    let replaced = f(&text);
+    if replaced == text.as_str() {
+        return;
+    }
+
+    let line = buffer.iter_at_mark(&buffer.get_insert()).line();
+    replace_range(buffer, &mut start, &mut end, &replaced);
+    if let Some(iter) = buffer.iter_at_line(line) {
+        buffer.place_cursor(&iter);
+    }
+}
+
 fn update_zoom_css(doc_state: &DocumentState) {
-    let zoom = *doc_state.zoom.borrow();
+    let mut zoom = *doc_state.zoom.borrow();
+    if *doc_state.accessible.borrow() {
+        zoom = zoom * ACCESSIBLE_FONT_SCALE / 100;
+    }
     let css = format!("textview {{ font-size: {}%; }}", zoom);
     doc_state.css_provider.load_from_data(&css);
 }
 
+// Show `text` in the status bar for a few seconds; a newer message
+// replaces it and restarts the clock
+fn flash_status_message(status: &StatusBar, text: &str) {
+    if let Some(source_id) = status.message_timeout.borrow_mut().take() {
+        source_id.remove();
+    }
+    status.label_message.set_text(text);
+    status.label_message.set_visible(true);
+
+    let label = status.label_message.clone();
+    let message_timeout = status.message_timeout.clone();
+    let source_id = glib::timeout_add_seconds_local_once(STATUS_MESSAGE_SECS, move || {
+        message_timeout.borrow_mut().take();
+        label.set_visible(false);
+    });
+    *status.message_timeout.borrow_mut() = Some(source_id);
+}
+
+// The local time as "14:32", for status messages
+fn clock_time() -> String {
+    glib::DateTime::now_local()
+        .and_then(|now| now.format("%H:%M"))
+        .map(String::from)
+        .unwrap_or_default()
+}
+
+// Counting walks the whole buffer, so batch keystrokes into one recount
+fn schedule_count_update(window: &gtk::ApplicationWindow, doc_state: &DocumentState) {
+    if doc_state.status.counts_timeout.borrow().is_some() {
+        return;
+    }
+
+    let window = window.clone();
+    let counts_timeout = doc_state.status.counts_timeout.clone();
+    let source_id = glib::timeout_add_local_once(
+        std::time::Duration::from_millis(COUNTS_UPDATE_INTERVAL_MS),
+        move || {
+            counts_timeout.borrow_mut().take();
+            if let Some(doc_state) = active_doc_state(&window).as_deref() {
+                if let Some(buffer) = get_text_buffer_from_window(&window) {
+                    update_counts(doc_state, &buffer);
+                }
+            }
+        },
+    );
+    *doc_state.status.counts_timeout.borrow_mut() = Some(source_id);
+}
+
 fn update_counts(doc_state: &DocumentState, buffer: &gtk::TextBuffer) {
     let (start, end) = buffer.bounds();
-    let text = buffer.text(&start, &end, false);
-
-    let char_count = text.chars().count();
-    let word_count = text.split_whitespace().count();
+    let stats = text_ops::compute_stats(&buffer.text(&start, &end, true));
 
     doc_state
+        .status
         .label_words_chars
-        .set_text(&format!("{} words, {} chars", word_count, char_count));
+        .set_text(&format!("{} words, {} chars", stats.words, stats.chars));
 }
 
 fn update_cursor(doc_state: &DocumentState, buffer: &gtk::TextBuffer) {
@@ -1939,6 +8255,41 @@ fn update_cursor(doc_state: &DocumentState, buffer: &gtk::TextBuffer) {
     let col = iter.line_offset() + 1;
 
     doc_state
+        .status
         .label_line_col
         .set_text(&format!("Ln {}, Col {}", line, col));
+
+    if *doc_state.cursor_in_title.borrow() {
+        if let Some(window) = doc_state
+            .status
+            .status_box
+            .root()
+            .and_downcast::<gtk::ApplicationWindow>()
+        {
+            update_title(&window);
+        }
+    }
+
+    update_selection_span(doc_state, buffer);
+}
+
+// "3 lines selected" while there is a selection. A selection ending at the
+// very start of a line doesn't count that line.
+fn update_selection_span(doc_state: &DocumentState, buffer: &gtk::TextBuffer) {
+    let label = &doc_state.status.label_selection;
+    let Some((start, end)) = buffer.selection_bounds() else {
+        label.set_visible(false);
+        return;
+    };
+
+    let mut lines = end.line() - start.line() + 1;
+    if end.starts_line() && end.line() > start.line() {
+        lines -= 1;
+    }
+    label.set_text(&if lines == 1 {
+        "1 line selected".to_string()
+    } else {
+        format!("{} lines selected", lines)
+    });
+    label.set_visible(true);
 }
@@ -0,0 +1,327 @@
+// Markdown the editor needs to read or write, line by line and without a
+// full parse
+
+// Level (1–6) of an ATX heading such as "## Notes", or None for any other
+// line. Up to three spaces of indentation are allowed, as in CommonMark.
+pub fn heading_level(line: &str) -> Option<usize> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+
+    let rest = &line[indent..];
+    let level = rest.len() - rest.trim_start_matches('#').len();
+    let after = &rest[level..];
+    let separated = after.is_empty() || after.starts_with([' ', '\t']);
+    ((1..=6).contains(&level) && separated).then_some(level)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heading {
+    pub line: usize,
+    pub level: usize,
+}
+
+// Headings of `text` in order, skipping lines inside ``` or ~~~ code fences
+pub fn headings(text: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut fence: Option<&str> = None;
+
+    for (line, content) in text.lines().enumerate() {
+        let trimmed = content.trim_start();
+        let marker = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| trimmed.starts_with(marker));
+
+        match (fence, marker) {
+            (None, Some(marker)) => fence = Some(marker),
+            (Some(open), Some(marker)) if open == marker => fence = None,
+            (None, None) => {
+                if let Some(level) = heading_level(content) {
+                    headings.push(Heading { line, level });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    headings
+}
+
+// The line a heading's section runs up to (exclusive): the next heading at
+// the same or a higher level, or `line_count` when there is none
+pub fn section_end(headings: &[Heading], index: usize, line_count: usize) -> usize {
+    let level = headings[index].level;
+    headings[index + 1..]
+        .iter()
+        .find(|heading| heading.level <= level)
+        .map_or(line_count, |heading| heading.line)
+}
+
+// Heading level going by the `#`s a line starts with, whether or not
+// they're followed by a space (so "##Notes" counts as 2); 0 for none
+pub fn heading_prefix_level(line: &str) -> usize {
+    let rest = line.trim_start();
+    let hashes = rest.len() - rest.trim_start_matches('#').len();
+    hashes.min(6)
+}
+
+// `line` made a heading of `level` (1–6), or plain text for 0. Leading
+// `#`s and the whitespace around them are replaced by a single "## ".
+pub fn set_heading_level(line: &str, level: usize) -> String {
+    let text = line.trim_start().trim_start_matches('#').trim_start();
+    match level.min(6) {
+        0 => text.to_string(),
+        level if text.is_empty() => "#".repeat(level),
+        level => format!("{} {text}", "#".repeat(level)),
+    }
+}
+
+// An empty table with `columns` headed "Column 1", "Column 2", … and
+// `rows` rows of blank cells below the separator, each column padded to
+// the width of its header. Ends with a newline.
+pub fn table(rows: usize, columns: usize) -> String {
+    let headers: Vec<String> = (1..=columns.max(1))
+        .map(|column| format!("Column {column}"))
+        .collect();
+    let row = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+
+    let mut table = row(headers.clone());
+    table += &row(headers.iter().map(|h| "-".repeat(h.len())).collect());
+    for _ in 0..rows {
+        table += &row(headers.iter().map(|h| " ".repeat(h.len())).collect());
+    }
+    table
+}
+
+// What Enter should do after a list item or quote line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListEnter {
+    // Start the next line with this prefix ("- ", "3. ", "> ", indented
+    // like the line before)
+    Continue(String),
+    // The item was left empty: drop its prefix instead of adding a line
+    End,
+}
+
+// How Enter continues `line` (the text before the cursor), or None when it
+// isn't a "- ", "* ", "+ ", "1. " or "> " line. Ordered items count up.
+pub fn list_enter(line: &str) -> Option<ListEnter> {
+    let rest = line.trim_start_matches([' ', '\t']);
+    let indent = &line[..line.len() - rest.len()];
+
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (marker, text) = if let Some(text) = ["- ", "* ", "+ ", "> "]
+        .iter()
+        .find_map(|marker| rest.strip_prefix(marker))
+    {
+        (rest[..2].to_string(), text)
+    } else if (1..=9).contains(&digits) && rest[digits..].starts_with(". ") {
+        let number: u64 = rest[..digits].parse().ok()?;
+        (format!("{}. ", number + 1), &rest[digits + 2..])
+    } else {
+        return None;
+    };
+
+    if text.trim().is_empty() {
+        Some(ListEnter::End)
+    } else {
+        Some(ListEnter::Continue(format!("{indent}{marker}")))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emphasis {
+    Italic,
+    Bold,
+}
+
+impl Emphasis {
+    pub fn marker(self) -> &'static str {
+        match self {
+            Emphasis::Italic => "*",
+            Emphasis::Bold => "**",
+        }
+    }
+}
+
+// `text` wrapped in `emphasis`'s markers, or with them taken off when it's
+// already wrapped. "***both***" counts as bold and as italic, so either
+// kind comes off it without touching the other.
+pub fn toggle_emphasis(text: &str, emphasis: Emphasis) -> String {
+    let leading = text.len() - text.trim_start_matches('*').len();
+    let trailing = text.len() - text.trim_end_matches('*').len();
+    let run = leading.min(trailing);
+    let wrapped = text.len() > 2 * run
+        && match emphasis {
+            Emphasis::Italic => run % 2 == 1,
+            Emphasis::Bold => run >= 2,
+        };
+
+    let marker = emphasis.marker();
+    if wrapped {
+        text[marker.len()..text.len() - marker.len()].to_string()
+    } else {
+        format!("{marker}{text}{marker}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_separator_and_cells_match_the_header_widths() {
+        assert_eq!(
+            table(1, 2),
+            "| Column 1 | Column 2 |\n\
+             | -------- | -------- |\n\
+             |          |          |\n"
+        );
+        // Double-digit columns get wider separators
+        let wide = table(0, 10);
+        let separator = wide.lines().nth(1).unwrap();
+        assert!(separator.ends_with("| --------- |"));
+    }
+
+    #[test]
+    fn table_has_the_requested_number_of_blank_rows() {
+        for rows in [0, 1, 5] {
+            let lines = table(rows, 3).lines().count();
+            assert_eq!(lines, rows + 2);
+        }
+    }
+
+    #[test]
+    fn table_has_at_least_one_column() {
+        assert_eq!(table(1, 0), table(1, 1));
+        assert!(table(0, 0).starts_with("| Column 1 |\n"));
+    }
+
+    #[test]
+    fn heading_level_needs_a_space_after_one_to_six_hashes() {
+        assert_eq!(heading_level("# Title"), Some(1));
+        assert_eq!(heading_level("###### Six"), Some(6));
+        assert_eq!(heading_level("##\tTabbed"), Some(2));
+        assert_eq!(heading_level("##"), Some(2));
+        assert_eq!(heading_level("##Notes"), None);
+        assert_eq!(heading_level("####### Seven"), None);
+        assert_eq!(heading_level("Not # a heading"), None);
+    }
+
+    #[test]
+    fn heading_level_allows_up_to_three_spaces_of_indentation() {
+        assert_eq!(heading_level("   ## Indented"), Some(2));
+        assert_eq!(heading_level("    ## Code"), None);
+    }
+
+    #[test]
+    fn headings_skip_code_fences() {
+        let text = "# A\n```\n# not\n```\n## B\n~~~\n```\n# still code\n~~~\n# C";
+        assert_eq!(
+            headings(text),
+            [
+                Heading { line: 0, level: 1 },
+                Heading { line: 4, level: 2 },
+                Heading { line: 9, level: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn section_ends_at_the_next_heading_of_its_level_or_higher() {
+        let headings = headings("# A\ntext\n## B\ntext\n## C\n### D\n# E\ntext");
+        assert_eq!(section_end(&headings, 0, 8), 6);
+        assert_eq!(section_end(&headings, 1, 8), 4);
+        assert_eq!(section_end(&headings, 2, 8), 6);
+        assert_eq!(section_end(&headings, 3, 8), 6);
+        assert_eq!(section_end(&headings, 4, 8), 8);
+    }
+
+    #[test]
+    fn toggle_emphasis_wraps_and_unwraps() {
+        assert_eq!(toggle_emphasis("word", Emphasis::Bold), "**word**");
+        assert_eq!(toggle_emphasis("**word**", Emphasis::Bold), "word");
+        assert_eq!(toggle_emphasis("word", Emphasis::Italic), "*word*");
+        assert_eq!(toggle_emphasis("*word*", Emphasis::Italic), "word");
+    }
+
+    #[test]
+    fn toggle_emphasis_adds_one_kind_to_the_other() {
+        assert_eq!(toggle_emphasis("*word*", Emphasis::Bold), "***word***");
+        assert_eq!(toggle_emphasis("**word**", Emphasis::Italic), "***word***");
+    }
+
+    #[test]
+    fn toggle_emphasis_takes_either_kind_off_both() {
+        assert_eq!(toggle_emphasis("***both***", Emphasis::Bold), "*both*");
+        assert_eq!(toggle_emphasis("***both***", Emphasis::Italic), "**both**");
+    }
+
+    #[test]
+    fn heading_prefix_level_counts_hashes_with_or_without_a_space() {
+        assert_eq!(heading_prefix_level("##Notes"), 2);
+        assert_eq!(heading_prefix_level("  ### Notes"), 3);
+        assert_eq!(heading_prefix_level("########"), 6);
+        assert_eq!(heading_prefix_level("Notes"), 0);
+    }
+
+    #[test]
+    fn set_heading_level_replaces_the_hashes() {
+        assert_eq!(set_heading_level("Notes", 2), "## Notes");
+        assert_eq!(set_heading_level("### Notes", 1), "# Notes");
+        assert_eq!(set_heading_level("  #Notes", 2), "## Notes");
+        assert_eq!(set_heading_level("## Notes", 0), "Notes");
+        assert_eq!(set_heading_level("Notes", 9), "###### Notes");
+    }
+
+    #[test]
+    fn set_heading_level_on_an_empty_line_adds_only_hashes() {
+        assert_eq!(set_heading_level("", 3), "###");
+        assert_eq!(set_heading_level("## ", 1), "#");
+        assert_eq!(set_heading_level("##", 0), "");
+    }
+
+    #[test]
+    fn list_enter_continues_bullets_and_quotes() {
+        let next = |line| list_enter(line).unwrap();
+        assert_eq!(next("- item"), ListEnter::Continue("- ".to_string()));
+        assert_eq!(next("* item"), ListEnter::Continue("* ".to_string()));
+        assert_eq!(next("+ item"), ListEnter::Continue("+ ".to_string()));
+        assert_eq!(next("> quote"), ListEnter::Continue("> ".to_string()));
+    }
+
+    #[test]
+    fn list_enter_counts_ordered_items_up() {
+        let next = |line| list_enter(line).unwrap();
+        assert_eq!(next("1. one"), ListEnter::Continue("2. ".to_string()));
+        assert_eq!(next("9. nine"), ListEnter::Continue("10. ".to_string()));
+        assert_eq!(next("99. x"), ListEnter::Continue("100. ".to_string()));
+    }
+
+    #[test]
+    fn list_enter_keeps_the_indentation() {
+        let next = |line| list_enter(line).unwrap();
+        assert_eq!(next("  - nested"), ListEnter::Continue("  - ".to_string()));
+        assert_eq!(
+            next("\t3. tabbed"),
+            ListEnter::Continue("\t4. ".to_string())
+        );
+    }
+
+    #[test]
+    fn list_enter_ends_the_list_on_an_empty_item() {
+        assert_eq!(list_enter("- "), Some(ListEnter::End));
+        assert_eq!(list_enter("  3.   "), Some(ListEnter::End));
+        assert_eq!(list_enter("> "), Some(ListEnter::End));
+    }
+
+    #[test]
+    fn list_enter_ignores_other_lines() {
+        assert_eq!(list_enter("plain text"), None);
+        assert_eq!(list_enter("-item"), None);
+        assert_eq!(list_enter("1) item"), None);
+        assert_eq!(list_enter("1234567890. too long"), None);
+        assert_eq!(list_enter(""), None);
+    }
+}
@@ -0,0 +1,100 @@
+// Copies of documents with unsaved changes, kept in
+// $XDG_CACHE_HOME/rpad/recovery so their text survives a crash. Each
+// document has an id "<pid>-<random>"; its text goes to <id>.txt and, once
+// it has one, its own path to <id>.path. Files left behind by a process
+// that is no longer running mean that process didn't exit cleanly.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use gtk4::glib;
+
+#[derive(Debug, Clone)]
+pub struct Recovered {
+    id: String,
+    // Where the document was saved before, if it ever was
+    pub path: Option<PathBuf>,
+    pub text: String,
+}
+
+fn dir() -> PathBuf {
+    glib::user_cache_dir().join("rpad").join("recovery")
+}
+
+// A fresh id for a document in this process
+pub fn new_id() -> String {
+    format!("{}-{:016x}", std::process::id(), rand::random::<u64>())
+}
+
+pub fn save(id: &str, path: Option<&Path>, text: &str) {
+    let dir = dir();
+    let result = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(dir.join(format!("{id}.txt")), text))
+        .and_then(|_| match path {
+            Some(path) => fs::write(
+                dir.join(format!("{id}.path")),
+                path.as_os_str().as_encoded_bytes(),
+            ),
+            None => remove_if_exists(&dir.join(format!("{id}.path"))),
+        });
+    if let Err(err) = result {
+        eprintln!("Error writing recovery file: {err}");
+    }
+}
+
+// Called once a document is saved, reverted or closed
+pub fn remove(id: &str) {
+    let dir = dir();
+    for extension in ["txt", "path"] {
+        if let Err(err) = remove_if_exists(&dir.join(format!("{id}.{extension}"))) {
+            eprintln!("Error removing recovery file: {err}");
+        }
+    }
+}
+
+// Documents left behind by rpad processes that have since gone away
+pub fn leftovers() -> Vec<Recovered> {
+    let Ok(entries) = fs::read_dir(dir()) else {
+        return Vec::new();
+    };
+
+    let mut recovered: Vec<Recovered> = entries
+        .filter_map(|entry| {
+            let file = entry.ok()?.path();
+            if file.extension()? != "txt" {
+                return None;
+            }
+            let id = file.file_stem()?.to_str()?.to_string();
+            let pid: u32 = id.split_once('-')?.0.parse().ok()?;
+            if is_running(pid) {
+                return None;
+            }
+
+            let text = fs::read_to_string(&file).ok()?;
+            let path = fs::read_to_string(file.with_extension("path"))
+                .ok()
+                .map(PathBuf::from);
+            Some(Recovered { id, path, text })
+        })
+        .collect();
+    recovered.sort_by(|a, b| a.path.cmp(&b.path));
+    recovered
+}
+
+// Drop a leftover once it has been restored or turned down
+pub fn discard(recovered: &Recovered) {
+    remove(&recovered.id);
+}
+
+// Whether a process with this id still runs. Without /proc there is no
+// cheap way to tell, so every other process counts as gone.
+fn is_running(pid: u32) -> bool {
+    pid == std::process::id() || Path::new("/proc").join(pid.to_string()).exists()
+}
+
+fn remove_if_exists(file: &Path) -> std::io::Result<()> {
+    match fs::remove_file(file) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,178 @@
+// Find's match-finding, on plain text so it can be tested without a buffer.
+// Offsets count characters, like GtkTextIter offsets.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+// The next match of `pattern` from offset `from`, as (start, end): going
+// forward, the first one starting at or after `from`; going backward, the
+// last one ending at or before it. The search wraps around to the other
+// end of the text when it runs out. None for an empty pattern or no match.
+pub fn find_match(
+    text: &str,
+    pattern: &str,
+    from: usize,
+    direction: Direction,
+    match_case: bool,
+) -> Option<(usize, usize)> {
+    let text: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    if pattern.is_empty() || pattern.len() > text.len() {
+        return None;
+    }
+
+    let matches_at = |start: usize| {
+        text[start..start + pattern.len()]
+            .iter()
+            .zip(&pattern)
+            .all(|(&a, &b)| chars_match(a, b, match_case))
+    };
+    let last_start = text.len() - pattern.len();
+    let from = from.min(text.len());
+
+    let start = match direction {
+        Direction::Forward => (from..=last_start)
+            .find(|&start| matches_at(start))
+            .or_else(|| (0..from.min(last_start + 1)).find(|&start| matches_at(start))),
+        Direction::Backward => {
+            let before = from.checked_sub(pattern.len()).map_or(0, |last| last + 1);
+            (0..before)
+                .rev()
+                .find(|&start| matches_at(start))
+                .or_else(|| (before..=last_start).rev().find(|&start| matches_at(start)))
+        }
+    }?;
+    Some((start, start + pattern.len()))
+}
+
+fn chars_match(a: char, b: char, match_case: bool) -> bool {
+    a == b || (!match_case && a.to_lowercase().eq(b.to_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_next_match_forward() {
+        let text = "one two one two";
+        assert_eq!(
+            find_match(text, "two", 0, Direction::Forward, true),
+            Some((4, 7))
+        );
+        assert_eq!(
+            find_match(text, "two", 5, Direction::Forward, true),
+            Some((12, 15))
+        );
+    }
+
+    #[test]
+    fn a_match_at_the_start_offset_counts_forward() {
+        assert_eq!(
+            find_match("abc abc", "abc", 4, Direction::Forward, true),
+            Some((4, 7))
+        );
+    }
+
+    #[test]
+    fn finds_the_previous_match_backward() {
+        let text = "one two one two";
+        assert_eq!(
+            find_match(text, "one", 15, Direction::Backward, true),
+            Some((8, 11))
+        );
+        // A match has to end at or before the offset
+        assert_eq!(
+            find_match(text, "one", 10, Direction::Backward, true),
+            Some((0, 3))
+        );
+    }
+
+    #[test]
+    fn wraps_around_forward() {
+        assert_eq!(
+            find_match("needle hay hay", "needle", 3, Direction::Forward, true),
+            Some((0, 6))
+        );
+    }
+
+    #[test]
+    fn wraps_around_backward() {
+        assert_eq!(
+            find_match("hay hay needle", "needle", 3, Direction::Backward, true),
+            Some((8, 14))
+        );
+    }
+
+    #[test]
+    fn the_only_match_is_found_again_after_wrapping() {
+        // Starting just past the only match, forward has to come back to it
+        assert_eq!(
+            find_match("x needle y", "needle", 5, Direction::Forward, true),
+            Some((2, 8))
+        );
+    }
+
+    #[test]
+    fn case_sensitivity() {
+        let text = "Hello hello";
+        assert_eq!(
+            find_match(text, "hello", 0, Direction::Forward, true),
+            Some((6, 11))
+        );
+        assert_eq!(
+            find_match(text, "hello", 0, Direction::Forward, false),
+            Some((0, 5))
+        );
+        assert_eq!(
+            find_match("ÄRGER", "ärger", 0, Direction::Forward, false),
+            Some((0, 5))
+        );
+        assert_eq!(
+            find_match("ÄRGER", "ärger", 0, Direction::Forward, true),
+            None
+        );
+    }
+
+    #[test]
+    fn offsets_count_characters_not_bytes() {
+        assert_eq!(
+            find_match("café au lait", "au", 0, Direction::Forward, true),
+            Some((5, 7))
+        );
+    }
+
+    #[test]
+    fn empty_pattern_finds_nothing() {
+        assert_eq!(find_match("text", "", 0, Direction::Forward, true), None);
+        assert_eq!(find_match("text", "", 4, Direction::Backward, false), None);
+    }
+
+    #[test]
+    fn no_match() {
+        assert_eq!(
+            find_match("text", "other", 0, Direction::Forward, true),
+            None
+        );
+        assert_eq!(
+            find_match("text", "x t", 2, Direction::Backward, true),
+            None
+        );
+        assert_eq!(find_match("", "x", 0, Direction::Forward, true), None);
+    }
+
+    #[test]
+    fn start_offset_past_the_end_is_clamped() {
+        assert_eq!(
+            find_match("ab ab", "ab", 99, Direction::Forward, true),
+            Some((0, 2))
+        );
+        assert_eq!(
+            find_match("ab ab", "ab", 99, Direction::Backward, true),
+            Some((3, 5))
+        );
+    }
+}
@@ -0,0 +1,658 @@
+// Pure text transforms behind the editing commands. They work on plain
+// strings so the GTK side only has to swap the result into the buffer.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use similar::TextDiff;
+
+// Joins the lines of `text` with single spaces, dropping the whitespace on
+// both sides of each join point. Blank lines disappear; a trailing newline
+// is kept so joining whole selected lines doesn't swallow the next one.
+pub fn join_lines(text: &str) -> String {
+    let (body, trailing_newline) = split_trailing_newline(text);
+
+    let mut lines = body.split('\n');
+    let mut joined = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        let line = line.trim_start();
+        if line.is_empty() {
+            continue;
+        }
+        joined.truncate(joined.trim_end().len());
+        if !joined.is_empty() {
+            joined.push(' ');
+        }
+        joined.push_str(line);
+    }
+
+    joined.push_str(trailing_newline);
+    joined
+}
+
+pub fn reverse_lines(text: &str) -> String {
+    let (body, trailing_newline) = split_trailing_newline(text);
+    let mut lines: Vec<&str> = body.split('\n').collect();
+    lines.reverse();
+    lines.join("\n") + trailing_newline
+}
+
+pub fn shuffle_lines<R: Rng + ?Sized>(text: &str, rng: &mut R) -> String {
+    let (body, trailing_newline) = split_trailing_newline(text);
+    let mut lines: Vec<&str> = body.split('\n').collect();
+    lines.shuffle(rng);
+    lines.join("\n") + trailing_newline
+}
+
+// Each line as part of a Markdown block quote: "> " in front, or a bare
+// ">" on blank lines so the quote carries on through them
+pub fn quote_lines(text: &str) -> String {
+    let (body, trailing_newline) = split_trailing_newline(text);
+    let quoted: Vec<String> = body
+        .split('\n')
+        .map(|line| {
+            if line.trim().is_empty() {
+                ">".to_string()
+            } else {
+                format!("> {line}")
+            }
+        })
+        .collect();
+    quoted.join("\n") + trailing_newline
+}
+
+// Smart Quotes: what typing `typed` turns into, given `before`, the text
+// before the cursor on its line. Returns how many characters before the
+// cursor to replace along with it, and the typographic character to put in
+// their place: curly quotes (opening after whitespace or an opening
+// bracket, closing or an apostrophe otherwise), "--" as an em dash and
+// "..." as an ellipsis. Lines of nothing but dashes, pipes and colons (a
+// Markdown rule or table separator) keep their dashes.
+pub fn smart_substitution(before: &str, typed: char) -> Option<(usize, char)> {
+    let previous = before.chars().next_back();
+    let opens = previous.is_none_or(|c| c.is_whitespace() || "([{<\u{2014}".contains(c));
+
+    match typed {
+        '"' => Some((0, if opens { '\u{201c}' } else { '\u{201d}' })),
+        '\'' => Some((0, if opens { '\u{2018}' } else { '\u{2019}' })),
+        '-' if previous == Some('-') => {
+            let rule = before
+                .chars()
+                .all(|c| c.is_whitespace() || "-|:".contains(c));
+            (!rule).then_some((1, '\u{2014}'))
+        }
+        '.' if before.ends_with("..") => Some((2, '\u{2026}')),
+        _ => None,
+    }
+}
+
+// Line diff from `old` to `new` in unified format, with three lines of
+// context around each change; empty when they're the same
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(old_name, new_name)
+        .to_string()
+}
+
+// Where `new` differs from `old`, in chars: the change starts at the
+// first offset and ends at the second in `old` and the third in `new`
+pub fn changed_span(old: &str, new: &str) -> (usize, usize, usize) {
+    let prefix = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_len = old.chars().count();
+    let new_len = new.chars().count();
+    let suffix = old
+        .chars()
+        .rev()
+        .zip(new.chars().rev())
+        .take(old_len.min(new_len) - prefix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    (prefix, old_len - suffix, new_len - suffix)
+}
+
+// Width of a line's leading whitespace in columns, with tabs advancing to
+// the next multiple of `tab_width`. None for a blank line, which has no
+// indentation of its own.
+pub fn indent_columns(line: &str, tab_width: u32) -> Option<u32> {
+    let mut columns = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => columns += 1,
+            '\t' => columns += tab_width - columns % tab_width,
+            c if c.is_whitespace() => {}
+            _ => return Some(columns),
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    Tabs,
+    // Spaces per indent level
+    Spaces(u32),
+}
+
+// Lines looked at when guessing a file's indentation
+const INDENT_SAMPLE_LINES: usize = 1000;
+
+// Guess how `text` is indented from the leading whitespace of its first
+// lines. Tabs win if more lines start with a tab than with spaces; the
+// space width is the most common step between consecutive indented lines.
+// None when nothing is indented.
+pub fn detect_indentation(text: &str) -> Option<Indent> {
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    // How often each step (2 to 8 spaces) occurs between neighbouring lines
+    let mut steps = [0usize; 9];
+    let mut previous = 0;
+
+    for line in text.lines().take(INDENT_SAMPLE_LINES) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with('\t') {
+            tab_lines += 1;
+            continue;
+        }
+
+        let spaces = line.len() - line.trim_start_matches(' ').len();
+        if spaces > 0 {
+            space_lines += 1;
+        }
+        let step = spaces.abs_diff(previous);
+        if (2..steps.len()).contains(&step) {
+            steps[step] += 1;
+        }
+        previous = spaces;
+    }
+
+    if tab_lines == 0 && space_lines == 0 {
+        None
+    } else if tab_lines > space_lines {
+        Some(Indent::Tabs)
+    } else {
+        // Ties go to the smaller step, so 2-space files with some 4-space
+        // jumps still read as 2
+        let width = (2..steps.len())
+            .rev()
+            .max_by_key(|&step| steps[step])
+            .filter(|&step| steps[step] > 0)
+            .unwrap_or(4);
+        Some(Indent::Spaces(width as u32))
+    }
+}
+
+// `replacement` cased like `matched`: all upper case if the match is
+// ("FOO"), capitalized if the match starts with a capital ("Foo"), and
+// lower case otherwise. A match without letters has no case to follow, so
+// the replacement stays as typed.
+pub fn preserve_case(matched: &str, replacement: &str) -> String {
+    let has_upper = matched.chars().any(char::is_uppercase);
+    let has_lower = matched.chars().any(char::is_lowercase);
+
+    if !has_upper && !has_lower {
+        replacement.to_string()
+    } else if has_upper && !has_lower {
+        replacement.to_uppercase()
+    } else if matched
+        .chars()
+        .find(|c| c.is_alphabetic())
+        .is_some_and(char::is_uppercase)
+    {
+        let lower = replacement.to_lowercase();
+        let mut chars = lower.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    } else {
+        replacement.to_lowercase()
+    }
+}
+
+// Leading tabs become spaces, keeping each line's indentation width.
+// Whitespace after the first other character is left alone.
+pub fn tabs_to_spaces(text: &str, tab_width: u32) -> String {
+    map_indentation(text, tab_width, |columns| " ".repeat(columns as usize))
+}
+
+// Leading runs of spaces become tabs wherever they fill a whole tab stop;
+// spaces short of the next stop stay as they are
+pub fn spaces_to_tabs(text: &str, tab_width: u32) -> String {
+    map_indentation(text, tab_width, |columns| {
+        let tabs = "\t".repeat((columns / tab_width) as usize);
+        tabs + &" ".repeat((columns % tab_width) as usize)
+    })
+}
+
+// Rewrite each line's leading spaces and tabs as `indent(width in columns)`.
+// Lines of nothing but whitespace have no indentation to convert and stay
+// as they are.
+fn map_indentation(text: &str, tab_width: u32, indent: impl Fn(u32) -> String) -> String {
+    let tab_width = tab_width.max(1);
+    text.split_inclusive('\n')
+        .map(|line| {
+            let rest = line.trim_start_matches([' ', '\t']);
+            if rest.trim_start_matches(['\r', '\n']).is_empty() {
+                return line.to_string();
+            }
+            let leading = &line[..line.len() - rest.len()];
+            let columns = leading.chars().fold(0, |columns, c| match c {
+                '\t' => columns + tab_width - columns % tab_width,
+                _ => columns + 1,
+            });
+            indent(columns) + rest
+        })
+        .collect()
+}
+
+// Length in bytes of the longest line
+pub fn longest_line(text: &str) -> usize {
+    text.split('\n').map(str::len).max().unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    // A lone carriage return, as classic Mac OS wrote them
+    Cr,
+}
+
+impl LineEnding {
+    pub const ALL: [LineEnding; 3] = [LineEnding::Lf, LineEnding::CrLf, LineEnding::Cr];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+            LineEnding::Cr => "CR",
+        }
+    }
+}
+
+// Spaces and tabs at the end of each line removed, keeping the line
+// breaks themselves (CRLF included) as they are
+pub fn trim_trailing_whitespace(text: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|line| {
+            let body = line.trim_end_matches(['\r', '\n']);
+            body.trim_end_matches([' ', '\t']).to_string() + &line[body.len()..]
+        })
+        .collect()
+}
+
+// `text` with a line break at the end, of the kind it mostly uses already
+// (LF when it has none). Empty text stays empty.
+pub fn ensure_final_newline(text: &str) -> String {
+    if text.is_empty() || text.ends_with(['\n', '\r']) {
+        return text.to_string();
+    }
+    let ending = dominant_line_ending(text).unwrap_or(LineEnding::Lf);
+    format!("{text}{}", ending.as_str())
+}
+
+// How many line breaks of each kind `text` has, in LineEnding::ALL order
+pub fn count_line_endings(text: &str) -> [usize; 3] {
+    let mut counts = [0; 3];
+    let mut bytes = text.bytes().peekable();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'\n' => counts[0] += 1,
+            b'\r' if bytes.peek() == Some(&b'\n') => {
+                bytes.next();
+                counts[1] += 1;
+            }
+            b'\r' => counts[2] += 1,
+            _ => {}
+        }
+    }
+    counts
+}
+
+// The kind most line breaks use, LF winning ties; None without any
+pub fn dominant_line_ending(text: &str) -> Option<LineEnding> {
+    let counts = count_line_endings(text);
+    let mut dominant = None;
+    let mut most = 0;
+    for (ending, count) in LineEnding::ALL.into_iter().zip(counts) {
+        if count > most {
+            dominant = Some(ending);
+            most = count;
+        }
+    }
+    dominant
+}
+
+// Every CRLF, LF and lone CR in `text` becomes `ending`
+pub fn normalize_line_endings(text: &str, ending: LineEnding) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                normalized.push_str(ending.as_str());
+            }
+            '\n' => normalized.push_str(ending.as_str()),
+            _ => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+// Reading speed behind Stats::reading_minutes, typical for prose
+const READING_WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    // Counted like the editor does, so a trailing newline starts a last,
+    // empty line
+    pub lines: usize,
+    pub words: usize,
+    pub chars: usize,
+}
+
+impl Stats {
+    // Rounded up, so any text at all takes at least a minute
+    pub fn reading_minutes(&self) -> usize {
+        self.words.div_ceil(READING_WORDS_PER_MINUTE)
+    }
+}
+
+pub fn compute_stats(text: &str) -> Stats {
+    Stats {
+        lines: text.split('\n').count(),
+        words: text.split_whitespace().count(),
+        chars: text.chars().count(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    // 0-based
+    pub line: usize,
+    // The rest of the line from the marker on, e.g. "TODO: handle errors"
+    pub text: String,
+}
+
+// Lines containing one of `markers` as a whole word, so "TODO" doesn't
+// match "TODOS" or "MASTODON"
+pub fn tasks(text: &str, markers: &[String]) -> Vec<Task> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    text.lines()
+        .enumerate()
+        .filter_map(|(line, content)| {
+            let start = markers
+                .iter()
+                .filter(|marker| !marker.is_empty())
+                .flat_map(|marker| {
+                    content
+                        .match_indices(marker.as_str())
+                        .filter(|(index, found)| {
+                            let before = content[..*index].chars().next_back();
+                            let after = content[index + found.len()..].chars().next();
+                            !before.is_some_and(is_word) && !after.is_some_and(is_word)
+                        })
+                })
+                .map(|(index, _)| index)
+                .min()?;
+            Some(Task {
+                line,
+                text: content[start..].trim_end().to_string(),
+            })
+        })
+        .collect()
+}
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+// Offset of the bracket matching the one next to `cursor`, trying the one
+// after the cursor before the one before it. Offsets count chars, like GTK
+// text iters; None when neither is a bracket or it's unbalanced.
+pub fn matching_bracket(text: &str, cursor: usize) -> Option<usize> {
+    let chars: Vec<char> = text.chars().collect();
+
+    for position in [Some(cursor), cursor.checked_sub(1)].into_iter().flatten() {
+        let Some(&c) = chars.get(position) else {
+            continue;
+        };
+
+        if let Some(&(open, close)) = BRACKETS.iter().find(|(open, _)| *open == c) {
+            let mut after = chars.iter().enumerate().skip(position);
+            return find_partner(&mut after, open, close);
+        }
+        if let Some(&(open, close)) = BRACKETS.iter().find(|(_, close)| *close == c) {
+            let mut before = chars.iter().enumerate().take(position + 1).rev();
+            return find_partner(&mut before, close, open);
+        }
+    }
+
+    None
+}
+
+// Walk from a bracket (`from`, the first item) until the nesting depth
+// returns to zero at its partner `to`
+fn find_partner<'a>(
+    chars: &mut impl Iterator<Item = (usize, &'a char)>,
+    from: char,
+    to: char,
+) -> Option<usize> {
+    let mut depth = 0usize;
+    for (index, &c) in chars {
+        if c == from {
+            depth += 1;
+        } else if c == to {
+            depth -= 1;
+            if depth == 0 {
+                return Some(index);
+            }
+        }
+    }
+    None
+}
+
+// Line operations act on the lines before a final newline and put it back
+// afterwards, so it doesn't turn into an empty line that moves around
+fn split_trailing_newline(text: &str) -> (&str, &str) {
+    match text.strip_suffix('\n') {
+        Some(body) => (body, "\n"),
+        None => (text, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_span_covers_only_the_difference() {
+        assert_eq!(changed_span("one two", "one new two"), (4, 4, 8));
+        assert_eq!(changed_span("one new two", "one two"), (4, 8, 4));
+        assert_eq!(changed_span("café", "cafe"), (3, 4, 4));
+        assert_eq!(changed_span("same", "same"), (4, 4, 4));
+    }
+
+    #[test]
+    fn changed_span_doesnt_overlap_in_repeated_text() {
+        assert_eq!(changed_span("aa", "aaa"), (2, 2, 3));
+        assert_eq!(changed_span("aaa", "aa"), (2, 3, 2));
+    }
+
+    #[test]
+    fn join_lines_drops_blank_lines() {
+        assert_eq!(join_lines("one\n\n\ntwo"), "one two");
+        assert_eq!(join_lines("one\n   \ntwo"), "one two");
+    }
+
+    #[test]
+    fn join_lines_keeps_the_trailing_newline() {
+        assert_eq!(join_lines("one\ntwo\n"), "one two\n");
+    }
+
+    #[test]
+    fn join_lines_collapses_whitespace_at_the_join() {
+        assert_eq!(join_lines("one  \t\n    two"), "one two");
+        // Whitespace inside a line stays
+        assert_eq!(join_lines("a  b\nc"), "a  b c");
+    }
+
+    #[test]
+    fn join_lines_leaves_a_single_line_alone() {
+        assert_eq!(join_lines("just one  line"), "just one  line");
+        assert_eq!(join_lines("just one\n"), "just one\n");
+        assert_eq!(join_lines(""), "");
+    }
+
+    #[test]
+    fn mixed_line_endings_normalize_to_each_kind() {
+        let mixed = "a\r\nb\nc\rd";
+        assert_eq!(normalize_line_endings(mixed, LineEnding::Lf), "a\nb\nc\nd");
+        assert_eq!(
+            normalize_line_endings(mixed, LineEnding::CrLf),
+            "a\r\nb\r\nc\r\nd"
+        );
+        assert_eq!(normalize_line_endings(mixed, LineEnding::Cr), "a\rb\rc\rd");
+    }
+
+    #[test]
+    fn a_lone_cr_at_the_end_is_a_line_break() {
+        assert_eq!(normalize_line_endings("end\r", LineEnding::Lf), "end\n");
+        assert_eq!(normalize_line_endings("end\r", LineEnding::CrLf), "end\r\n");
+    }
+
+    #[test]
+    fn cr_before_crlf_is_two_line_breaks() {
+        assert_eq!(normalize_line_endings("a\r\r\nb", LineEnding::Lf), "a\n\nb");
+        assert_eq!(
+            normalize_line_endings("a\r\r\nb", LineEnding::CrLf),
+            "a\r\n\r\nb"
+        );
+    }
+
+    #[test]
+    fn tab_indentation_is_detected() {
+        assert_eq!(
+            detect_indentation("fn main() {\n\tlet x = 1;\n\t\tx\n}\n"),
+            Some(Indent::Tabs)
+        );
+    }
+
+    #[test]
+    fn space_widths_are_detected() {
+        assert_eq!(
+            detect_indentation("a:\n  b:\n    c: 1\n  d: 2\n"),
+            Some(Indent::Spaces(2))
+        );
+        assert_eq!(
+            detect_indentation("def f():\n    if x:\n        return 1\n    return 2\n"),
+            Some(Indent::Spaces(4))
+        );
+    }
+
+    #[test]
+    fn two_space_indentation_with_some_four_space_jumps_reads_as_two() {
+        // One step of 2 and one of 4: the tie goes to the smaller width
+        assert_eq!(
+            detect_indentation("a\n  b\n      c\n"),
+            Some(Indent::Spaces(2))
+        );
+        assert_eq!(
+            detect_indentation("a\n  b\n    c\n  d\ne\n    f\ng\n"),
+            Some(Indent::Spaces(2))
+        );
+    }
+
+    #[test]
+    fn unindented_text_has_no_indentation() {
+        assert_eq!(detect_indentation("one\ntwo\nthree\n"), None);
+        assert_eq!(detect_indentation(""), None);
+    }
+
+    #[test]
+    fn blank_lines_are_ignored_when_detecting_indentation() {
+        assert_eq!(
+            detect_indentation("a\n    \n\t\n  b\n\n  c\n"),
+            Some(Indent::Spaces(2))
+        );
+    }
+
+    #[test]
+    fn preserve_case_follows_all_caps() {
+        assert_eq!(preserve_case("FOO", "bar"), "BAR");
+        assert_eq!(preserve_case("FOO_1", "new name"), "NEW NAME");
+    }
+
+    #[test]
+    fn preserve_case_follows_a_capital_first_letter() {
+        assert_eq!(preserve_case("Foo", "bar"), "Bar");
+        assert_eq!(preserve_case("Foo", "BAR"), "Bar");
+        assert_eq!(preserve_case("Élan", "ñame"), "Ñame");
+    }
+
+    #[test]
+    fn preserve_case_follows_lower_case() {
+        assert_eq!(preserve_case("foo", "Bar"), "bar");
+    }
+
+    #[test]
+    fn preserve_case_goes_by_the_first_letter_of_mixed_case() {
+        assert_eq!(preserve_case("fooBar", "BazQux"), "bazqux");
+        assert_eq!(preserve_case("FooBar", "bazQux"), "Bazqux");
+        assert_eq!(preserve_case("_Foo", "bar"), "Bar");
+    }
+
+    #[test]
+    fn preserve_case_leaves_the_replacement_alone_without_letters_to_follow() {
+        assert_eq!(preserve_case("123", "Bar"), "Bar");
+        assert_eq!(preserve_case("--", "eM"), "eM");
+    }
+
+    #[test]
+    fn preserve_case_with_an_empty_replacement() {
+        assert_eq!(preserve_case("Foo", ""), "");
+        assert_eq!(preserve_case("FOO", ""), "");
+    }
+
+    #[test]
+    fn converting_indentation_leaves_tabs_after_the_text_alone() {
+        assert_eq!(tabs_to_spaces("\tx\ty\n", 4), "    x\ty\n");
+        assert_eq!(spaces_to_tabs("    x    y\n", 4), "\tx    y\n");
+    }
+
+    #[test]
+    fn converting_indentation_respects_partial_tab_stops() {
+        // A tab after a space only reaches the next stop
+        assert_eq!(tabs_to_spaces(" \tx", 4), "    x");
+        assert_eq!(tabs_to_spaces("  \t\tx", 4), "        x");
+        // Spaces short of a whole stop stay spaces
+        assert_eq!(spaces_to_tabs("      x", 4), "\t  x");
+        assert_eq!(spaces_to_tabs("  x", 4), "  x");
+    }
+
+    #[test]
+    fn converting_indentation_leaves_whitespace_only_lines_alone() {
+        let text = "a\n    \n\t\n  \t \r\nb";
+        assert_eq!(spaces_to_tabs(text, 4), text);
+        assert_eq!(tabs_to_spaces(text, 4), text);
+    }
+}
@@ -0,0 +1,272 @@
+// Key handling for the optional Vim-style modal editing. This side only
+// turns keys into commands; main.rs carries them out on the buffer.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VimMode {
+    #[default]
+    Normal,
+    Insert,
+    Visual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    LineStart,
+    LineEnd,
+    WordForward,
+    WordBackward,
+    DocumentStart,
+    DocumentEnd,
+}
+
+// Where i, a, I, A, o and O start inserting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertAt {
+    Cursor,
+    AfterCursor,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Move(Motion),
+    Insert(InsertAt),
+    Visual,
+    Normal,
+    DeleteChar,
+    DeleteLine,
+    YankLine,
+    DeleteSelection,
+    YankSelection,
+    Paste { after: bool },
+    Undo,
+    Redo,
+    Search(String),
+    SearchNext { forward: bool },
+    // A ":" command line without the colon, e.g. "wq" or "42"
+    Ex(String),
+}
+
+// Stand-ins for the keys that finish and edit a ":" or "/" line
+pub const ENTER: char = '\n';
+pub const BACKSPACE: char = '\u{8}';
+
+#[derive(Debug, Default)]
+pub struct Vim {
+    pub mode: VimMode,
+    // Keys of a command still being typed, such as "d" or ":wq"
+    pending: String,
+    // Last deleted or yanked text; `linewise` when it was whole lines
+    pub register: String,
+    pub linewise: bool,
+    // Shown in place of the mode until the next key, e.g. for a bad ":" command
+    pub message: Option<String>,
+}
+
+impl Vim {
+    // Feed one key typed in Normal or Visual mode and get back the command
+    // it completes, if any. Keys that don't mean anything are dropped.
+    pub fn key(&mut self, c: char) -> Option<Command> {
+        self.message = None;
+        if self.pending.starts_with([':', '/']) {
+            return self.command_line_key(c);
+        }
+
+        self.pending.push(c);
+        let visual = self.mode == VimMode::Visual;
+        let command = match self.pending.as_str() {
+            ":" | "/" | "g" => return None,
+            "d" | "y" if !visual => return None,
+            "h" => Command::Move(Motion::Left),
+            "l" => Command::Move(Motion::Right),
+            "k" => Command::Move(Motion::Up),
+            "j" => Command::Move(Motion::Down),
+            "0" => Command::Move(Motion::LineStart),
+            "$" => Command::Move(Motion::LineEnd),
+            "w" => Command::Move(Motion::WordForward),
+            "b" => Command::Move(Motion::WordBackward),
+            "gg" => Command::Move(Motion::DocumentStart),
+            "G" => Command::Move(Motion::DocumentEnd),
+            "d" | "x" if visual => Command::DeleteSelection,
+            "y" if visual => Command::YankSelection,
+            "v" if visual => Command::Normal,
+            "v" => Command::Visual,
+            "x" => Command::DeleteChar,
+            "dd" => Command::DeleteLine,
+            "yy" => Command::YankLine,
+            "p" => Command::Paste { after: true },
+            "P" => Command::Paste { after: false },
+            "u" => Command::Undo,
+            "i" => Command::Insert(InsertAt::Cursor),
+            "a" => Command::Insert(InsertAt::AfterCursor),
+            "I" => Command::Insert(InsertAt::LineStart),
+            "A" => Command::Insert(InsertAt::LineEnd),
+            "o" => Command::Insert(InsertAt::LineBelow),
+            "O" => Command::Insert(InsertAt::LineAbove),
+            "n" => Command::SearchNext { forward: true },
+            "N" => Command::SearchNext { forward: false },
+            _ => {
+                self.pending.clear();
+                return None;
+            }
+        };
+        self.pending.clear();
+
+        self.mode = match command {
+            Command::Insert(_) => VimMode::Insert,
+            Command::Visual => VimMode::Visual,
+            Command::Normal | Command::DeleteSelection | Command::YankSelection => VimMode::Normal,
+            _ => self.mode,
+        };
+        Some(command)
+    }
+
+    // ":" and "/" collect a line of text until Enter
+    fn command_line_key(&mut self, c: char) -> Option<Command> {
+        match c {
+            ENTER => {
+                let line = std::mem::take(&mut self.pending);
+                let (prefix, text) = line.split_at(1);
+                let text = text.to_string();
+                match prefix {
+                    ":" => Some(Command::Ex(text)),
+                    // A bare "/" repeats the last search
+                    _ if text.is_empty() => Some(Command::SearchNext { forward: true }),
+                    _ => Some(Command::Search(text)),
+                }
+            }
+            BACKSPACE => {
+                self.pending.pop();
+                None
+            }
+            _ => {
+                self.pending.push(c);
+                None
+            }
+        }
+    }
+
+    // Escape: back to Normal mode, dropping a half-typed command
+    pub fn escape(&mut self) {
+        self.mode = VimMode::Normal;
+        self.pending.clear();
+        self.message = None;
+    }
+
+    // What the status bar shows: a message, the command being typed, or
+    // else the mode
+    pub fn status(&self) -> String {
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        if !self.pending.is_empty() {
+            return self.pending.clone();
+        }
+        match self.mode {
+            VimMode::Normal => "NORMAL".to_string(),
+            VimMode::Insert => "-- INSERT --".to_string(),
+            VimMode::Visual => "-- VISUAL --".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every command the keys complete, in order
+    fn keys(vim: &mut Vim, keys: &str) -> Vec<Command> {
+        keys.chars().filter_map(|c| vim.key(c)).collect()
+    }
+
+    #[test]
+    fn doubled_keys_act_on_the_line() {
+        let mut vim = Vim::default();
+        assert_eq!(vim.key('d'), None);
+        assert_eq!(vim.status(), "d");
+        assert_eq!(vim.key('d'), Some(Command::DeleteLine));
+        assert_eq!(keys(&mut vim, "yy"), [Command::YankLine]);
+        assert_eq!(keys(&mut vim, "gg"), [Command::Move(Motion::DocumentStart)]);
+        assert_eq!(vim.status(), "NORMAL");
+    }
+
+    #[test]
+    fn an_unknown_sequence_is_dropped() {
+        let mut vim = Vim::default();
+        assert_eq!(keys(&mut vim, "dx"), []);
+        assert_eq!(vim.status(), "NORMAL");
+        // The next key starts afresh rather than extending "dx"
+        assert_eq!(keys(&mut vim, "x"), [Command::DeleteChar]);
+    }
+
+    #[test]
+    fn visual_mode_acts_on_the_selection() {
+        let mut vim = Vim::default();
+        assert_eq!(keys(&mut vim, "v"), [Command::Visual]);
+        assert_eq!(vim.mode, VimMode::Visual);
+        assert_eq!(keys(&mut vim, "d"), [Command::DeleteSelection]);
+        assert_eq!(vim.mode, VimMode::Normal);
+
+        assert_eq!(
+            keys(&mut vim, "vy"),
+            [Command::Visual, Command::YankSelection]
+        );
+        assert_eq!(vim.mode, VimMode::Normal);
+
+        assert_eq!(keys(&mut vim, "vv"), [Command::Visual, Command::Normal]);
+        assert_eq!(vim.mode, VimMode::Normal);
+    }
+
+    #[test]
+    fn insert_commands_leave_normal_mode() {
+        let mut vim = Vim::default();
+        assert_eq!(keys(&mut vim, "o"), [Command::Insert(InsertAt::LineBelow)]);
+        assert_eq!(vim.mode, VimMode::Insert);
+        vim.escape();
+        assert_eq!(vim.mode, VimMode::Normal);
+    }
+
+    #[test]
+    fn a_colon_line_runs_on_enter() {
+        let mut vim = Vim::default();
+        assert_eq!(keys(&mut vim, ":wq"), []);
+        assert_eq!(vim.status(), ":wq");
+        assert_eq!(vim.key(ENTER), Some(Command::Ex("wq".to_string())));
+        assert_eq!(vim.status(), "NORMAL");
+    }
+
+    #[test]
+    fn backspace_edits_the_command_line() {
+        let mut vim = Vim::default();
+        keys(&mut vim, ":wx");
+        assert_eq!(vim.key(BACKSPACE), None);
+        assert_eq!(vim.key('q'), None);
+        assert_eq!(vim.key(ENTER), Some(Command::Ex("wq".to_string())));
+    }
+
+    #[test]
+    fn slash_searches_and_a_bare_slash_repeats() {
+        let mut vim = Vim::default();
+        keys(&mut vim, "/two");
+        assert_eq!(vim.key(ENTER), Some(Command::Search("two".to_string())));
+
+        assert_eq!(vim.key('/'), None);
+        assert_eq!(vim.key(ENTER), Some(Command::SearchNext { forward: true }));
+    }
+
+    #[test]
+    fn escape_drops_a_half_typed_command() {
+        let mut vim = Vim::default();
+        keys(&mut vim, ":w");
+        vim.escape();
+        assert_eq!(vim.status(), "NORMAL");
+        assert_eq!(keys(&mut vim, "u"), [Command::Undo]);
+    }
+}